@@ -0,0 +1,171 @@
+// What goes back and forth over the server's admin socket, which is how
+// scan2blob-ctl talks to it. Each request is one line of JSON, and so is each
+// response, and a connection can have as many of them as it likes, one after
+// the other.
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    ListGates,
+    Open {
+        gate: String,
+        name_hint: Option<String>,
+        // In seconds. The gate's timed_assertion_lifetime, if this isn't
+        // here.
+        duration: Option<u32>,
+    },
+    Close {
+        gate: String,
+    },
+    ListUploads,
+    CancelUpload {
+        id: u64,
+    },
+    ListConnections,
+    // Checks the config file, and if it's all right, restarts the server
+    // with it.
+    Reload,
+    Metrics,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Response {
+    Ok {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        result: Option<ResponseResult>,
+    },
+    Error {
+        error: String,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseResult {
+    Gates(Vec<GateStatus>),
+    Uploads(Vec<UploadStatus>),
+    Connections(Vec<ConnectionStatus>),
+    Metrics(std::collections::BTreeMap<String, u64>),
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct GateStatus {
+    pub name: String,
+    pub open: bool,
+    // The whole queue, starting with the one that's currently active.
+    pub name_hints: Vec<String>,
+    // In seconds, if it's going to change by itself.
+    pub next_change_in: Option<u64>,
+    pub files_remaining: Option<u32>,
+    pub category: Option<String>,
+}
+
+// Only uploads that are still under way. The gate's web UI is where the
+// finished ones are.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct UploadStatus {
+    pub id: u64,
+    pub destination: String,
+    pub blob_name: String,
+    pub gate: String,
+    pub username: String,
+    // Seconds since the epoch.
+    pub started: u64,
+    // How much of it has been uploaded so far.
+    pub size: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ConnectionStatus {
+    pub id: u64,
+    pub listener: String,
+    pub peer: std::net::SocketAddr,
+    // Once they've logged in.
+    pub username: Option<String>,
+    // Seconds since the epoch.
+    pub since: u64,
+}
+
+impl Response {
+    pub fn ok(result: Option<ResponseResult>) -> Self {
+        Self::Ok { result }
+    }
+
+    pub fn error<T: std::fmt::Display>(err: T) -> Self {
+        Self::Error {
+            error: format!("{}", err),
+        }
+    }
+
+    pub fn into_result(
+        self,
+    ) -> Result<Option<ResponseResult>, crate::error::WuffError> {
+        match self {
+            Self::Ok { result } => Ok(result),
+            Self::Error { error } => Err(crate::error::WuffError::from(error)),
+        }
+    }
+}
+
+// Both directions are the same: one line per message.
+pub fn encode<T: serde::Serialize>(message: &T) -> String {
+    let mut line: String = serde_json::to_string(message).expect("serde_json");
+    line.push('\n');
+    line
+}
+
+pub fn decode<T: serde::de::DeserializeOwned>(
+    line: &str,
+) -> Result<T, crate::error::WuffError> {
+    Ok(serde_json::from_str(line.trim_end())?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn requests() {
+        let request: Request = Request::Open {
+            gate: String::from("front desk"),
+            name_hint: Some(String::from("lease")),
+            duration: None,
+        };
+        let line: String = encode(&request);
+        assert!(line.ends_with('\n'));
+        assert_eq!(decode::<Request>(&line).unwrap(), request);
+        assert_eq!(
+            decode::<Request>(r#"{"command":"list_gates"}"#).unwrap(),
+            Request::ListGates
+        );
+        assert_eq!(
+            decode::<Request>(r#"{"command":"cancel_upload","id":3}"#)
+                .unwrap(),
+            Request::CancelUpload { id: 3 }
+        );
+        assert!(decode::<Request>(r#"{"command":"explode"}"#).is_err());
+    }
+
+    #[test]
+    fn responses() {
+        let ok: Response = Response::ok(None);
+        assert_eq!(encode(&ok), "{\"status\":\"ok\"}\n");
+        assert_eq!(decode::<Response>(&encode(&ok)).unwrap(), ok);
+        let metrics: Response = Response::ok(Some(ResponseResult::Metrics(
+            [(String::from("uploads_committed"), 2)]
+                .into_iter()
+                .collect(),
+        )));
+        assert_eq!(decode::<Response>(&encode(&metrics)).unwrap(), metrics);
+        let err: Response = Response::error("no such gate");
+        assert_eq!(
+            decode::<Response>(&encode(&err))
+                .unwrap()
+                .into_result()
+                .unwrap_err()
+                .message,
+            "no such gate"
+        );
+    }
+}
@@ -0,0 +1,43 @@
+// API tokens are random strings that API clients send as bearer tokens.
+// Only a hash of each one goes in the config file, so that anyone who can
+// read that can't use them. There's no need for anything like pwhash's salt
+// and rounds, since the tokens themselves are long and random, rather than
+// something a person came up with.
+
+// So that a token is recognizable as one, if it turns up somewhere it
+// shouldn't have.
+const PREFIX: &str = "s2b_";
+
+pub fn generate() -> String {
+    format!("{}{}", PREFIX, crate::util::random_token())
+}
+
+// Lowercase hex SHA-256.
+pub fn hash(token: &str) -> String {
+    let digest = <sha2::Sha256 as sha2::Digest>::digest(token.as_bytes());
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Whether something looks like it came out of hash().
+pub fn is_hash(s: &str) -> bool {
+    s.len() == 64
+        && s.bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hashing() {
+        assert_eq!(
+            hash("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        let token: String = generate();
+        assert!(token.starts_with(PREFIX));
+        assert!(is_hash(&hash(&token)));
+        assert!(!is_hash("BA7816BF"));
+    }
+}
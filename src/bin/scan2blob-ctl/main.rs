@@ -0,0 +1,235 @@
+// Talks to a running server over its admin socket, so that gates can be
+// looked at and opened and closed from the shell, or from cron jobs and
+// scripts. With --json, whatever the server sends back gets printed as is,
+// which is easier for a script to make sense of.
+
+const DEFAULT_SOCKET: &str = "/var/run/scan2blob.sock";
+
+fn make_cmdline_parser() -> clap::Command {
+    scan2blob::util::make_cmdline_parser("scan2blob-ctl")
+        .arg(
+            clap::Arg::new("socket")
+                .long("socket")
+                .short('s')
+                .value_parser(clap::value_parser!(std::path::PathBuf))
+                .default_value(DEFAULT_SOCKET)
+                .action(clap::ArgAction::Set),
+        )
+        .arg(
+            clap::Arg::new("json")
+                .long("json")
+                .help("Print the server's response as JSON")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .subcommand_required(true)
+        .subcommand(
+            clap::Command::new("gates").about("List gates and their state"),
+        )
+        .subcommand(
+            clap::Command::new("open")
+                .about("Open a gate")
+                .arg(clap::Arg::new("gate").required(true))
+                .arg(
+                    clap::Arg::new("name_hint")
+                        .long("name-hint")
+                        .action(clap::ArgAction::Set),
+                )
+                .arg(
+                    clap::Arg::new("duration")
+                        .long("duration")
+                        .help("In seconds (default: the gate's own)")
+                        .value_parser(clap::value_parser!(u32))
+                        .action(clap::ArgAction::Set),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("close")
+                .about("Close a gate")
+                .arg(clap::Arg::new("gate").required(true)),
+        )
+        .subcommand(
+            clap::Command::new("uploads")
+                .about("List uploads that are under way"),
+        )
+        .subcommand(
+            clap::Command::new("cancel")
+                .about("Cancel an upload that's under way")
+                .arg(
+                    clap::Arg::new("id")
+                        .required(true)
+                        .value_parser(clap::value_parser!(u64)),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("connections")
+                .about("List connections to the listeners"),
+        )
+        .subcommand(
+            clap::Command::new("reload").about(
+                "Check the config file, and restart the server with it",
+            ),
+        )
+        .subcommand(clap::Command::new("metrics").about("Dump metrics"))
+}
+
+fn make_request(
+    subcommand: &str,
+    args: &clap::ArgMatches,
+) -> scan2blob::admin::Request {
+    match subcommand {
+        "gates" => scan2blob::admin::Request::ListGates,
+        "open" => scan2blob::admin::Request::Open {
+            gate: args.get_one::<String>("gate").unwrap().clone(),
+            name_hint: args.get_one::<String>("name_hint").cloned(),
+            duration: args.get_one::<u32>("duration").copied(),
+        },
+        "close" => scan2blob::admin::Request::Close {
+            gate: args.get_one::<String>("gate").unwrap().clone(),
+        },
+        "uploads" => scan2blob::admin::Request::ListUploads,
+        "cancel" => scan2blob::admin::Request::CancelUpload {
+            id: *args.get_one::<u64>("id").unwrap(),
+        },
+        "connections" => scan2blob::admin::Request::ListConnections,
+        "reload" => scan2blob::admin::Request::Reload,
+        "metrics" => scan2blob::admin::Request::Metrics,
+        _ => unreachable!("clap"),
+    }
+}
+
+fn send_request(
+    socket: &std::path::Path,
+    request: &scan2blob::admin::Request,
+) -> Result<String, scan2blob::error::WuffError> {
+    let sock: std::os::unix::net::UnixStream =
+        std::os::unix::net::UnixStream::connect(socket).map_err(|err| {
+            scan2blob::error::WuffError::from(format!(
+                "{}: {}",
+                socket.display(),
+                err
+            ))
+        })?;
+    std::io::Write::write_all(
+        &mut &sock,
+        scan2blob::admin::encode(request).as_bytes(),
+    )?;
+    let mut line: String = String::new();
+    let _ = std::io::BufRead::read_line(
+        &mut std::io::BufReader::new(&sock),
+        &mut line,
+    )?;
+    if line.is_empty() {
+        return Err(scan2blob::error::WuffError::from(
+            "the server hung up without answering",
+        ));
+    }
+    Ok(line)
+}
+
+fn time_ago(epoch_secs: u64) -> String {
+    let now: u64 = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    format!("{}s ago", now.saturating_sub(epoch_secs))
+}
+
+fn print_result(result: &scan2blob::admin::ResponseResult) {
+    match result {
+        scan2blob::admin::ResponseResult::Gates(gates) => {
+            for gate in gates {
+                let mut line: String = format!(
+                    "{}: {}",
+                    gate.name,
+                    if gate.open { "open" } else { "closed" }
+                );
+                if let Some(next_change_in) = gate.next_change_in {
+                    line.push_str(&format!(
+                        ", {} in {}s",
+                        if gate.open { "closes" } else { "opens" },
+                        next_change_in
+                    ));
+                }
+                if !gate.name_hints.is_empty() {
+                    line.push_str(&format!(
+                        ", name hints {:?}",
+                        gate.name_hints
+                    ));
+                }
+                if let Some(files_remaining) = gate.files_remaining {
+                    line.push_str(&format!(
+                        ", {} file(s) to go",
+                        files_remaining
+                    ));
+                }
+                if let Some(ref category) = gate.category {
+                    line.push_str(&format!(", category {}", category));
+                }
+                println!("{}", line);
+            }
+        }
+        scan2blob::admin::ResponseResult::Uploads(uploads) => {
+            for upload in uploads {
+                println!(
+                    "{}: {} to {} through gate {}, from {}, started {}, {} bytes so far",
+                    upload.id,
+                    upload.blob_name,
+                    upload.destination,
+                    upload.gate,
+                    upload.username,
+                    time_ago(upload.started),
+                    upload.size
+                );
+            }
+        }
+        scan2blob::admin::ResponseResult::Connections(connections) => {
+            for connection in connections {
+                println!(
+                    "{}: {} from {}{}, connected {}",
+                    connection.id,
+                    connection.listener,
+                    connection.peer,
+                    connection
+                        .username
+                        .as_ref()
+                        .map(|username| format!(" as {}", username))
+                        .unwrap_or_default(),
+                    time_ago(connection.since)
+                );
+            }
+        }
+        scan2blob::admin::ResponseResult::Metrics(metrics) => {
+            for (name, value) in metrics {
+                println!("{} {}", name, value);
+            }
+        }
+    }
+}
+
+fn main() -> Result<(), scan2blob::error::WuffError> {
+    let cmdline_matches: clap::ArgMatches =
+        make_cmdline_parser().get_matches();
+    let socket: &std::path::PathBuf = cmdline_matches
+        .get_one::<std::path::PathBuf>("socket")
+        .unwrap();
+    let json: bool = cmdline_matches.get_flag("json");
+    let (subcommand, args) = cmdline_matches.subcommand().unwrap();
+    let request: scan2blob::admin::Request = make_request(subcommand, args);
+
+    let line: String = send_request(socket, &request)?;
+    if json {
+        print!("{}", line);
+    }
+    let response: scan2blob::admin::Response =
+        scan2blob::admin::decode(&line)?;
+    // Even with --json, the exit status says whether it worked.
+    match response.into_result() {
+        Ok(Some(result)) if !json => {
+            print_result(&result);
+            Ok(())
+        }
+        Ok(_) => Ok(()),
+        Err(_) if json => std::process::exit(1),
+        Err(err) => Err(err),
+    }
+}
@@ -1,7 +1,49 @@
+// Makes up a TOTP secret for a web UI user, instead of hashing a password,
+// and shows it as a QR code for an authenticator app to scan.
+fn mktotp(user: &str) -> Result<(), scan2blob::error::WuffError> {
+    let secret: String = scan2blob::totp::generate_secret();
+    let uri: String = scan2blob::totp::otpauth_uri("scan2blob", user, &secret);
+    let qr: String = qrcode::QrCode::new(uri.as_bytes())
+        .map_err(|e| scan2blob::error::WuffError::from(format!("{}", e)))?
+        .render::<qrcode::render::unicode::Dense1x2>()
+        // Terminals are mostly light text on a dark background, which is
+        // the other way around from how QR codes are meant to be.
+        .dark_color(qrcode::render::unicode::Dense1x2::Light)
+        .light_color(qrcode::render::unicode::Dense1x2::Dark)
+        .build();
+    println!("{}", qr);
+    println!();
+    println!("otpauth URI: {}", uri);
+    println!();
+    println!("TOTP secret (goes in the user's \"totp\"): {}", secret);
+    println!();
+    Ok(())
+}
+
 fn main() -> Result<(), scan2blob::error::WuffError> {
-    let cmdline_parser: clap::Command =
-        scan2blob::util::make_cmdline_parser("scan2blob-mkpass");
-    let _cmdline_matches: clap::ArgMatches = cmdline_parser.get_matches();
+    let cmdline_parser: clap::Command = scan2blob::util::make_cmdline_parser(
+        "scan2blob-mkpass",
+    )
+    .arg(
+        clap::Arg::new("totp")
+            .long("totp")
+            .help("Make a TOTP secret for a web UI user instead")
+            .action(clap::ArgAction::SetTrue),
+    )
+    .arg(
+        clap::Arg::new("user")
+            .long("user")
+            .help(
+                "Who the TOTP secret is for, as the authenticator app shows it",
+            )
+            .default_value("scan2blob")
+            .requires("totp")
+            .action(clap::ArgAction::Set),
+    );
+    let cmdline_matches: clap::ArgMatches = cmdline_parser.get_matches();
+    if cmdline_matches.get_flag("totp") {
+        return mktotp(cmdline_matches.get_one::<String>("user").unwrap());
+    }
 
     println!(
         "Warning: the password you enter will be echoed (by this tool) and will also be"
@@ -0,0 +1,100 @@
+// The server's config file only has the hash of each API token in it, so
+// this makes up a new token, and prints both the token (for whatever's going
+// to be using it) and what to put in the config file.
+
+#[derive(serde::Serialize)]
+struct ExampleConfig {
+    hash: String,
+    gates: std::collections::BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires: Option<String>,
+}
+
+fn main() -> Result<(), scan2blob::error::WuffError> {
+    let cmdline_parser: clap::Command = scan2blob::util::make_cmdline_parser(
+        "scan2blob-mktoken",
+    )
+    .arg(
+        clap::Arg::new("name")
+            .long("name")
+            .required(true)
+            .action(clap::ArgAction::Set),
+    )
+    .arg(
+        clap::Arg::new("gate")
+            .long("gate")
+            .help("GATE=status or GATE=toggle (can be given more than once)")
+            .required(true)
+            .action(clap::ArgAction::Append),
+    )
+    .arg(
+        clap::Arg::new("days")
+            .long("days")
+            .help("Expire after this many days (default: never)")
+            .value_parser(clap::value_parser!(u32))
+            .action(clap::ArgAction::Set),
+    );
+
+    let cmdline_matches: clap::ArgMatches = cmdline_parser.get_matches();
+    let name: &String = cmdline_matches.get_one::<String>("name").unwrap();
+    let mut gates: std::collections::BTreeMap<String, String> =
+        std::collections::BTreeMap::new();
+    for gate in cmdline_matches.get_many::<String>("gate").unwrap() {
+        let Some((gate_name, scope)) = gate.split_once('=') else {
+            return Err(scan2blob::error::WuffError::from(format!(
+                "{}: expected GATE=status or GATE=toggle",
+                gate
+            )));
+        };
+        if scope != "status" && scope != "toggle" {
+            return Err(scan2blob::error::WuffError::from(format!(
+                "{}: expected GATE=status or GATE=toggle",
+                gate
+            )));
+        }
+        let _ = gates.insert(gate_name.to_string(), scope.to_string());
+    }
+    let expires: Option<String> = if let Some(days) =
+        cmdline_matches.get_one::<u32>("days")
+    {
+        let expires: jiff::Timestamp = jiff::Timestamp::now()
+            .checked_add(jiff::SignedDuration::from_hours(*days as i64 * 24))
+            .map_err(|e| {
+                scan2blob::error::WuffError::from(format!("{}", e))
+            })?;
+        Some(expires.round(jiff::Unit::Second).unwrap().to_string())
+    } else {
+        None
+    };
+
+    let token: String = scan2blob::api_token::generate();
+    println!("API token: {}", token);
+    println!();
+    println!(
+        "This is the only time it'll be shown. Send it as \"Authorization: Bearer"
+    );
+    println!(
+        "<token>\". In the server's config file, it goes under the web UI's"
+    );
+    println!("\"api_tokens\", like so:");
+
+    let mut example_config_file_syntax: std::collections::BTreeMap<
+        String,
+        ExampleConfig,
+    > = std::collections::BTreeMap::new();
+    let _ = example_config_file_syntax.insert(
+        name.clone(),
+        ExampleConfig {
+            hash: scan2blob::api_token::hash(&token),
+            gates,
+            expires,
+        },
+    );
+    serde_json::to_writer_pretty(
+        std::io::stdout().lock(),
+        &example_config_file_syntax,
+    )?;
+    println!();
+
+    Ok(())
+}
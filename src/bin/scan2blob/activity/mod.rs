@@ -0,0 +1,305 @@
+// What's going on in the server right now, and some running totals, for the
+// admin socket to tell whoever asks. Connections and uploads are each held
+// onto by a guard for as long as they're going on, and they drop out of here
+// when it goes away, the same way a guarded assertion on a gate does.
+
+pub struct Activity {
+    started: std::time::Instant,
+    next_id: std::sync::atomic::AtomicU64,
+    connections:
+        std::sync::Mutex<std::collections::BTreeMap<u64, ConnectionInfo>>,
+    uploads: std::sync::Mutex<
+        std::collections::BTreeMap<u64, std::sync::Arc<UploadInfo>>,
+    >,
+    connections_accepted: std::sync::atomic::AtomicU64,
+    uploads_started: std::sync::atomic::AtomicU64,
+    uploads_committed: std::sync::atomic::AtomicU64,
+    uploads_failed: std::sync::atomic::AtomicU64,
+    uploads_cancelled: std::sync::atomic::AtomicU64,
+    bytes_committed: std::sync::atomic::AtomicU64,
+}
+
+struct ConnectionInfo {
+    listener: crate::upload_context::Listener,
+    peer: std::net::SocketAddr,
+    username: Option<String>,
+    since: std::time::SystemTime,
+}
+
+struct UploadInfo {
+    destination: String,
+    blob_name: String,
+    gate: String,
+    username: String,
+    started: std::time::SystemTime,
+    size: std::sync::atomic::AtomicU64,
+    committed: std::sync::atomic::AtomicBool,
+    cancelled: std::sync::atomic::AtomicBool,
+    cancel: tokio::sync::Notify,
+}
+
+fn epoch_secs(t: std::time::SystemTime) -> u64 {
+    t.duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+impl Activity {
+    pub fn new() -> Self {
+        Self {
+            started: std::time::Instant::now(),
+            next_id: std::sync::atomic::AtomicU64::new(1),
+            connections: std::sync::Mutex::new(
+                std::collections::BTreeMap::new(),
+            ),
+            uploads: std::sync::Mutex::new(std::collections::BTreeMap::new()),
+            connections_accepted: std::sync::atomic::AtomicU64::new(0),
+            uploads_started: std::sync::atomic::AtomicU64::new(0),
+            uploads_committed: std::sync::atomic::AtomicU64::new(0),
+            uploads_failed: std::sync::atomic::AtomicU64::new(0),
+            uploads_cancelled: std::sync::atomic::AtomicU64::new(0),
+            bytes_committed: std::sync::atomic::AtomicU64::new(0),
+        }
+    }
+
+    fn next_id(&self) -> u64 {
+        self.next_id
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+    }
+
+    pub fn connection(
+        self: &std::sync::Arc<Self>,
+        listener: crate::upload_context::Listener,
+        peer: std::net::SocketAddr,
+    ) -> ConnectionGuard {
+        let id: u64 = self.next_id();
+        let _ = self.connections.lock().unwrap().insert(
+            id,
+            ConnectionInfo {
+                listener,
+                peer,
+                username: None,
+                since: std::time::SystemTime::now(),
+            },
+        );
+        let _ = self
+            .connections_accepted
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        ConnectionGuard {
+            activity: std::sync::Arc::clone(self),
+            id,
+        }
+    }
+
+    pub fn upload(
+        self: &std::sync::Arc<Self>,
+        destination: &str,
+        blob_name: &str,
+        upload_context: &crate::upload_context::UploadContext,
+    ) -> UploadGuard {
+        let id: u64 = self.next_id();
+        let info: std::sync::Arc<UploadInfo> =
+            std::sync::Arc::new(UploadInfo {
+                destination: destination.to_string(),
+                blob_name: blob_name.to_string(),
+                gate: upload_context.gate.clone(),
+                username: upload_context.username.clone(),
+                started: upload_context.started,
+                size: std::sync::atomic::AtomicU64::new(0),
+                committed: std::sync::atomic::AtomicBool::new(false),
+                cancelled: std::sync::atomic::AtomicBool::new(false),
+                cancel: tokio::sync::Notify::new(),
+            });
+        let _ = self
+            .uploads
+            .lock()
+            .unwrap()
+            .insert(id, std::sync::Arc::clone(&info));
+        let _ = self
+            .uploads_started
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        UploadGuard {
+            activity: std::sync::Arc::clone(self),
+            id,
+            info,
+        }
+    }
+
+    // Oldest first.
+    pub fn connections(&self) -> Vec<scan2blob::admin::ConnectionStatus> {
+        self.connections
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, info)| scan2blob::admin::ConnectionStatus {
+                id: *id,
+                listener: listener_name(info.listener).to_string(),
+                peer: info.peer,
+                username: info.username.clone(),
+                since: epoch_secs(info.since),
+            })
+            .collect()
+    }
+
+    // Oldest first.
+    pub fn uploads(&self) -> Vec<scan2blob::admin::UploadStatus> {
+        self.uploads
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, info)| scan2blob::admin::UploadStatus {
+                id: *id,
+                destination: info.destination.clone(),
+                blob_name: info.blob_name.clone(),
+                gate: info.gate.clone(),
+                username: info.username.clone(),
+                started: epoch_secs(info.started),
+                size: info.size.load(std::sync::atomic::Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    pub fn num_uploads(&self) -> usize {
+        self.uploads.lock().unwrap().len()
+    }
+
+    // Whoever's doing the upload finds out the next time it's waiting for
+    // more of it to come in, or before it commits it, whichever's first.
+    pub fn cancel_upload(
+        &self,
+        id: u64,
+    ) -> Result<String, scan2blob::error::WuffError> {
+        let Some(info) = self.uploads.lock().unwrap().get(&id).cloned() else {
+            return Err(scan2blob::error::WuffError::from(format!(
+                "{}: no such upload (it might have already finished)",
+                id
+            )));
+        };
+        info.cancelled
+            .store(true, std::sync::atomic::Ordering::Relaxed);
+        info.cancel.notify_waiters();
+        Ok(info.blob_name.clone())
+    }
+
+    pub fn metrics(&self) -> std::collections::BTreeMap<String, u64> {
+        let counters: [(&str, &std::sync::atomic::AtomicU64); 6] = [
+            ("connections_accepted", &self.connections_accepted),
+            ("uploads_started", &self.uploads_started),
+            ("uploads_committed", &self.uploads_committed),
+            ("uploads_failed", &self.uploads_failed),
+            ("uploads_cancelled", &self.uploads_cancelled),
+            ("bytes_committed", &self.bytes_committed),
+        ];
+        let mut metrics: std::collections::BTreeMap<String, u64> = counters
+            .into_iter()
+            .map(|(name, counter)| {
+                (
+                    name.to_string(),
+                    counter.load(std::sync::atomic::Ordering::Relaxed),
+                )
+            })
+            .collect();
+        let _ = metrics.insert(
+            String::from("connections_open"),
+            self.connections.lock().unwrap().len() as u64,
+        );
+        let _ = metrics.insert(
+            String::from("uploads_in_flight"),
+            self.num_uploads() as u64,
+        );
+        let _ = metrics.insert(
+            String::from("uptime_seconds"),
+            self.started.elapsed().as_secs(),
+        );
+        metrics
+    }
+}
+
+fn listener_name(listener: crate::upload_context::Listener) -> &'static str {
+    match listener {
+        crate::upload_context::Listener::Sftp => "sftp",
+        crate::upload_context::Listener::Webdav => "webdav",
+    }
+}
+
+pub struct ConnectionGuard {
+    activity: std::sync::Arc<Activity>,
+    id: u64,
+}
+
+impl ConnectionGuard {
+    pub fn set_username(&self, username: &str) {
+        if let Some(info) =
+            self.activity.connections.lock().unwrap().get_mut(&self.id)
+        {
+            info.username = Some(username.to_string());
+        }
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let _ = self.activity.connections.lock().unwrap().remove(&self.id);
+    }
+}
+
+// If it goes away without having been committed, the upload counts as having
+// failed, unless it was cancelled.
+pub struct UploadGuard {
+    activity: std::sync::Arc<Activity>,
+    id: u64,
+    info: std::sync::Arc<UploadInfo>,
+}
+
+impl UploadGuard {
+    pub fn set_size(&self, size: u64) {
+        self.info
+            .size
+            .store(size, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    pub fn committed(&self) {
+        self.info
+            .committed
+            .store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.info
+            .cancelled
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    // Never finishes, unless the upload gets cancelled.
+    pub async fn cancelled(&self) {
+        // This has to be made before the flag's checked, so that it can't
+        // miss being told in between.
+        let notified = self.info.cancel.notified();
+        if self.is_cancelled() {
+            return;
+        }
+        notified.await;
+    }
+}
+
+impl Drop for UploadGuard {
+    fn drop(&mut self) {
+        let _ = self.activity.uploads.lock().unwrap().remove(&self.id);
+        let counter: &std::sync::atomic::AtomicU64 = if self
+            .info
+            .committed
+            .load(std::sync::atomic::Ordering::Relaxed)
+        {
+            let _ = self.activity.bytes_committed.fetch_add(
+                self.info.size.load(std::sync::atomic::Ordering::Relaxed),
+                std::sync::atomic::Ordering::Relaxed,
+            );
+            &self.activity.uploads_committed
+        } else if self.is_cancelled() {
+            &self.activity.uploads_cancelled
+        } else {
+            &self.activity.uploads_failed
+        };
+        let _ = counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    }
+}
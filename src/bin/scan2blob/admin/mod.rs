@@ -0,0 +1,375 @@
+// A Unix domain socket that scan2blob-ctl (or anything else that speaks its
+// protocol, which is in scan2blob::admin) can connect to, to see what's going
+// on and to control gates. There's no authentication: whoever the socket's
+// file mode lets connect to it can do everything, so it's up to whoever
+// configures it to put it somewhere only the right people can get at.
+//
+// Gates that are opened and closed from here go in their audit log as having
+// been done by "ctl", along with the uid that was on the other end of the
+// socket.
+
+#[derive(serde::Deserialize)]
+pub struct ConfigAdmin {
+    pub socket: std::path::PathBuf,
+    // Octal, like chmod takes it.
+    #[serde(default = "default_mode")]
+    pub mode: String,
+}
+
+pub struct ConfigAdminEnriched {
+    pub socket: std::path::PathBuf,
+    pub mode: u32,
+}
+
+impl TryFrom<ConfigAdmin> for ConfigAdminEnriched {
+    type Error = scan2blob::error::WuffError;
+    fn try_from(
+        config: ConfigAdmin,
+    ) -> Result<Self, scan2blob::error::WuffError> {
+        let ConfigAdmin { socket, mode } = config;
+        let mode: u32 = match u32::from_str_radix(&mode, 8) {
+            Ok(mode) if mode <= 0o777 => mode,
+            _ => {
+                return Err(scan2blob::error::WuffError::from(format!(
+                    "admin: {:?} is not a file mode",
+                    mode
+                )));
+            }
+        };
+        Ok(Self { socket, mode })
+    }
+}
+
+fn default_mode() -> String {
+    String::from("0600")
+}
+
+// A line longer than this isn't a request that we're going to understand.
+const MAX_REQUEST_SIZE: u64 = 65536;
+
+pub struct AdminListener {
+    ctx: std::sync::Arc<crate::ctx::Ctx>,
+    gates: Vec<std::sync::Arc<crate::gate::Gate>>,
+    socket: std::path::PathBuf,
+    mode: u32,
+}
+
+impl AdminListener {
+    pub fn new(
+        ctx: &std::sync::Arc<crate::ctx::Ctx>,
+        gates: Vec<std::sync::Arc<crate::gate::Gate>>,
+        config: &ConfigAdminEnriched,
+    ) -> Self {
+        Self {
+            ctx: std::sync::Arc::clone(ctx),
+            gates,
+            socket: config.socket.clone(),
+            mode: config.mode,
+        }
+    }
+
+    // The socket's there (with the right mode) by the time this returns, so
+    // that a script that starts the server and then runs scan2blob-ctl
+    // doesn't have to wait around for it. It never exists with any other
+    // mode, even for a moment: it's made in a directory of its own that
+    // only we can get into, and only moved to where it belongs once its mode
+    // has been set.
+    pub fn start(
+        self: &std::sync::Arc<Self>,
+    ) -> Result<(), scan2blob::error::WuffError> {
+        if let Ok(metadata) = std::fs::symlink_metadata(&self.socket) {
+            if !std::os::unix::fs::FileTypeExt::is_socket(
+                &metadata.file_type(),
+            ) {
+                return Err(scan2blob::error::WuffError::from(format!(
+                    "{}: exists, and isn't a socket",
+                    self.socket.display()
+                )));
+            }
+            // Left over from last time, most likely, but if something's
+            // still answering on it, it isn't ours to take away.
+            if std::os::unix::net::UnixStream::connect(&self.socket).is_ok() {
+                return Err(scan2blob::error::WuffError::from(format!(
+                    "{}: something else is already listening on it",
+                    self.socket.display()
+                )));
+            }
+            std::fs::remove_file(&self.socket)?;
+        }
+        let mut private_dir: std::ffi::OsString =
+            self.socket.as_os_str().to_owned();
+        private_dir.push(format!(".{}", std::process::id()));
+        let private_dir: std::path::PathBuf = private_dir.into();
+        std::os::unix::fs::DirBuilderExt::mode(
+            &mut std::fs::DirBuilder::new(),
+            0o700,
+        )
+        .create(&private_dir)?;
+        let result: Result<
+            tokio::net::UnixListener,
+            scan2blob::error::WuffError,
+        > = self.bind_in(&private_dir);
+        let _ = std::fs::remove_dir(&private_dir);
+        let server_sock: tokio::net::UnixListener = result?;
+        self.ctx.spawn_critical(
+            String::from("admin"),
+            std::sync::Arc::clone(self).run(server_sock),
+        );
+        Ok(())
+    }
+
+    fn bind_in(
+        &self,
+        private_dir: &std::path::Path,
+    ) -> Result<tokio::net::UnixListener, scan2blob::error::WuffError> {
+        let private_socket: std::path::PathBuf = private_dir.join("socket");
+        let server_sock: tokio::net::UnixListener =
+            tokio::net::UnixListener::bind(&private_socket)?;
+        let result: Result<(), std::io::Error> = std::fs::set_permissions(
+            &private_socket,
+            std::os::unix::fs::PermissionsExt::from_mode(self.mode),
+        )
+        .and_then(|()| std::fs::rename(&private_socket, &self.socket));
+        if let Err(err) = result {
+            let _ = std::fs::remove_file(&private_socket);
+            return Err(err.into());
+        }
+        Ok(server_sock)
+    }
+
+    async fn run(
+        self: std::sync::Arc<Self>,
+        server_sock: tokio::net::UnixListener,
+    ) {
+        let async_spawner = self.ctx.base_ctx.get_async_spawner();
+        loop {
+            let Ok((sock, _)) = server_sock.accept().await else {
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                continue;
+            };
+            async_spawner
+                .spawn(std::sync::Arc::clone(&self).handle_connection(sock));
+        }
+    }
+
+    async fn handle_connection(
+        self: std::sync::Arc<Self>,
+        sock: tokio::net::UnixStream,
+    ) {
+        let who: String = match sock.peer_cred() {
+            Ok(cred) => format!("ctl (uid {})", cred.uid()),
+            Err(_) => String::from("ctl"),
+        };
+        let (reader, mut writer) = sock.into_split();
+        let mut reader: tokio::io::BufReader<
+            tokio::io::Take<tokio::net::unix::OwnedReadHalf>,
+        > = tokio::io::BufReader::new(tokio::io::AsyncReadExt::take(
+            reader,
+            MAX_REQUEST_SIZE,
+        ));
+        loop {
+            let mut line: String = String::new();
+            match tokio::io::AsyncBufReadExt::read_line(&mut reader, &mut line)
+                .await
+            {
+                Ok(0) | Err(_) => {
+                    return;
+                }
+                Ok(_) => {}
+            }
+            // Each request gets its own allowance.
+            reader.get_mut().set_limit(MAX_REQUEST_SIZE);
+            let mut reload: bool = false;
+            let response: scan2blob::admin::Response =
+                match scan2blob::admin::decode::<scan2blob::admin::Request>(
+                    &line,
+                ) {
+                    Ok(scan2blob::admin::Request::Reload) => {
+                        match self.check_reload() {
+                            Ok(()) => {
+                                reload = true;
+                                scan2blob::admin::Response::ok(None)
+                            }
+                            Err(err) => scan2blob::admin::Response::error(err),
+                        }
+                    }
+                    Ok(request) => self.handle_request(&who, request),
+                    Err(err) => scan2blob::admin::Response::error(format!(
+                        "bad request: {}",
+                        err
+                    )),
+                };
+            if tokio::io::AsyncWriteExt::write_all(
+                &mut writer,
+                scan2blob::admin::encode(&response).as_bytes(),
+            )
+            .await
+            .is_err()
+            {
+                return;
+            }
+            // Only once they've been told that it's going to happen.
+            if reload {
+                self.ctx.log_info(format!("admin: {}: reloading", who));
+                let _ = self.ctx.reload_requested.set(());
+                return;
+            }
+        }
+    }
+
+    fn get_gate(
+        &self,
+        gate_name: &str,
+    ) -> Result<&std::sync::Arc<crate::gate::Gate>, scan2blob::error::WuffError>
+    {
+        let Some(gate) = self.gates.iter().find(|gate| gate.name == gate_name)
+        else {
+            return Err(scan2blob::error::WuffError::from(format!(
+                "{}: no such gate",
+                gate_name
+            )));
+        };
+        Ok(gate)
+    }
+
+    fn handle_request(
+        &self,
+        who: &str,
+        request: scan2blob::admin::Request,
+    ) -> scan2blob::admin::Response {
+        match self.try_handle_request(who, request) {
+            Ok(result) => scan2blob::admin::Response::ok(result),
+            Err(err) => scan2blob::admin::Response::error(err),
+        }
+    }
+
+    fn try_handle_request(
+        &self,
+        who: &str,
+        request: scan2blob::admin::Request,
+    ) -> Result<
+        Option<scan2blob::admin::ResponseResult>,
+        scan2blob::error::WuffError,
+    > {
+        match request {
+            scan2blob::admin::Request::ListGates => {
+                Ok(Some(scan2blob::admin::ResponseResult::Gates(
+                    self.gates.iter().map(|gate| gate_status(gate)).collect(),
+                )))
+            }
+            scan2blob::admin::Request::Open {
+                gate,
+                name_hint,
+                duration,
+            } => {
+                let gate: &std::sync::Arc<crate::gate::Gate> =
+                    self.get_gate(&gate)?;
+                let name_hints: Vec<String> = name_hint.into_iter().collect();
+                let mut action: String = String::from("unlocked");
+                if !name_hints.is_empty() {
+                    action.push_str(&format!(", name hints {:?}", name_hints));
+                }
+                if let Some(duration) = duration {
+                    action.push_str(&format!(", for {}s", duration));
+                }
+                gate.assert_gate_open_timed_with_name_hints(
+                    name_hints,
+                    None,
+                    None,
+                    duration.map(|duration| {
+                        std::time::Duration::from_secs(duration as u64)
+                    }),
+                )?;
+                gate.audit(who, action);
+                Ok(None)
+            }
+            scan2blob::admin::Request::Close { gate } => {
+                let gate: &std::sync::Arc<crate::gate::Gate> =
+                    self.get_gate(&gate)?;
+                gate.assert_gate_closed();
+                gate.audit(who, String::from("locked"));
+                Ok(None)
+            }
+            scan2blob::admin::Request::ListUploads => {
+                Ok(Some(scan2blob::admin::ResponseResult::Uploads(
+                    self.ctx.activity.uploads(),
+                )))
+            }
+            scan2blob::admin::Request::CancelUpload { id } => {
+                let blob_name: String = self.ctx.activity.cancel_upload(id)?;
+                self.ctx.log_info(format!(
+                    "admin: {}: cancelling upload of {}",
+                    who, blob_name
+                ));
+                Ok(None)
+            }
+            scan2blob::admin::Request::ListConnections => {
+                Ok(Some(scan2blob::admin::ResponseResult::Connections(
+                    self.ctx.activity.connections(),
+                )))
+            }
+            scan2blob::admin::Request::Reload => {
+                unreachable!("handled by the caller")
+            }
+            scan2blob::admin::Request::Metrics => {
+                let mut metrics: std::collections::BTreeMap<String, u64> =
+                    self.ctx.activity.metrics();
+                let gates_open: usize = self
+                    .gates
+                    .iter()
+                    .filter(|gate| gate.get_current_state().is_some())
+                    .count();
+                let _ = metrics
+                    .insert(String::from("gates_open"), gates_open as u64);
+                Ok(Some(scan2blob::admin::ResponseResult::Metrics(metrics)))
+            }
+        }
+    }
+
+    // Reloading means starting over with the new config file, which would
+    // cut off any uploads that are under way, and throw away any files that
+    // batches or duplex jobs are holding onto, so it's up to whoever's asking
+    // to try again once there aren't any. Gates without a state file start
+    // over, too.
+    fn check_reload(&self) -> Result<(), scan2blob::error::WuffError> {
+        let num_uploads: usize = self.ctx.activity.num_uploads();
+        if num_uploads > 0 {
+            return Err(scan2blob::error::WuffError::from(format!(
+                "{} upload(s) in progress, try again later",
+                num_uploads
+            )));
+        }
+        let num_spool_files: usize = crate::spool::num_spool_files();
+        if num_spool_files > 0 {
+            return Err(scan2blob::error::WuffError::from(format!(
+                "{} file(s) held or still being processed, try again later",
+                num_spool_files
+            )));
+        }
+        let cmdline_matches: clap::ArgMatches =
+            crate::ctx::make_cmdline_parser().get_matches();
+        let _config: crate::ctx::ConfigEnriched =
+            crate::ctx::ConfigEnriched::new(&cmdline_matches).map_err(
+                |err| {
+                    scan2blob::error::WuffError::from(format!(
+                        "not reloading: {}",
+                        err
+                    ))
+                },
+            )?;
+        Ok(())
+    }
+}
+
+fn gate_status(gate: &crate::gate::Gate) -> scan2blob::admin::GateStatus {
+    let (state, next_change_time) = gate.get_current_state_extended();
+    scan2blob::admin::GateStatus {
+        name: gate.name.clone(),
+        open: state.is_some(),
+        name_hints: state.unwrap_or_default(),
+        next_change_in: next_change_time
+            .map(|next_change_time| next_change_time.as_secs()),
+        files_remaining: gate.files_remaining(),
+        category: gate.current_category(),
+    }
+}
@@ -44,6 +44,11 @@ pub struct Config {
     pub destinations: crate::destination::ConfigDestinations,
     #[serde(default = "crate::mime_types::default_mime_types")]
     pub mime_types: crate::mime_types::ConfigMimeTypes,
+    // A web UI for more than one gate at once, as opposed to the ones that
+    // each gate can have.
+    pub web_ui: Option<crate::gate::web::ConfigWebUi>,
+    pub mqtt: Option<crate::mqtt::ConfigMqtt>,
+    pub admin: Option<crate::admin::ConfigAdmin>,
 }
 
 pub struct ConfigEnriched {
@@ -51,6 +56,9 @@ pub struct ConfigEnriched {
     pub gates: crate::gate::ConfigGatesEnriched,
     pub destinations: crate::destination::ConfigDestinationsEnriched,
     pub mime_types: crate::mime_types::ConfigMimeTypesEnriched,
+    pub web_ui: Option<crate::gate::web::ConfigWebUiEnriched>,
+    pub mqtt: Option<crate::mqtt::ConfigMqttEnriched>,
+    pub admin: Option<crate::admin::ConfigAdminEnriched>,
 }
 
 impl TryFrom<Config> for ConfigEnriched {
@@ -61,6 +69,9 @@ impl TryFrom<Config> for ConfigEnriched {
             gates,
             destinations,
             mime_types,
+            web_ui,
+            mqtt,
+            admin,
         } = config;
         let mut enriched_listeners: Vec<
             crate::listener::ConfigListenerEnriched,
@@ -82,11 +93,64 @@ impl TryFrom<Config> for ConfigEnriched {
                     .is_none()
             );
         }
+        let web_ui: Option<crate::gate::web::ConfigWebUiEnriched> =
+            if let Some(web_ui) = web_ui {
+                Some(web_ui.try_into()?)
+            } else {
+                None
+            };
+        if let Some(ref web_ui) = web_ui
+            && let Some(ref gate_names) = web_ui.gates
+        {
+            for gate_name in gate_names {
+                if !enriched_gates.contains_key(gate_name) {
+                    return Err(scan2blob::error::WuffError::from(format!(
+                        "web_ui: no such gate {}",
+                        gate_name
+                    )));
+                }
+            }
+        }
+        let mqtt: Option<crate::mqtt::ConfigMqttEnriched> =
+            if let Some(mqtt) = mqtt {
+                Some(mqtt.try_into()?)
+            } else {
+                None
+            };
+        if let Some(ref mqtt) = mqtt {
+            let gate_names: Vec<&String> =
+                if let Some(ref gate_names) = mqtt.gates {
+                    gate_names.iter().collect()
+                } else {
+                    enriched_gates.keys().collect()
+                };
+            for gate_name in gate_names {
+                if !enriched_gates.contains_key(gate_name) {
+                    return Err(scan2blob::error::WuffError::from(format!(
+                        "mqtt: no such gate {}",
+                        gate_name
+                    )));
+                }
+                crate::mqtt::check_gate_name(gate_name).map_err(|e| {
+                    scan2blob::error::WuffError::from(format!(
+                        "mqtt: gate {}",
+                        e
+                    ))
+                })?;
+            }
+        }
         Ok(Self {
             listeners: enriched_listeners,
             gates: enriched_gates,
             destinations: enriched_destinations,
             mime_types: mime_types.try_into()?,
+            web_ui,
+            mqtt,
+            admin: if let Some(admin) = admin {
+                Some(admin.try_into()?)
+            } else {
+                None
+            },
         })
     }
 }
@@ -214,6 +278,9 @@ pub struct Ctx {
     pub logger: std::sync::Arc<Logger>,
     pub shutdown_due_to_error:
         tokio::sync::SetOnce<scan2blob::error::WuffError>,
+    // Once this is set, the server starts over, config file and all.
+    pub reload_requested: tokio::sync::SetOnce<()>,
+    pub activity: std::sync::Arc<crate::activity::Activity>,
 }
 
 impl Ctx {
@@ -226,6 +293,8 @@ impl Ctx {
             config,
             logger: std::sync::Arc::clone(logger),
             shutdown_due_to_error: tokio::sync::SetOnce::new(),
+            reload_requested: tokio::sync::SetOnce::new(),
+            activity: std::sync::Arc::new(crate::activity::Activity::new()),
         }
     }
 
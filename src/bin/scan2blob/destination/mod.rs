@@ -1,5 +1,22 @@
+pub mod sidecar;
+pub mod transform;
+pub mod virus_scan;
+
 const MAX_NUM_CHUNKS: u16 = 50000;
 
+// The variables that a category's naming template can use. "timestamp" is the
+// same as what blobs are normally named after, and "date" and "time" are
+// pieces of it.
+pub const TEMPLATE_VARIABLES: &[&str] = &[
+    "timestamp",
+    "date",
+    "time",
+    "name_hint",
+    "category",
+    "user",
+    "gate",
+];
+
 #[derive(serde::Deserialize)]
 pub struct ConfigDestination {
     #[serde(flatten)]
@@ -8,6 +25,10 @@ pub struct ConfigDestination {
     pub initial_chunk_size: usize,
     #[serde(default = "default_max_chunk_size")]
     pub max_chunk_size: usize,
+    pub transform: Option<transform::ConfigTransform>,
+    pub virus_scan: Option<virus_scan::ConfigVirusScan>,
+    #[serde(default)]
+    pub sidecar: bool,
 }
 
 pub type ConfigDestinations =
@@ -28,6 +49,9 @@ pub struct ConfigDestinationEnriched {
     pub blob_storage_spec: scan2blob::util::BlobStorageSpecEnriched,
     pub initial_chunk_size: usize,
     pub max_chunk_size: usize,
+    pub transform: Option<transform::ConfigTransformEnriched>,
+    pub virus_scan: Option<virus_scan::ConfigVirusScanEnriched>,
+    pub sidecar: bool,
 }
 
 impl TryFrom<ConfigDestination> for ConfigDestinationEnriched {
@@ -40,11 +64,25 @@ impl TryFrom<ConfigDestination> for ConfigDestinationEnriched {
             blob_storage_spec,
             initial_chunk_size,
             max_chunk_size,
+            transform,
+            virus_scan,
+            sidecar,
         } = config;
         Ok(Self {
             blob_storage_spec: blob_storage_spec.try_into()?,
             initial_chunk_size,
             max_chunk_size,
+            transform: if let Some(transform) = transform {
+                Some(transform.try_into()?)
+            } else {
+                None
+            },
+            virus_scan: if let Some(virus_scan) = virus_scan {
+                Some(virus_scan.try_into()?)
+            } else {
+                None
+            },
+            sidecar,
         })
     }
 }
@@ -54,11 +92,14 @@ pub type ConfigDestinationsEnriched =
 
 pub struct Destination {
     ctx: std::sync::Arc<crate::ctx::Ctx>,
-    name: String,
+    pub name: String,
     container_client: azure_storage_blobs::prelude::ContainerClient,
     prefix: String,
     initial_chunk_size: usize,
     max_chunk_size: usize,
+    transform: Option<transform::Transform>,
+    virus_scanner: Option<virus_scan::VirusScanner>,
+    sidecar: bool,
 }
 
 impl Destination {
@@ -83,68 +124,213 @@ impl Destination {
             prefix: cfg.blob_storage_spec.prefix.clone(),
             initial_chunk_size: cfg.initial_chunk_size,
             max_chunk_size: cfg.max_chunk_size,
+            transform: if let Some(ref transform) = cfg.transform {
+                Some(transform::Transform::new(ctx, transform)?)
+            } else {
+                None
+            },
+            virus_scanner: cfg
+                .virus_scan
+                .as_ref()
+                .map(virus_scan::VirusScanner::new),
+            sidecar: cfg.sidecar,
         })
     }
 
     pub fn write_file(
         self: &std::sync::Arc<Self>,
-        name_hint: Option<String>,
+        upload_context: crate::upload_context::UploadContext,
         suffix: String,
         content_type: String,
     ) -> scan2blob::chunker::Writer {
         let now: std::time::SystemTime = std::time::SystemTime::now();
+        let timestamp: String =
+            scan2blob::util::system_time_to_utc_rfc3339(now);
+        let name_hint: Option<&str> = upload_context.name_hint.as_deref();
+        let blob_name_base: String = if let Some(ref template) =
+            upload_context.template
+        {
+            let (date, time) = timestamp.split_once('T').unwrap();
+            let values: std::collections::HashMap<&str, String> = [
+                ("timestamp", timestamp.clone()),
+                ("date", date.to_string()),
+                ("time", time.trim_end_matches('Z').replace([':', '.'], "")),
+                ("name_hint", name_hint.unwrap_or_default().to_string()),
+                (
+                    "category",
+                    upload_context.category.clone().unwrap_or_default(),
+                ),
+                ("user", upload_context.username.clone()),
+                ("gate", upload_context.gate.clone()),
+            ]
+            .into_iter()
+            .collect();
+            format!(
+                "{}{}{}",
+                self.prefix,
+                upload_context.sub_prefix,
+                template.render(&values)
+            )
+        } else {
+            let name_hint1: &str = name_hint.map_or("", |_| "-");
+            let name_hint2: &str = name_hint.unwrap_or_default();
+            format!(
+                "{}{}{}{}{}",
+                self.prefix,
+                upload_context.sub_prefix,
+                timestamp,
+                name_hint1,
+                name_hint2
+            )
+        };
 
-        let (writer, reader) = scan2blob::chunker::new(
-            self.initial_chunk_size,
-            self.max_chunk_size,
-            MAX_NUM_CHUNKS,
-        );
+        if let Some(ref transform) = self.transform
+            && transform.applies_to(&content_type)
+        {
+            let (writer, reader) = self.new_chunker();
+            let async_spawner = self.ctx.base_ctx.get_async_spawner();
+            async_spawner.spawn(transform::run(
+                std::sync::Arc::clone(self),
+                reader,
+                blob_name_base,
+                suffix,
+                content_type,
+                upload_context,
+            ));
+            writer
+        } else {
+            self.write_blob(
+                blob_name_base + &suffix,
+                content_type,
+                std::collections::BTreeMap::new(),
+                upload_context,
+            )
+        }
+    }
+
+    // Like write_file(), except that the caller decides exactly what the blob
+    // is going to be called, and no transforms are applied. Anything in
+    // `metadata` is set as metadata on the blob.
+    pub fn write_blob(
+        self: &std::sync::Arc<Self>,
+        blob_name: String,
+        content_type: String,
+        metadata: std::collections::BTreeMap<String, String>,
+        upload_context: crate::upload_context::UploadContext,
+    ) -> scan2blob::chunker::Writer {
+        let (writer, reader) = self.new_chunker();
 
         let async_spawner = self.ctx.base_ctx.get_async_spawner();
         async_spawner.spawn(std::sync::Arc::clone(self).do_upload(
             reader,
-            now,
-            name_hint,
-            suffix,
+            blob_name,
             content_type,
+            metadata,
+            upload_context,
         ));
 
         writer
     }
 
+    pub fn new_chunker(
+        &self,
+    ) -> (scan2blob::chunker::Writer, scan2blob::chunker::Reader) {
+        scan2blob::chunker::new(
+            self.initial_chunk_size,
+            self.max_chunk_size,
+            MAX_NUM_CHUNKS,
+        )
+    }
+
     async fn do_upload(
         self: std::sync::Arc<Self>,
         mut reader: scan2blob::chunker::Reader,
-        timestamp: std::time::SystemTime,
-        name_hint: Option<String>,
-        suffix: String,
+        blob_name: String,
         content_type: String,
+        metadata: std::collections::BTreeMap<String, String>,
+        mut upload_context: crate::upload_context::UploadContext,
     ) {
-        let name_hint1: &str = name_hint.as_ref().map_or("", |_| "-");
-        let name_hint2: String = name_hint.unwrap_or_default();
-        let blob_name: String = format!(
-            "{}{}{}{}{}",
-            self.prefix,
-            scan2blob::util::system_time_to_utc_rfc3339(timestamp),
-            name_hint1,
-            name_hint2,
-            suffix
-        );
         let blob_client: azure_storage_blobs::prelude::BlobClient =
             self.container_client.blob_client(&blob_name);
         let mut block_num: u16 = 0;
         let mut block_ids: Vec<azure_storage_blobs::prelude::BlobBlockType> =
             Vec::new();
+        // The sidecar wants the hash, and the sidecar and the gate both want
+        // the size.
+        let mut size: u64 = 0;
+        let mut sha256: Option<sha2::Sha256> = if self.sidecar {
+            Some(<sha2::Sha256 as sha2::Digest>::new())
+        } else {
+            None
+        };
 
         self.ctx
             .log_debug(format!("{}: uploading {}", self.name, blob_name));
+        let activity: crate::activity::UploadGuard =
+            self.ctx
+                .activity
+                .upload(&self.name, &blob_name, &upload_context);
+        upload_context.report_upload(
+            &self.name,
+            crate::upload_context::UploadStage::Started,
+            &blob_name,
+            size,
+            None,
+        );
+        let mut virus_scan: Option<virus_scan::Session> = if let Some(
+            ref virus_scanner,
+        ) =
+            self.virus_scanner
+        {
+            match virus_scanner.start(&blob_name).await {
+                Ok(session) => Some(session),
+                Err(err) => {
+                    self.ctx.log_info(format!(
+                        "{}: aborting upload of {}: could not start virus scan: {}",
+                        self.name, blob_name, err
+                    ));
+                    upload_context.report_upload(
+                        &self.name,
+                        crate::upload_context::UploadStage::Failed,
+                        &blob_name,
+                        size,
+                        Some(format!("could not start virus scan: {}", err)),
+                    );
+                    reader.observe_error(err);
+                    return;
+                }
+            }
+        } else {
+            None
+        };
         let hash: [u8; 16] = loop {
-            let chunk: Vec<u8> = match reader.get_next_chunk().await {
+            // Cancelling it from the admin socket only gets noticed here, and
+            // just before it would have been committed.
+            let next_chunk: Result<
+                scan2blob::chunker::ChunkOrEof,
+                scan2blob::error::WuffError,
+            > = futures::select! {
+                next_chunk = futures::FutureExt::fuse(
+                    reader.get_next_chunk()
+                ) => next_chunk,
+                _ = futures::FutureExt::fuse(activity.cancelled()) => {
+                    self.cancel(&reader, &blob_name, &upload_context, size);
+                    return;
+                }
+            };
+            let chunk: Vec<u8> = match next_chunk {
                 Err(err) => {
                     self.ctx.log_info(format!(
                         "{}: aborting upload of {} due to propagated error: {}",
                         self.name, blob_name, err
                     ));
+                    upload_context.report_upload(
+                        &self.name,
+                        crate::upload_context::UploadStage::Failed,
+                        &blob_name,
+                        size,
+                        Some(format!("{}", err)),
+                    );
                     return;
                 }
                 Ok(scan2blob::chunker::ChunkOrEof::Chunk(chunk)) => chunk,
@@ -166,34 +352,184 @@ impl Destination {
                 ),
             );
 
-            if let Err(e) =
-                blob_client.put_block(block_id, chunk).into_future().await
-            {
+            size += chunk.len() as u64;
+            if let Some(ref mut sha256) = sha256 {
+                <sha2::Sha256 as sha2::Digest>::update(sha256, &chunk);
+            }
+
+            // The virus scan happens at the same time as the upload.
+            let chunk: bytes::Bytes = chunk.into();
+            let (upload_result, scan_result) = futures::join!(
+                blob_client.put_block(block_id, chunk.clone()).into_future(),
+                async {
+                    if let Some(ref mut virus_scan) = virus_scan {
+                        virus_scan.send(&chunk).await
+                    } else {
+                        Ok(())
+                    }
+                }
+            );
+            if let Err(e) = upload_result {
                 self.ctx.log_info(format!(
                     "{}: upload of {} failed: {}",
                     self.name, blob_name, e
                 ));
+                upload_context.report_upload(
+                    &self.name,
+                    crate::upload_context::UploadStage::Failed,
+                    &blob_name,
+                    size,
+                    Some(format!("{}", e)),
+                );
                 reader.observe_error(scan2blob::error::WuffError::from(e));
                 return;
             }
+            if let Err(err) = scan_result {
+                self.ctx.log_info(format!(
+                    "{}: aborting upload of {}: virus scan failed: {}",
+                    self.name, blob_name, err
+                ));
+                upload_context.report_upload(
+                    &self.name,
+                    crate::upload_context::UploadStage::Failed,
+                    &blob_name,
+                    size,
+                    Some(format!("virus scan failed: {}", err)),
+                );
+                reader.observe_error(err);
+                return;
+            }
+            activity.set_size(size);
+            upload_context.report_upload(
+                &self.name,
+                crate::upload_context::UploadStage::Progress,
+                &blob_name,
+                size,
+                None,
+            );
         };
 
-        if let Err(e) = blob_client
+        if let Some(virus_scan) = virus_scan {
+            let quarantine_path: Option<std::path::PathBuf> = virus_scan
+                .quarantine_path()
+                .map(std::path::Path::to_path_buf);
+            match virus_scan.finish().await {
+                Ok(scan2blob::clamd::Verdict::Clean) => {
+                    upload_context.processing.push(
+                        crate::upload_context::ProcessingStep::VirusScan,
+                    );
+                }
+                Ok(scan2blob::clamd::Verdict::Infected(signature)) => {
+                    if let Some(quarantine_path) = quarantine_path {
+                        self.ctx.log_warn(format!(
+                            "{}: not committing {}: virus scan found {}, quarantined as {}",
+                            self.name,
+                            blob_name,
+                            signature,
+                            quarantine_path.display()
+                        ));
+                    } else {
+                        self.ctx.log_warn(format!(
+                            "{}: not committing {}: virus scan found {}, dropped",
+                            self.name, blob_name, signature
+                        ));
+                    }
+                    upload_context.report_upload(
+                        &self.name,
+                        crate::upload_context::UploadStage::Failed,
+                        &blob_name,
+                        size,
+                        Some(format!("virus scan found {}", signature)),
+                    );
+                    reader.observe_error(scan2blob::error::WuffError::from(
+                        format!("virus scan found {}", signature),
+                    ));
+                    return;
+                }
+                Err(err) => {
+                    self.ctx.log_info(format!(
+                        "{}: aborting upload of {}: virus scan failed: {}",
+                        self.name, blob_name, err
+                    ));
+                    upload_context.report_upload(
+                        &self.name,
+                        crate::upload_context::UploadStage::Failed,
+                        &blob_name,
+                        size,
+                        Some(format!("virus scan failed: {}", err)),
+                    );
+                    reader.observe_error(err);
+                    return;
+                }
+            }
+        }
+
+        if activity.is_cancelled() {
+            self.cancel(&reader, &blob_name, &upload_context, size);
+            return;
+        }
+
+        let mut put_block_list = blob_client
             .put_block_list(azure_storage_blobs::blob::BlockList {
                 blocks: block_ids,
             })
             .content_md5(hash)
-            .content_type(&content_type)
-            .into_future()
-            .await
-        {
+            .content_type(&content_type);
+        if !metadata.is_empty() {
+            let mut m: azure_core::request_options::Metadata =
+                azure_core::request_options::Metadata::new();
+            for (k, v) in &metadata {
+                m.insert(k.clone(), v.clone());
+            }
+            put_block_list = put_block_list.metadata(m);
+        }
+        if let Err(e) = put_block_list.into_future().await {
             self.ctx.log_info(format!(
                 "{}: upload of {} failed: {}",
                 self.name, blob_name, e
             ));
+            upload_context.report_upload(
+                &self.name,
+                crate::upload_context::UploadStage::Failed,
+                &blob_name,
+                size,
+                Some(format!("{}", e)),
+            );
             reader.observe_error(scan2blob::error::WuffError::from(e));
             return;
         }
+        activity.committed();
+        upload_context.report_upload(
+            &self.name,
+            crate::upload_context::UploadStage::Committed,
+            &blob_name,
+            size,
+            None,
+        );
+
+        // Failing to write the sidecar doesn't fail the upload: the blob
+        // itself is already there by the time we get here.
+        if let Some(sha256) = sha256 {
+            match sidecar::render(
+                &blob_name,
+                &content_type,
+                size,
+                &hash,
+                &<sha2::Sha256 as sha2::Digest>::finalize(sha256),
+                &upload_context,
+                &metadata,
+            ) {
+                Ok(sidecar) => {
+                    self.write_sidecar(&blob_name, sidecar).await;
+                }
+                Err(err) => {
+                    self.ctx.log_warn(format!(
+                        "{}: could not write sidecar for {}: {}",
+                        self.name, blob_name, err
+                    ));
+                }
+            }
+        }
 
         if let Err(e) = reader.finalize().await {
             self.ctx.log_info(format!(
@@ -202,8 +538,85 @@ impl Destination {
             ));
         }
     }
+
+    // Whatever's been uploaded so far never gets committed, so it's as if it
+    // never happened, and whoever was sending it finds out.
+    fn cancel(
+        &self,
+        reader: &scan2blob::chunker::Reader,
+        blob_name: &str,
+        upload_context: &crate::upload_context::UploadContext,
+        size: u64,
+    ) {
+        self.ctx.log_info(format!(
+            "{}: aborting upload of {}: cancelled",
+            self.name, blob_name
+        ));
+        upload_context.report_upload(
+            &self.name,
+            crate::upload_context::UploadStage::Failed,
+            blob_name,
+            size,
+            Some(String::from("cancelled")),
+        );
+        reader.observe_error(scan2blob::error::WuffError::from(
+            "upload cancelled",
+        ));
+    }
+
+    // The blob's sidecar goes, too, if there is one. If the container has
+    // soft delete turned on, they can both still be recovered, for as long
+    // as the container keeps them.
+    pub async fn delete_blob(
+        &self,
+        blob_name: &str,
+    ) -> Result<(), scan2blob::error::WuffError> {
+        self.ctx
+            .log_debug(format!("{}: deleting {}", self.name, blob_name));
+        self.container_client
+            .blob_client(blob_name)
+            .delete()
+            .into_future()
+            .await?;
+        if self.sidecar {
+            let sidecar_name: String = sidecar::name_for(blob_name);
+            if let Err(e) = self
+                .container_client
+                .blob_client(&sidecar_name)
+                .delete()
+                .into_future()
+                .await
+            {
+                self.ctx.log_warn(format!(
+                    "{}: could not delete {}: {}",
+                    self.name, sidecar_name, e
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    async fn write_sidecar(&self, blob_name: &str, sidecar: Vec<u8>) {
+        let sidecar_name: String = sidecar::name_for(blob_name);
+        self.ctx
+            .log_debug(format!("{}: uploading {}", self.name, sidecar_name));
+        if let Err(e) = self
+            .container_client
+            .blob_client(&sidecar_name)
+            .put_block_blob(sidecar)
+            .content_type("application/json")
+            .into_future()
+            .await
+        {
+            self.ctx.log_warn(format!(
+                "{}: upload of {} failed: {}",
+                self.name, sidecar_name, e
+            ));
+        }
+    }
 }
 
+#[derive(Clone)]
 pub struct Destinations {
     destinations:
         std::collections::HashMap<String, std::sync::Arc<Destination>>,
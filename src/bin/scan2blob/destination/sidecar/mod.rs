@@ -0,0 +1,67 @@
+// A sidecar is a small JSON document that's uploaded next to a blob, as
+// "<blob name>.json", describing where the blob came from. It's for the
+// benefit of anything downstream that can't see blob metadata. It only gets
+// uploaded once the blob itself has been committed, so anything that sees a
+// sidecar can count on the blob being there.
+
+#[derive(serde::Serialize)]
+struct Sidecar<'a> {
+    blob: &'a str,
+    content_type: &'a str,
+    size: u64,
+    md5: String,
+    sha256: String,
+    user: &'a str,
+    gate: &'a str,
+    name_hint: Option<&'a str>,
+    category: Option<&'a str>,
+    original_filenames: &'a [String],
+    listener: crate::upload_context::Listener,
+    client_ip: Option<std::net::IpAddr>,
+    upload_started: String,
+    committed: String,
+    processing: &'a [crate::upload_context::ProcessingStep],
+    metadata: &'a std::collections::BTreeMap<String, String>,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn name_for(blob_name: &str) -> String {
+    format!("{}.json", blob_name)
+}
+
+pub fn render(
+    blob_name: &str,
+    content_type: &str,
+    size: u64,
+    md5: &[u8],
+    sha256: &[u8],
+    upload_context: &crate::upload_context::UploadContext,
+    metadata: &std::collections::BTreeMap<String, String>,
+) -> Result<Vec<u8>, scan2blob::error::WuffError> {
+    let sidecar: Sidecar = Sidecar {
+        blob: blob_name,
+        content_type,
+        size,
+        md5: to_hex(md5),
+        sha256: to_hex(sha256),
+        user: &upload_context.username,
+        gate: &upload_context.gate,
+        name_hint: upload_context.name_hint.as_deref(),
+        category: upload_context.category.as_deref(),
+        original_filenames: &upload_context.orig_filenames,
+        listener: upload_context.listener,
+        client_ip: upload_context.client_addr.map(|addr| addr.ip()),
+        upload_started: scan2blob::util::system_time_to_utc_rfc3339(
+            upload_context.started,
+        ),
+        committed: scan2blob::util::system_time_to_utc_rfc3339(
+            std::time::SystemTime::now(),
+        ),
+        processing: &upload_context.processing,
+        metadata,
+    };
+    Ok(serde_json::to_vec_pretty(&sidecar)?)
+}
@@ -0,0 +1,466 @@
+#[derive(serde::Deserialize)]
+pub struct ConfigTransform {
+    #[serde(default)]
+    pub convert_to_pdf: bool,
+    #[serde(default)]
+    pub keep_original: bool,
+    #[serde(default)]
+    pub remove_blank_pages: bool,
+    #[serde(default = "default_blank_threshold")]
+    pub blank_threshold: f64,
+    pub spool_directory: Option<std::path::PathBuf>,
+}
+
+pub struct ConfigTransformEnriched {
+    pub convert_to_pdf: bool,
+    pub keep_original: bool,
+    pub remove_blank_pages: bool,
+    pub blank_threshold: f64,
+    pub spool_directory: std::path::PathBuf,
+}
+
+impl TryFrom<ConfigTransform> for ConfigTransformEnriched {
+    type Error = scan2blob::error::WuffError;
+
+    fn try_from(
+        config: ConfigTransform,
+    ) -> Result<Self, scan2blob::error::WuffError> {
+        let ConfigTransform {
+            convert_to_pdf,
+            keep_original,
+            remove_blank_pages,
+            blank_threshold,
+            spool_directory,
+        } = config;
+        if !(0.0..=1.0).contains(&blank_threshold) {
+            return Err(scan2blob::error::WuffError::from(
+                "blank_threshold must be between 0 and 1",
+            ));
+        }
+        Ok(Self {
+            convert_to_pdf,
+            keep_original,
+            remove_blank_pages,
+            blank_threshold,
+            spool_directory: spool_directory
+                .unwrap_or_else(std::env::temp_dir),
+        })
+    }
+}
+
+fn default_blank_threshold() -> f64 {
+    // 0.2% of the page
+    0.002
+}
+
+// Converted output is handed from the (blocking) conversion code to the
+// (async) upload code in pieces of about this size.
+const CONVERSION_BUFFER_SIZE: usize = 65536;
+
+// Blobs that have been checked for blank pages get this metadata item, saying
+// how many were removed.
+const REMOVED_BLANK_PAGES_METADATA: &str = "removed_blank_pages";
+
+pub struct Transform {
+    convert_to_pdf: bool,
+    keep_original: bool,
+    // Only set if blank pages are to be removed.
+    blank_threshold: Option<f64>,
+    spool_directory: std::path::PathBuf,
+    pdf_mime_type: crate::mime_types::ConfigMimeTypeEnriched,
+}
+
+impl Transform {
+    pub fn new(
+        ctx: &std::sync::Arc<crate::ctx::Ctx>,
+        cfg: &ConfigTransformEnriched,
+    ) -> Result<Self, scan2blob::error::WuffError> {
+        let Some(pdf_mime_type) =
+            ctx.config.mime_types.get_by_extension("pdf")
+        else {
+            return Err(scan2blob::error::WuffError::from(
+                "convert_to_pdf and remove_blank_pages require a mime type for pdf",
+            ));
+        };
+        Ok(Self {
+            convert_to_pdf: cfg.convert_to_pdf,
+            keep_original: cfg.keep_original,
+            blank_threshold: if cfg.remove_blank_pages {
+                Some(cfg.blank_threshold)
+            } else {
+                None
+            },
+            spool_directory: cfg.spool_directory.clone(),
+            pdf_mime_type,
+        })
+    }
+
+    pub fn applies_to(&self, content_type: &str) -> bool {
+        match scan2blob::pdf::InputKind::from_content_type(content_type) {
+            // A JPEG is only ever one page, and we never remove every page.
+            Some(scan2blob::pdf::InputKind::Jpeg) => self.convert_to_pdf,
+            Some(scan2blob::pdf::InputKind::Tiff) => {
+                self.convert_to_pdf || self.blank_threshold.is_some()
+            }
+            Some(scan2blob::pdf::InputKind::Pdf) => {
+                self.blank_threshold.is_some()
+            }
+            None => false,
+        }
+    }
+}
+
+// Sends whatever's written to it over to the async side, in reasonably sized
+// pieces.
+pub struct ChannelSink {
+    tx: tokio::sync::mpsc::Sender<Vec<u8>>,
+    buf: Vec<u8>,
+}
+
+impl ChannelSink {
+    fn send_buf(&mut self) -> std::io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let buf: Vec<u8> = std::mem::replace(
+            &mut self.buf,
+            Vec::with_capacity(CONVERSION_BUFFER_SIZE),
+        );
+        self.tx
+            .blocking_send(buf)
+            .map_err(|_| std::io::Error::other("upload went away"))
+    }
+}
+
+impl std::io::Write for ChannelSink {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        let n: usize =
+            std::cmp::min(data.len(), CONVERSION_BUFFER_SIZE - self.buf.len());
+        self.buf.extend_from_slice(&data[..n]);
+        if self.buf.len() >= CONVERSION_BUFFER_SIZE {
+            self.send_buf()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.send_buf()
+    }
+}
+
+fn convert(
+    path: &std::path::Path,
+    kind: scan2blob::pdf::InputKind,
+    sink: ChannelSink,
+) -> Result<(), scan2blob::error::WuffError> {
+    let mut input: std::io::BufReader<std::fs::File> =
+        std::io::BufReader::new(std::fs::File::open(path)?);
+    let mut pdf: scan2blob::pdf::Writer<ChannelSink> =
+        scan2blob::pdf::Writer::new(sink)?;
+    scan2blob::pdf::convert(kind, &mut input, &mut pdf)?;
+    pdf.finish()?;
+    Ok(())
+}
+
+// Returns the document with its blank pages taken out, and how many were
+// taken out. If there weren't any, there's no document. There's also no
+// document if every page was blank: whatever went wrong there, an upload of
+// nothing but blank pages is more useful to whoever's looking for it than no
+// upload at all. Files bigger than scan2blob::pdf::MAX_IN_MEMORY_SIZE aren't
+// checked, and go through with their blank pages still in.
+fn remove_blank_pages(
+    path: &std::path::Path,
+    kind: scan2blob::pdf::InputKind,
+    blank_threshold: f64,
+) -> Result<(Option<lopdf::Document>, usize), scan2blob::error::WuffError> {
+    let mut input: std::io::BufReader<std::fs::File> =
+        std::io::BufReader::new(std::fs::File::open(path)?);
+    let doc: lopdf::Document = scan2blob::pdf::load(kind, &mut input)?;
+    let num_pages: u32 = scan2blob::pdf::num_pages(&doc);
+    let blank_pages: Vec<u32> =
+        scan2blob::ink::blank_pages(&doc, blank_threshold);
+    if blank_pages.is_empty() || blank_pages.len() as u32 == num_pages {
+        return Ok((None, 0));
+    }
+    let pages: Vec<(usize, u32)> = (1..=num_pages)
+        .filter(|page_number| !blank_pages.contains(page_number))
+        .map(|page_number| (0, page_number))
+        .collect();
+    Ok((
+        Some(scan2blob::pdf::assemble(vec![doc], &pages)?),
+        blank_pages.len(),
+    ))
+}
+
+fn save(
+    mut doc: lopdf::Document,
+    mut sink: ChannelSink,
+) -> Result<(), scan2blob::error::WuffError> {
+    doc.save_to(&mut sink)?;
+    std::io::Write::flush(&mut sink)?;
+    Ok(())
+}
+
+pub enum Conversion {
+    Uploaded,
+    Unconvertible(scan2blob::error::WuffError),
+}
+
+// Runs `convert` on a blocking thread, and uploads whatever it writes to the
+// ChannelSink it's given. Conversion failures are not errors as far as this
+// function is concerned: they come back as Conversion::Unconvertible, so the
+// caller can decide what to do instead.
+pub async fn upload_converted<M, F>(
+    make_writer: M,
+    convert: F,
+) -> Result<Conversion, scan2blob::error::WuffError>
+where
+    M: FnOnce() -> scan2blob::chunker::Writer,
+    F: FnOnce(ChannelSink) -> Result<(), scan2blob::error::WuffError>
+        + Send
+        + 'static,
+{
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<Vec<u8>>(2);
+    let sink: ChannelSink = ChannelSink {
+        tx,
+        buf: Vec::with_capacity(CONVERSION_BUFFER_SIZE),
+    };
+    let conversion: tokio::task::JoinHandle<
+        Result<(), scan2blob::error::WuffError>,
+    > = tokio::task::spawn_blocking(move || convert(sink));
+
+    // We don't start the upload until the first piece of output shows up. If
+    // the conversion is going to fail right away (which is how it usually
+    // fails, if it's going to) then there's no upload to clean up after.
+    let mut make_writer: Option<M> = Some(make_writer);
+    let mut writer: Option<scan2blob::chunker::Writer> = None;
+    while let Some(data) = rx.recv().await {
+        if let Some(make_writer) = make_writer.take() {
+            writer = Some(make_writer());
+        }
+        let w: &mut scan2blob::chunker::Writer = writer.as_mut().unwrap();
+        if let Err(err) = w.write(&data).await {
+            // Dropping the receiver makes the conversion give up, too.
+            drop(rx);
+            let _ = conversion.await;
+            return Err(err);
+        }
+    }
+
+    match (conversion.await?, writer) {
+        (Ok(()), Some(mut writer)) => {
+            writer.finalize().await?;
+            Ok(Conversion::Uploaded)
+        }
+        (Ok(()), None) => Err(scan2blob::error::WuffError::from(
+            "conversion produced no output",
+        )),
+        (Err(err), Some(writer)) => {
+            writer.observe_error(err.clone());
+            Ok(Conversion::Unconvertible(err))
+        }
+        (Err(err), None) => Ok(Conversion::Unconvertible(err)),
+    }
+}
+
+async fn process(
+    destination: &std::sync::Arc<crate::destination::Destination>,
+    transform: &Transform,
+    spool_file: &crate::spool::SpoolFile,
+    blob_name_base: &str,
+    suffix: &str,
+    content_type: &str,
+    upload_context: crate::upload_context::UploadContext,
+) -> Result<(), scan2blob::error::WuffError> {
+    let Some(kind) =
+        scan2blob::pdf::InputKind::from_content_type(content_type)
+    else {
+        return Err(scan2blob::error::WuffError::from(
+            "not a convertible content type",
+        ));
+    };
+    let mut orig_blob_name: String = format!("{}{}", blob_name_base, suffix);
+    let pdf_blob_name: String =
+        format!("{}{}", blob_name_base, transform.pdf_mime_type.suffix);
+
+    let mut metadata: std::collections::BTreeMap<String, String> =
+        std::collections::BTreeMap::new();
+    let mut pruned_context: crate::upload_context::UploadContext =
+        upload_context.clone();
+    let mut pruned: Option<lopdf::Document> = None;
+    if let Some(blank_threshold) = transform.blank_threshold {
+        let path: std::path::PathBuf = spool_file.path().to_path_buf();
+        match tokio::task::spawn_blocking(move || {
+            remove_blank_pages(&path, kind, blank_threshold)
+        })
+        .await?
+        {
+            Ok((doc, num_removed)) => {
+                metadata.insert(
+                    REMOVED_BLANK_PAGES_METADATA.to_string(),
+                    num_removed.to_string(),
+                );
+                pruned_context.processing.push(
+                    crate::upload_context::ProcessingStep::RemoveBlankPages {
+                        num_removed,
+                    },
+                );
+                pruned = doc;
+            }
+            Err(err) => {
+                destination.ctx.log_warn(format!(
+                    "{}: could not check {} for blank pages: {}",
+                    destination.name, orig_blob_name, err
+                ));
+            }
+        }
+    }
+
+    if pruned.is_none()
+        && !(transform.convert_to_pdf
+            && kind != scan2blob::pdf::InputKind::Pdf)
+    {
+        // Nothing to do to it after all.
+        return spool_file
+            .copy_to(destination.write_blob(
+                orig_blob_name,
+                content_type.to_string(),
+                metadata,
+                pruned_context,
+            ))
+            .await;
+    }
+
+    // If what came in was a PDF already, and we're keeping it, the original
+    // and the new PDF would otherwise both want the same name.
+    if transform.keep_original && orig_blob_name == pdf_blob_name {
+        orig_blob_name = format!("{}-original{}", blob_name_base, suffix);
+    }
+    let mut pdf_context: crate::upload_context::UploadContext = pruned_context;
+    if kind != scan2blob::pdf::InputKind::Pdf {
+        pdf_context
+            .processing
+            .insert(0, crate::upload_context::ProcessingStep::ConvertToPdf);
+    }
+    let upload_original = async {
+        if transform.keep_original {
+            spool_file
+                .copy_to(destination.write_blob(
+                    orig_blob_name.clone(),
+                    content_type.to_string(),
+                    std::collections::BTreeMap::new(),
+                    upload_context.clone(),
+                ))
+                .await
+        } else {
+            Ok(())
+        }
+    };
+    let upload_pdf = async {
+        let path: std::path::PathBuf = spool_file.path().to_path_buf();
+        upload_converted(
+            || {
+                destination.write_blob(
+                    pdf_blob_name,
+                    transform.pdf_mime_type.content_type.clone(),
+                    metadata,
+                    pdf_context,
+                )
+            },
+            move |sink| match pruned {
+                Some(doc) => save(doc, sink),
+                None => convert(&path, kind, sink),
+            },
+        )
+        .await
+    };
+    let (original_result, pdf_result) =
+        futures::join!(upload_original, upload_pdf);
+    original_result?;
+    match pdf_result? {
+        Conversion::Uploaded => Ok(()),
+        Conversion::Unconvertible(err) => {
+            // Better to end up with the file in its original format than to
+            // end up with nothing at all.
+            destination.ctx.log_warn(format!(
+                "{}: could not convert {} to pdf, uploading it as-is: {}",
+                destination.name, orig_blob_name, err
+            ));
+            if transform.keep_original {
+                Ok(())
+            } else {
+                spool_file
+                    .copy_to(destination.write_blob(
+                        orig_blob_name,
+                        content_type.to_string(),
+                        std::collections::BTreeMap::new(),
+                        upload_context,
+                    ))
+                    .await
+            }
+        }
+    }
+}
+
+pub async fn run(
+    destination: std::sync::Arc<crate::destination::Destination>,
+    mut reader: scan2blob::chunker::Reader,
+    blob_name_base: String,
+    suffix: String,
+    content_type: String,
+    upload_context: crate::upload_context::UploadContext,
+) {
+    let transform: &Transform = destination.transform.as_ref().unwrap();
+    let spool_file: crate::spool::SpoolFile =
+        match crate::spool::SpoolFile::receive(
+            &transform.spool_directory,
+            &mut reader,
+        )
+        .await
+        {
+            Ok(spool_file) => spool_file,
+            Err(err) => {
+                destination.ctx.log_info(format!(
+                    "{}: aborting upload of {}{} due to error: {}",
+                    destination.name, blob_name_base, suffix, err
+                ));
+                reader.observe_error(err);
+                return;
+            }
+        };
+
+    destination.ctx.log_debug(format!(
+        "{}: processing {}{} ({} bytes)",
+        destination.name,
+        blob_name_base,
+        suffix,
+        spool_file.len()
+    ));
+    match process(
+        &destination,
+        transform,
+        &spool_file,
+        &blob_name_base,
+        &suffix,
+        &content_type,
+        upload_context,
+    )
+    .await
+    {
+        Ok(()) => {
+            if let Err(err) = reader.finalize().await {
+                destination.ctx.log_info(format!(
+                    "{}: aborting upload of {}{} due to propagated error: {}",
+                    destination.name, blob_name_base, suffix, err
+                ));
+            }
+        }
+        Err(err) => {
+            destination.ctx.log_info(format!(
+                "{}: upload of {}{} failed: {}",
+                destination.name, blob_name_base, suffix, err
+            ));
+            reader.observe_error(err);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+// With virus scanning turned on, everything that's uploaded to a destination
+// is also streamed to clamd as it goes, and the blob only gets committed if
+// clamd says it's clean. If clamd finds something, or can't be reached, or
+// can't scan the file, the blob is never committed, and the uncommitted
+// blocks are eventually cleaned up by Azure. If there's a quarantine
+// directory, a copy of anything clamd found something in is kept there, so
+// that someone can take a look at it.
+
+#[derive(serde::Deserialize)]
+pub struct ConfigVirusScan {
+    pub clamd: String,
+    pub quarantine_directory: Option<std::path::PathBuf>,
+}
+
+pub struct ConfigVirusScanEnriched {
+    pub clamd: scan2blob::clamd::Address,
+    pub quarantine_directory: Option<std::path::PathBuf>,
+}
+
+impl TryFrom<ConfigVirusScan> for ConfigVirusScanEnriched {
+    type Error = scan2blob::error::WuffError;
+
+    fn try_from(
+        config: ConfigVirusScan,
+    ) -> Result<Self, scan2blob::error::WuffError> {
+        let ConfigVirusScan {
+            clamd,
+            quarantine_directory,
+        } = config;
+        Ok(Self {
+            clamd: scan2blob::clamd::Address::parse(&clamd)?,
+            quarantine_directory,
+        })
+    }
+}
+
+pub struct VirusScanner {
+    clamd: scan2blob::clamd::Address,
+    quarantine_directory: Option<std::path::PathBuf>,
+}
+
+impl VirusScanner {
+    pub fn new(cfg: &ConfigVirusScanEnriched) -> Self {
+        Self {
+            clamd: cfg.clamd.clone(),
+            quarantine_directory: cfg.quarantine_directory.clone(),
+        }
+    }
+
+    pub async fn start(
+        &self,
+        blob_name: &str,
+    ) -> Result<Session, scan2blob::error::WuffError> {
+        let scan: scan2blob::clamd::Scan<Box<dyn scan2blob::clamd::Stream>> =
+            match scan2blob::clamd::Scan::connect(&self.clamd).await {
+                Ok(scan) => scan,
+                Err(err) => {
+                    return Err(scan2blob::error::WuffError::from(format!(
+                        "could not connect to clamd at {}: {}",
+                        self.clamd, err
+                    )));
+                }
+            };
+        let quarantine: Option<(std::path::PathBuf, tokio::fs::File)> =
+            if let Some(ref quarantine_directory) = self.quarantine_directory {
+                // Blob names can have slashes in them, but that's not
+                // something we want in a file name.
+                let path: std::path::PathBuf =
+                    quarantine_directory.join(blob_name.replace('/', "_"));
+                let f: tokio::fs::File =
+                    tokio::fs::File::create(&path).await?;
+                Some((path, f))
+            } else {
+                None
+            };
+        Ok(Session {
+            scan: Some(scan),
+            quarantine,
+        })
+    }
+}
+
+pub struct Session {
+    // Only None once the scan has finished.
+    scan: Option<scan2blob::clamd::Scan<Box<dyn scan2blob::clamd::Stream>>>,
+    // Where we're keeping a copy, in case we need to quarantine it. Dropping
+    // the session deletes the copy, unless it's been quarantined.
+    quarantine: Option<(std::path::PathBuf, tokio::fs::File)>,
+}
+
+impl Session {
+    pub fn quarantine_path(&self) -> Option<&std::path::Path> {
+        self.quarantine.as_ref().map(|(path, _)| path.as_path())
+    }
+
+    pub async fn send(
+        &mut self,
+        data: &[u8],
+    ) -> Result<(), scan2blob::error::WuffError> {
+        if let Some((_, ref mut f)) = self.quarantine {
+            tokio::io::AsyncWriteExt::write_all(f, data).await?;
+        }
+        self.scan.as_mut().unwrap().send(data).await
+    }
+
+    pub async fn finish(
+        mut self,
+    ) -> Result<scan2blob::clamd::Verdict, scan2blob::error::WuffError> {
+        if let Some((_, ref mut f)) = self.quarantine {
+            tokio::io::AsyncWriteExt::flush(f).await?;
+        }
+        let verdict: scan2blob::clamd::Verdict =
+            self.scan.take().unwrap().finish().await?;
+        if let scan2blob::clamd::Verdict::Infected(_) = verdict {
+            // Keep the copy.
+            self.quarantine = None;
+        }
+        Ok(verdict)
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        if let Some((ref path, _)) = self.quarantine {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
@@ -0,0 +1,49 @@
+// Who's done what to a gate lately, for admins to look at. It only goes back
+// so far, and doesn't survive a restart, so anything that needs to be kept
+// for longer than that should come from the logs instead, which get
+// everything that goes in here, too.
+
+// How many entries each gate keeps.
+const AUDIT_LOG_LENGTH: usize = 50;
+
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct AuditEntry {
+    // Seconds since the epoch.
+    pub time: u64,
+    pub username: String,
+    pub action: String,
+}
+
+#[derive(Debug)]
+pub struct AuditLog {
+    // Oldest first.
+    entries: std::sync::Mutex<std::collections::VecDeque<AuditEntry>>,
+}
+
+impl AuditLog {
+    pub fn new() -> Self {
+        Self {
+            entries: std::sync::Mutex::new(std::collections::VecDeque::new()),
+        }
+    }
+
+    pub fn record(&self, username: &str, action: String) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.push_back(AuditEntry {
+            time: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            username: username.to_string(),
+            action,
+        });
+        while entries.len() > AUDIT_LOG_LENGTH {
+            let _ = entries.pop_front();
+        }
+    }
+
+    // Newest first.
+    pub fn get(&self) -> Vec<AuditEntry> {
+        self.entries.lock().unwrap().iter().rev().cloned().collect()
+    }
+}
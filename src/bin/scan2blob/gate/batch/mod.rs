@@ -0,0 +1,718 @@
+// Some scanners upload every page as a file of its own. With batching turned
+// on, files of the kinds we know how to merge are held back (spooled to local
+// disk) rather than uploaded right away, and once nothing new has shown up
+// for a while, everything that was held is merged into a single PDF and
+// uploaded as one document. If any of them are PDFs already, merging means
+// having the whole batch in memory at once, so batches that add up to more
+// than scan2blob::pdf::MAX_IN_MEMORY_SIZE are uploaded as separate files, the
+// same as batches that can't be merged for any other reason.
+//
+// There's one batch at a time per upload session: files only get merged
+// together if they came in through the same listener, as the same user, from
+// the same address, and are going to the same destination. Otherwise, two
+// scanners (or two people) uploading through the same gate at the same time
+// would end up in each other's documents.
+//
+// How long to wait for the next file is up to the gate, although some users
+// can be given a window of their own, for scanners that are slower (or
+// faster) between pages than the rest. It's by username, whichever listener
+// they come in through.
+
+#[derive(serde::Deserialize)]
+pub struct ConfigBatching {
+    #[serde(default = "default_window")]
+    pub window: u32,
+    #[serde(default)]
+    pub user_windows: std::collections::HashMap<String, u32>,
+    pub spool_directory: Option<std::path::PathBuf>,
+}
+
+pub struct ConfigBatchingEnriched {
+    pub window: u32,
+    pub user_windows: std::collections::HashMap<String, u32>,
+    pub spool_directory: std::path::PathBuf,
+}
+
+impl TryFrom<ConfigBatching> for ConfigBatchingEnriched {
+    type Error = scan2blob::error::WuffError;
+
+    fn try_from(
+        config: ConfigBatching,
+    ) -> Result<Self, scan2blob::error::WuffError> {
+        let ConfigBatching {
+            window,
+            user_windows,
+            spool_directory,
+        } = config;
+        if window == 0 {
+            return Err(scan2blob::error::WuffError::from(
+                "batching window must be at least 1 second",
+            ));
+        }
+        for (username, window) in &user_windows {
+            if *window == 0 {
+                return Err(scan2blob::error::WuffError::from(format!(
+                    "{}: batching window must be at least 1 second",
+                    username
+                )));
+            }
+        }
+        Ok(Self {
+            window,
+            user_windows,
+            spool_directory: spool_directory
+                .unwrap_or_else(std::env::temp_dir),
+        })
+    }
+}
+
+fn default_window() -> u32 {
+    30
+}
+
+struct BatchFile {
+    spool_file: crate::spool::SpoolFile,
+    kind: scan2blob::pdf::InputKind,
+    suffix: String,
+    content_type: String,
+    upload_context: crate::upload_context::UploadContext,
+}
+
+struct BatchState {
+    files: Vec<BatchFile>,
+    // Files that have started arriving but haven't been spooled yet. The
+    // batch doesn't time out while there are any of these.
+    pending: usize,
+    deadline: std::time::Instant,
+    // Once a batch is closed, nothing new can join it, and it gets flushed as
+    // soon as the pending files have all arrived.
+    closed: bool,
+}
+
+// Which session a file is part of. It's the address without the port, since
+// a scanner that uploads every page as a file of its own might well connect
+// again for each one.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct BatchKey {
+    listener: crate::upload_context::Listener,
+    username: String,
+    client_ip: Option<std::net::IpAddr>,
+    destination: String,
+}
+
+impl BatchKey {
+    fn new(
+        upload_context: &crate::upload_context::UploadContext,
+        destination: &crate::destination::Destination,
+    ) -> Self {
+        Self {
+            listener: upload_context.listener,
+            username: upload_context.username.clone(),
+            client_ip: upload_context
+                .client_addr
+                .map(|client_addr| client_addr.ip()),
+            destination: destination.name.clone(),
+        }
+    }
+}
+
+struct Batch {
+    key: BatchKey,
+    window: std::time::Duration,
+    // So that the name hint can be consumed once the batch is closed.
+    gate: std::sync::Arc<super::Gate>,
+    destination: std::sync::Arc<crate::destination::Destination>,
+    name_hint: Option<String>,
+    state: std::sync::Mutex<BatchState>,
+}
+
+impl Batch {
+    fn try_join(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            false
+        } else {
+            state.pending += 1;
+            true
+        }
+    }
+}
+
+pub struct Batcher {
+    ctx: std::sync::Arc<crate::ctx::Ctx>,
+    gate_name: String,
+    window: std::time::Duration,
+    user_windows: std::collections::HashMap<String, std::time::Duration>,
+    spool_directory: std::path::PathBuf,
+    pdf_mime_type: crate::mime_types::ConfigMimeTypeEnriched,
+    batches: std::sync::Mutex<
+        std::collections::HashMap<BatchKey, std::sync::Arc<Batch>>,
+    >,
+}
+
+impl Batcher {
+    pub fn new(
+        ctx: &std::sync::Arc<crate::ctx::Ctx>,
+        gate_name: &str,
+        cfg: &ConfigBatchingEnriched,
+    ) -> Result<Self, scan2blob::error::WuffError> {
+        let Some(pdf_mime_type) =
+            ctx.config.mime_types.get_by_extension("pdf")
+        else {
+            return Err(scan2blob::error::WuffError::from(
+                "batching requires a mime type for pdf",
+            ));
+        };
+        Ok(Self {
+            ctx: std::sync::Arc::clone(ctx),
+            gate_name: gate_name.to_string(),
+            window: std::time::Duration::from_secs(cfg.window as u64),
+            user_windows: cfg
+                .user_windows
+                .iter()
+                .map(|(username, window)| {
+                    (
+                        username.clone(),
+                        std::time::Duration::from_secs(*window as u64),
+                    )
+                })
+                .collect(),
+            spool_directory: cfg.spool_directory.clone(),
+            pdf_mime_type,
+            batches: std::sync::Mutex::new(std::collections::HashMap::new()),
+        })
+    }
+
+    pub fn applies_to(&self, content_type: &str) -> bool {
+        scan2blob::pdf::InputKind::from_content_type(content_type).is_some()
+    }
+
+    fn window_for(&self, username: &str) -> std::time::Duration {
+        self.user_windows
+            .get(username)
+            .copied()
+            .unwrap_or(self.window)
+    }
+
+    // How many files are currently being held, across all batches.
+    pub fn num_held_files(&self) -> usize {
+        let batches = self.batches.lock().unwrap();
+        batches
+            .values()
+            .map(|batch| {
+                let state = batch.state.lock().unwrap();
+                state.files.len() + state.pending
+            })
+            .sum()
+    }
+
+    pub fn write_file(
+        self: &std::sync::Arc<Self>,
+        gate: &std::sync::Arc<super::Gate>,
+        destination: &std::sync::Arc<crate::destination::Destination>,
+        upload_context: crate::upload_context::UploadContext,
+        suffix: String,
+        content_type: String,
+    ) -> scan2blob::chunker::Writer {
+        let name_hint: Option<String> = upload_context.name_hint.clone();
+        let kind: scan2blob::pdf::InputKind =
+            scan2blob::pdf::InputKind::from_content_type(&content_type)
+                .unwrap();
+        let key: BatchKey = BatchKey::new(&upload_context, destination);
+        let (writer, reader) = destination.new_chunker();
+
+        let batch: std::sync::Arc<Batch> = {
+            let mut batches = self.batches.lock().unwrap();
+            let existing: Option<std::sync::Arc<Batch>> =
+                batches.get(&key).cloned();
+            match existing {
+                // A different name hint means a different document.
+                Some(batch)
+                    if batch.name_hint == name_hint && batch.try_join() =>
+                {
+                    batch
+                }
+                existing => {
+                    if let Some(existing) = existing {
+                        self.close(&existing);
+                    }
+                    let window: std::time::Duration =
+                        self.window_for(&key.username);
+                    let batch: std::sync::Arc<Batch> =
+                        std::sync::Arc::new(Batch {
+                            key: key.clone(),
+                            window,
+                            gate: std::sync::Arc::clone(gate),
+                            destination: std::sync::Arc::clone(destination),
+                            name_hint,
+                            state: std::sync::Mutex::new(BatchState {
+                                files: Vec::new(),
+                                pending: 1,
+                                deadline: std::time::Instant::now() + window,
+                                closed: false,
+                            }),
+                        });
+                    batches.insert(key, std::sync::Arc::clone(&batch));
+                    let async_spawner = self.ctx.base_ctx.get_async_spawner();
+                    async_spawner.spawn(
+                        std::sync::Arc::clone(self)
+                            .expire(std::sync::Arc::clone(&batch)),
+                    );
+                    batch
+                }
+            }
+        };
+
+        let async_spawner = self.ctx.base_ctx.get_async_spawner();
+        async_spawner.spawn(std::sync::Arc::clone(self).receive(
+            batch,
+            reader,
+            kind,
+            suffix,
+            content_type,
+            upload_context,
+        ));
+        writer
+    }
+
+    // Closes all open batches, which will then be uploaded as soon as any
+    // files still on their way in have arrived.
+    pub fn finish_all(self: &std::sync::Arc<Self>) {
+        let mut batches = self.batches.lock().unwrap();
+        for (_, batch) in batches.drain() {
+            self.close(&batch);
+        }
+    }
+
+    fn close(self: &std::sync::Arc<Self>, batch: &std::sync::Arc<Batch>) {
+        let mut state = batch.state.lock().unwrap();
+        if state.closed {
+            return;
+        }
+        state.closed = true;
+        if state.pending == 0 {
+            self.spawn_flush(batch, &mut state);
+        }
+        drop(state);
+        // Anything that shows up from now on is a different document.
+        if let Some(ref name_hint) = batch.name_hint {
+            batch.gate.consume_name_hint(name_hint);
+        }
+    }
+
+    fn spawn_flush(
+        self: &std::sync::Arc<Self>,
+        batch: &std::sync::Arc<Batch>,
+        state: &mut BatchState,
+    ) {
+        let files: Vec<BatchFile> = std::mem::take(&mut state.files);
+        if files.is_empty() {
+            return;
+        }
+        let async_spawner = self.ctx.base_ctx.get_async_spawner();
+        async_spawner.spawn(
+            std::sync::Arc::clone(self)
+                .flush(std::sync::Arc::clone(batch), files),
+        );
+    }
+
+    async fn expire(self: std::sync::Arc<Self>, batch: std::sync::Arc<Batch>) {
+        loop {
+            let deadline: std::time::Instant = {
+                let state = batch.state.lock().unwrap();
+                if state.closed {
+                    return;
+                }
+                state.deadline
+            };
+            tokio::time::sleep_until(deadline.into()).await;
+
+            let mut batches = self.batches.lock().unwrap();
+            let mut state = batch.state.lock().unwrap();
+            if state.closed {
+                return;
+            }
+            let now: std::time::Instant = std::time::Instant::now();
+            if state.pending > 0 {
+                // Something's still arriving. Whenever it's done, it'll push
+                // the deadline out again.
+                state.deadline = now + batch.window;
+            } else if now >= state.deadline {
+                drop(state);
+                if let Some(current) = batches.get(&batch.key) {
+                    if std::sync::Arc::ptr_eq(current, &batch) {
+                        batches.remove(&batch.key);
+                    }
+                }
+                drop(batches);
+                self.close(&batch);
+                return;
+            }
+        }
+    }
+
+    async fn receive(
+        self: std::sync::Arc<Self>,
+        batch: std::sync::Arc<Batch>,
+        mut reader: scan2blob::chunker::Reader,
+        kind: scan2blob::pdf::InputKind,
+        suffix: String,
+        content_type: String,
+        upload_context: crate::upload_context::UploadContext,
+    ) {
+        let result: Result<
+            crate::spool::SpoolFile,
+            scan2blob::error::WuffError,
+        > = match crate::spool::SpoolFile::receive(
+            &self.spool_directory,
+            &mut reader,
+        )
+        .await
+        {
+            Ok(spool_file) => match reader.finalize().await {
+                Ok(()) => Ok(spool_file),
+                Err(err) => Err(err),
+            },
+            Err(err) => {
+                reader.observe_error(err.clone());
+                Err(err)
+            }
+        };
+
+        let mut state = batch.state.lock().unwrap();
+        state.pending -= 1;
+        match result {
+            Ok(spool_file) => {
+                self.ctx.log_debug(format!(
+                    "{}: holding {} byte file for batch",
+                    self.gate_name,
+                    spool_file.len()
+                ));
+                state.files.push(BatchFile {
+                    spool_file,
+                    kind,
+                    suffix,
+                    content_type,
+                    upload_context,
+                });
+                state.deadline = std::time::Instant::now() + batch.window;
+            }
+            Err(err) => {
+                self.ctx.log_info(format!(
+                    "{}: not adding file to batch due to error: {}",
+                    self.gate_name, err
+                ));
+            }
+        }
+        if state.closed && state.pending == 0 {
+            self.spawn_flush(&batch, &mut state);
+        }
+    }
+
+    async fn flush(
+        self: std::sync::Arc<Self>,
+        batch: std::sync::Arc<Batch>,
+        files: Vec<BatchFile>,
+    ) {
+        let destination: &std::sync::Arc<crate::destination::Destination> =
+            &batch.destination;
+        if files.len() > 1 {
+            self.ctx.log_debug(format!(
+                "{}: merging batch of {} files for {}",
+                self.gate_name,
+                files.len(),
+                destination.name
+            ));
+            let inputs: Vec<(scan2blob::pdf::InputKind, std::path::PathBuf)> =
+                files
+                    .iter()
+                    .map(|file| {
+                        (file.kind, file.spool_file.path().to_path_buf())
+                    })
+                    .collect();
+            let contexts: Vec<crate::upload_context::UploadContext> = files
+                .iter()
+                .map(|file| file.upload_context.clone())
+                .collect();
+            let upload_context: crate::upload_context::UploadContext =
+                crate::upload_context::UploadContext::combine(&contexts)
+                    .with_step(
+                        crate::upload_context::ProcessingStep::MergeBatch {
+                            num_files: files.len(),
+                        },
+                    );
+            match crate::destination::transform::upload_converted(
+                || {
+                    destination.write_file(
+                        upload_context,
+                        self.pdf_mime_type.suffix.clone(),
+                        self.pdf_mime_type.content_type.clone(),
+                    )
+                },
+                move |sink| merge(inputs, sink),
+            )
+            .await
+            {
+                Ok(crate::destination::transform::Conversion::Uploaded) => {
+                    return;
+                }
+                Ok(
+                    crate::destination::transform::Conversion::Unconvertible(
+                        err,
+                    ),
+                ) => {
+                    // Better to end up with the pages as separate files than
+                    // to end up with nothing at all.
+                    self.ctx.log_warn(format!(
+                        "{}: could not merge batch for {}, uploading the files separately: {}",
+                        self.gate_name, destination.name, err
+                    ));
+                }
+                Err(err) => {
+                    self.ctx.log_warn(format!(
+                        "{}: upload of merged batch for {} failed, uploading the files separately: {}",
+                        self.gate_name, destination.name, err
+                    ));
+                }
+            }
+        }
+
+        // One at a time, so that they get distinct timestamps, in order.
+        for file in files {
+            if let Err(err) = file
+                .spool_file
+                .copy_to(destination.write_file(
+                    file.upload_context,
+                    file.suffix,
+                    file.content_type,
+                ))
+                .await
+            {
+                self.ctx.log_info(format!(
+                    "{}: upload of held file for {} failed: {}",
+                    self.gate_name, destination.name, err
+                ));
+            }
+        }
+    }
+}
+
+fn merge(
+    inputs: Vec<(scan2blob::pdf::InputKind, std::path::PathBuf)>,
+    sink: crate::destination::transform::ChannelSink,
+) -> Result<(), scan2blob::error::WuffError> {
+    let mut files: Vec<(
+        scan2blob::pdf::InputKind,
+        std::io::BufReader<std::fs::File>,
+    )> = Vec::with_capacity(inputs.len());
+    for (kind, path) in inputs {
+        files
+            .push((kind, std::io::BufReader::new(std::fs::File::open(path)?)));
+    }
+    scan2blob::pdf::merge(&mut files, sink)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // With a window that's short enough to wait out.
+    fn new_batcher(gate: &crate::gate::Gate) -> std::sync::Arc<Batcher> {
+        let cfg: ConfigBatchingEnriched =
+            serde_json::from_value::<ConfigBatching>(serde_json::json!({
+                "user_windows": {"slow": 120},
+            }))
+            .unwrap()
+            .try_into()
+            .unwrap();
+        let mut batcher: Batcher =
+            Batcher::new(&gate.ctx, &gate.name, &cfg).unwrap();
+        batcher.window = std::time::Duration::from_millis(20);
+        std::sync::Arc::new(batcher)
+    }
+
+    // An empty batch, as if the first file were on its way, if there's
+    // anything pending.
+    fn new_batch(
+        gate: &std::sync::Arc<crate::gate::Gate>,
+        batcher: &Batcher,
+        username: &str,
+        name_hint: Option<&str>,
+        pending: usize,
+    ) -> std::sync::Arc<Batch> {
+        let destination: std::sync::Arc<crate::destination::Destination> =
+            gate.destinations.get("test").unwrap();
+        let window: std::time::Duration = batcher.window_for(username);
+        let batch: std::sync::Arc<Batch> = std::sync::Arc::new(Batch {
+            key: BatchKey {
+                listener: crate::upload_context::Listener::Sftp,
+                username: username.to_string(),
+                client_ip: None,
+                destination: destination.name.clone(),
+            },
+            window,
+            gate: std::sync::Arc::clone(gate),
+            destination,
+            name_hint: name_hint.map(String::from),
+            state: std::sync::Mutex::new(BatchState {
+                files: Vec::new(),
+                pending,
+                deadline: std::time::Instant::now() + window,
+                closed: false,
+            }),
+        });
+        let _ = batcher
+            .batches
+            .lock()
+            .unwrap()
+            .insert(batch.key.clone(), std::sync::Arc::clone(&batch));
+        batch
+    }
+
+    fn run_test<F>(gate: &crate::gate::Gate, f: F)
+    where
+        F: std::future::Future<Output = ()>,
+    {
+        gate.ctx
+            .base_ctx
+            .run_async_main(async move {
+                f.await;
+                Ok(())
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn config() {
+        let parse = |cfg: serde_json::Value| {
+            ConfigBatchingEnriched::try_from(
+                serde_json::from_value::<ConfigBatching>(cfg).unwrap(),
+            )
+        };
+        let cfg: ConfigBatchingEnriched =
+            parse(serde_json::json!({})).unwrap();
+        assert_eq!(cfg.window, 30);
+        assert!(cfg.user_windows.is_empty());
+        assert!(parse(serde_json::json!({"window": 0})).is_err());
+        assert!(
+            parse(serde_json::json!({"user_windows": {"slow": 0}})).is_err()
+        );
+    }
+
+    #[test]
+    fn user_windows() {
+        let gate: std::sync::Arc<crate::gate::Gate> =
+            crate::gate::test::new_gate(serde_json::json!({}));
+        let batcher: std::sync::Arc<Batcher> = new_batcher(&gate);
+        assert_eq!(
+            batcher.window_for("slow"),
+            std::time::Duration::from_secs(120)
+        );
+        assert_eq!(
+            batcher.window_for("anyone"),
+            std::time::Duration::from_millis(20)
+        );
+    }
+
+    #[test]
+    fn expires_once_idle() {
+        let gate: std::sync::Arc<crate::gate::Gate> =
+            crate::gate::test::new_gate(serde_json::json!({}));
+        gate.assert_gate_open_timed_with_name_hints(
+            vec![String::from("one"), String::from("two")],
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        let batcher: std::sync::Arc<Batcher> = new_batcher(&gate);
+        let batch: std::sync::Arc<Batch> =
+            new_batch(&gate, &batcher, "fast", Some("one"), 0);
+        run_test(&gate, async {
+            let started: std::time::Instant = std::time::Instant::now();
+            std::sync::Arc::clone(&batcher)
+                .expire(std::sync::Arc::clone(&batch))
+                .await;
+            assert!(started.elapsed() >= batch.window);
+        });
+        assert!(batch.state.lock().unwrap().closed);
+        assert_eq!(batcher.num_held_files(), 0);
+        assert!(batcher.batches.lock().unwrap().is_empty());
+        // The next document gets the next name hint.
+        assert_eq!(gate.get_current_state(), Some(Some(String::from("two"))));
+    }
+
+    #[test]
+    fn pending_files_keep_it_open() {
+        let gate: std::sync::Arc<crate::gate::Gate> =
+            crate::gate::test::new_gate(serde_json::json!({}));
+        let batcher: std::sync::Arc<Batcher> = new_batcher(&gate);
+        let batch: std::sync::Arc<Batch> =
+            new_batch(&gate, &batcher, "fast", None, 1);
+        run_test(&gate, async {
+            let expiring: tokio::task::JoinHandle<()> =
+                gate.ctx.base_ctx.get_async_spawner().spawn(
+                    std::sync::Arc::clone(&batcher)
+                        .expire(std::sync::Arc::clone(&batch)),
+                );
+            tokio::time::sleep(batch.window * 5).await;
+            assert!(!batch.state.lock().unwrap().closed);
+            assert_eq!(batcher.num_held_files(), 1);
+
+            // As if it had failed to arrive.
+            batch.state.lock().unwrap().pending = 0;
+            expiring.await.unwrap();
+        });
+        assert!(batch.state.lock().unwrap().closed);
+        assert!(batcher.batches.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn finish_all() {
+        let gate: std::sync::Arc<crate::gate::Gate> =
+            crate::gate::test::new_gate(serde_json::json!({}));
+        let batcher: std::sync::Arc<Batcher> = new_batcher(&gate);
+        let fast: std::sync::Arc<Batch> =
+            new_batch(&gate, &batcher, "fast", None, 0);
+        let slow: std::sync::Arc<Batch> =
+            new_batch(&gate, &batcher, "slow", None, 1);
+        batcher.finish_all();
+        assert!(fast.state.lock().unwrap().closed);
+        assert!(slow.state.lock().unwrap().closed);
+        assert!(batcher.batches.lock().unwrap().is_empty());
+        // A closed batch can't be joined, so the next file starts a new one.
+        assert!(!slow.try_join());
+    }
+
+    #[test]
+    fn unmergeable_files_are_unconvertible() {
+        let gate: std::sync::Arc<crate::gate::Gate> =
+            crate::gate::test::new_gate(serde_json::json!({}));
+        let path: std::path::PathBuf = std::env::temp_dir().join(format!(
+            "scan2blob-test-{}-unmergeable.pdf",
+            std::process::id()
+        ));
+        std::fs::write(&path, b"not a pdf").unwrap();
+        let inputs: Vec<(scan2blob::pdf::InputKind, std::path::PathBuf)> = vec![
+            (scan2blob::pdf::InputKind::Pdf, path.clone()),
+            (scan2blob::pdf::InputKind::Pdf, path.clone()),
+        ];
+        run_test(&gate, async {
+            // Nothing gets as far as being uploaded, so the files are left
+            // to be uploaded one at a time instead.
+            let conversion: crate::destination::transform::Conversion =
+                crate::destination::transform::upload_converted(
+                    || panic!("nothing should be uploaded"),
+                    move |sink| merge(inputs, sink),
+                )
+                .await
+                .unwrap();
+            assert!(matches!(
+                conversion,
+                crate::destination::transform::Conversion::Unconvertible(_)
+            ));
+        });
+        std::fs::remove_file(&path).unwrap();
+    }
+}
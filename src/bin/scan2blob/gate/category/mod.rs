@@ -0,0 +1,104 @@
+// A gate can have a list of categories to pick from when it's opened, like
+// "taxes" or "receipts". Whatever comes through the gate while a category is
+// picked gets filed accordingly: in a different destination from the one the
+// user would normally upload to, and/or under a sub-prefix within it, and
+// optionally named according to a template of its own.
+
+#[derive(serde::Deserialize)]
+pub struct ConfigCategory {
+    pub name: String,
+    pub destination: Option<String>,
+    #[serde(default)]
+    pub prefix: String,
+    pub template: Option<String>,
+}
+
+pub struct ConfigCategoryEnriched {
+    pub name: String,
+    pub destination: Option<String>,
+    pub prefix: String,
+    pub template: Option<scan2blob::template::Template>,
+}
+
+impl TryFrom<ConfigCategory> for ConfigCategoryEnriched {
+    type Error = scan2blob::error::WuffError;
+
+    fn try_from(
+        config: ConfigCategory,
+    ) -> Result<Self, scan2blob::error::WuffError> {
+        let ConfigCategory {
+            name,
+            destination,
+            prefix,
+            template,
+        } = config;
+        if name.is_empty() {
+            return Err(scan2blob::error::WuffError::from(
+                "category name must not be empty",
+            ));
+        }
+        if destination.is_none() && prefix.is_empty() {
+            return Err(scan2blob::error::WuffError::from(format!(
+                "category {}: needs a destination, a prefix, or both",
+                name
+            )));
+        }
+        let template: Option<scan2blob::template::Template> =
+            if let Some(template) = template {
+                Some(
+                    scan2blob::template::Template::parse(
+                        &template,
+                        crate::destination::TEMPLATE_VARIABLES,
+                    )
+                    .map_err(|e| {
+                        scan2blob::error::WuffError::from(format!(
+                            "category {}: {}",
+                            name, e
+                        ))
+                    })?,
+                )
+            } else {
+                None
+            };
+        Ok(Self {
+            name,
+            destination,
+            prefix,
+            template,
+        })
+    }
+}
+
+pub struct Category {
+    pub name: String,
+    pub destination: Option<std::sync::Arc<crate::destination::Destination>>,
+    pub prefix: String,
+    pub template: Option<scan2blob::template::Template>,
+}
+
+impl Category {
+    pub fn new(
+        cfg: &ConfigCategoryEnriched,
+        destinations: &crate::destination::Destinations,
+    ) -> Result<Self, scan2blob::error::WuffError> {
+        let destination: Option<
+            std::sync::Arc<crate::destination::Destination>,
+        > = if let Some(ref destination) = cfg.destination {
+            let Some(destination) = destinations.get(destination) else {
+                return Err(scan2blob::error::WuffError::from(format!(
+                    "category {}: destination not found",
+                    cfg.name
+                )));
+            };
+            Some(destination)
+        } else {
+            None
+        };
+        Ok(Self {
+            name: cfg.name.clone(),
+            destination,
+            prefix: cfg.prefix.clone(),
+            template: cfg.template.clone(),
+        })
+    }
+}
@@ -0,0 +1,497 @@
+// Duplex collation, for scanners that can only scan one side of the page.
+// Someone scans the fronts, flips the stack over, and scans the backs. Once
+// duplex has been armed, the next upload through the gate is taken to be the
+// front pass, and the next one after that which comes from the same user and
+// address, and is going to the same destination, is taken to be the back
+// pass. Anything else that comes through in between goes through as if duplex
+// weren't armed at all. The back pass comes out in reverse order, so the
+// finished document is the first front, the last back, the second front, the
+// second-to-last back, and so on. Collating happens in memory, so if the two
+// passes add up to more than scan2blob::pdf::MAX_IN_MEMORY_SIZE, they're
+// uploaded separately, as they would be if they couldn't be collated at all.
+
+#[derive(serde::Deserialize)]
+pub struct ConfigDuplex {
+    #[serde(default)]
+    pub drop_blank_backs: bool,
+    #[serde(default = "default_blank_threshold")]
+    pub blank_threshold: f64,
+    #[serde(default = "default_timeout")]
+    pub timeout: u32,
+    pub spool_directory: Option<std::path::PathBuf>,
+}
+
+pub struct ConfigDuplexEnriched {
+    pub drop_blank_backs: bool,
+    pub blank_threshold: f64,
+    pub timeout: u32,
+    pub spool_directory: std::path::PathBuf,
+}
+
+impl TryFrom<ConfigDuplex> for ConfigDuplexEnriched {
+    type Error = scan2blob::error::WuffError;
+
+    fn try_from(
+        config: ConfigDuplex,
+    ) -> Result<Self, scan2blob::error::WuffError> {
+        let ConfigDuplex {
+            drop_blank_backs,
+            blank_threshold,
+            timeout,
+            spool_directory,
+        } = config;
+        if !(0.0..=1.0).contains(&blank_threshold) {
+            return Err(scan2blob::error::WuffError::from(
+                "blank_threshold must be between 0 and 1",
+            ));
+        }
+        Ok(Self {
+            drop_blank_backs,
+            blank_threshold,
+            timeout,
+            spool_directory: spool_directory
+                .unwrap_or_else(std::env::temp_dir),
+        })
+    }
+}
+
+fn default_blank_threshold() -> f64 {
+    // 0.2% of the page
+    0.002
+}
+
+fn default_timeout() -> u32 {
+    // 10 minutes
+    600
+}
+
+struct Pass {
+    spool_file: crate::spool::SpoolFile,
+    kind: scan2blob::pdf::InputKind,
+    suffix: String,
+    content_type: String,
+    upload_context: crate::upload_context::UploadContext,
+}
+
+// Where the front pass came from, which is where the back pass has to come
+// from, too. It's the address without the port, since the scanner might well
+// connect again for the second pass.
+#[derive(PartialEq)]
+struct PassSource {
+    listener: crate::upload_context::Listener,
+    username: String,
+    client_ip: Option<std::net::IpAddr>,
+    destination: String,
+}
+
+impl PassSource {
+    fn new(
+        upload_context: &crate::upload_context::UploadContext,
+        destination: &crate::destination::Destination,
+    ) -> Self {
+        Self {
+            listener: upload_context.listener,
+            username: upload_context.username.clone(),
+            client_ip: upload_context
+                .client_addr
+                .map(|client_addr| client_addr.ip()),
+            destination: destination.name.clone(),
+        }
+    }
+}
+
+struct JobState {
+    // Indexed by the order the uploads started in: front, then back.
+    passes: [Option<Pass>; 2],
+    destination: Option<std::sync::Arc<crate::destination::Destination>>,
+    source: Option<PassSource>,
+    name_hint: Option<String>,
+    joined: usize,
+    pending: usize,
+    // Once a job is closed, nothing new can join it, and whatever it has is
+    // dealt with as soon as the pending uploads have all arrived.
+    closed: bool,
+}
+
+struct Job {
+    state: std::sync::Mutex<JobState>,
+}
+
+pub struct Duplexer {
+    ctx: std::sync::Arc<crate::ctx::Ctx>,
+    gate_name: String,
+    drop_blank_backs: bool,
+    blank_threshold: f64,
+    timeout: std::time::Duration,
+    spool_directory: std::path::PathBuf,
+    pdf_mime_type: crate::mime_types::ConfigMimeTypeEnriched,
+    armed: std::sync::Mutex<Option<std::sync::Arc<Job>>>,
+}
+
+impl Duplexer {
+    pub fn new(
+        ctx: &std::sync::Arc<crate::ctx::Ctx>,
+        gate_name: &str,
+        cfg: &ConfigDuplexEnriched,
+    ) -> Result<Self, scan2blob::error::WuffError> {
+        let Some(pdf_mime_type) =
+            ctx.config.mime_types.get_by_extension("pdf")
+        else {
+            return Err(scan2blob::error::WuffError::from(
+                "duplex requires a mime type for pdf",
+            ));
+        };
+        Ok(Self {
+            ctx: std::sync::Arc::clone(ctx),
+            gate_name: gate_name.to_string(),
+            drop_blank_backs: cfg.drop_blank_backs,
+            blank_threshold: cfg.blank_threshold,
+            timeout: std::time::Duration::from_secs(cfg.timeout as u64),
+            spool_directory: cfg.spool_directory.clone(),
+            pdf_mime_type,
+            armed: std::sync::Mutex::new(None),
+        })
+    }
+
+    pub fn applies_to(&self, content_type: &str) -> bool {
+        scan2blob::pdf::InputKind::from_content_type(content_type).is_some()
+    }
+
+    // Starts over, with whatever had been received for a previous job (if
+    // anything) uploaded as it was.
+    pub fn arm(self: &std::sync::Arc<Self>) {
+        let job: std::sync::Arc<Job> = std::sync::Arc::new(Job {
+            state: std::sync::Mutex::new(JobState {
+                passes: [None, None],
+                destination: None,
+                source: None,
+                name_hint: None,
+                joined: 0,
+                pending: 0,
+                closed: false,
+            }),
+        });
+        let previous: Option<std::sync::Arc<Job>> = self
+            .armed
+            .lock()
+            .unwrap()
+            .replace(std::sync::Arc::clone(&job));
+        if let Some(previous) = previous {
+            self.close(&previous);
+        }
+        let async_spawner = self.ctx.base_ctx.get_async_spawner();
+        async_spawner.spawn(std::sync::Arc::clone(self).expire(job));
+    }
+
+    pub fn disarm(self: &std::sync::Arc<Self>) {
+        let previous: Option<std::sync::Arc<Job>> =
+            self.armed.lock().unwrap().take();
+        if let Some(previous) = previous {
+            self.close(&previous);
+        }
+    }
+
+    // How many more passes we're waiting to see start, or None if duplex
+    // isn't armed.
+    pub fn passes_expected(&self) -> Option<usize> {
+        let armed = self.armed.lock().unwrap();
+        armed
+            .as_ref()
+            .map(|job| 2 - job.state.lock().unwrap().joined)
+    }
+
+    // Returns None if duplex isn't armed, or if this can't be the back pass
+    // of the job that is, in which case the caller should handle the upload
+    // some other way.
+    pub fn try_write_file(
+        self: &std::sync::Arc<Self>,
+        gate: &super::Gate,
+        destination: &std::sync::Arc<crate::destination::Destination>,
+        mut upload_context: crate::upload_context::UploadContext,
+        suffix: String,
+        content_type: String,
+    ) -> Option<scan2blob::chunker::Writer> {
+        scan2blob::pdf::InputKind::from_content_type(&content_type)?;
+        let source: PassSource = PassSource::new(&upload_context, destination);
+        let (job, index): (std::sync::Arc<Job>, usize) = {
+            let mut armed = self.armed.lock().unwrap();
+            let job: std::sync::Arc<Job> =
+                std::sync::Arc::clone(armed.as_ref()?);
+            let mut state = job.state.lock().unwrap();
+            if state.joined == 1 && state.source.as_ref() != Some(&source) {
+                return None;
+            }
+            let index: usize = state.joined;
+            state.joined += 1;
+            state.pending += 1;
+            if index == 0 {
+                // Both passes go to wherever the front pass went.
+                state.destination = Some(std::sync::Arc::clone(destination));
+                state.source = Some(source);
+                state.name_hint = upload_context.name_hint.clone();
+            } else {
+                upload_context.name_hint = state.name_hint.clone();
+            }
+            if state.joined == 2 {
+                // Nothing else can join now, so there's no need to keep it
+                // around as the armed job. It'll get dealt with once both
+                // passes have arrived.
+                state.closed = true;
+                *armed = None;
+            }
+            drop(state);
+            (job, index)
+        };
+        // The front pass is what starts a new document. The back pass goes
+        // along with it, whatever the next name hint might be.
+        if index == 0 {
+            if let Some(ref name_hint) = upload_context.name_hint {
+                gate.consume_name_hint(name_hint);
+            }
+        }
+
+        let (writer, reader) = destination.new_chunker();
+        let async_spawner = self.ctx.base_ctx.get_async_spawner();
+        async_spawner.spawn(std::sync::Arc::clone(self).receive(
+            job,
+            index,
+            reader,
+            suffix,
+            content_type,
+            upload_context,
+        ));
+        Some(writer)
+    }
+
+    fn close(self: &std::sync::Arc<Self>, job: &std::sync::Arc<Job>) {
+        let mut state = job.state.lock().unwrap();
+        if state.closed {
+            return;
+        }
+        state.closed = true;
+        if state.pending == 0 {
+            self.spawn_finish(&mut state);
+        }
+    }
+
+    fn spawn_finish(self: &std::sync::Arc<Self>, state: &mut JobState) {
+        let [front, back] = std::mem::take(&mut state.passes);
+        let Some(destination) = state.destination.take() else {
+            return;
+        };
+        let async_spawner = self.ctx.base_ctx.get_async_spawner();
+        async_spawner.spawn(std::sync::Arc::clone(self).finish(
+            destination,
+            front,
+            back,
+        ));
+    }
+
+    async fn expire(self: std::sync::Arc<Self>, job: std::sync::Arc<Job>) {
+        tokio::time::sleep(self.timeout).await;
+        {
+            let mut armed = self.armed.lock().unwrap();
+            if let Some(ref current) = *armed {
+                if std::sync::Arc::ptr_eq(current, &job) {
+                    *armed = None;
+                }
+            }
+        }
+        if !job.state.lock().unwrap().closed {
+            self.ctx.log_info(format!(
+                "{}: gave up waiting for duplex passes",
+                self.gate_name
+            ));
+        }
+        self.close(&job);
+    }
+
+    async fn receive(
+        self: std::sync::Arc<Self>,
+        job: std::sync::Arc<Job>,
+        index: usize,
+        mut reader: scan2blob::chunker::Reader,
+        suffix: String,
+        content_type: String,
+        upload_context: crate::upload_context::UploadContext,
+    ) {
+        // try_write_file() already made sure this would work.
+        let kind: scan2blob::pdf::InputKind =
+            scan2blob::pdf::InputKind::from_content_type(&content_type)
+                .unwrap();
+        let result: Result<
+            crate::spool::SpoolFile,
+            scan2blob::error::WuffError,
+        > = match crate::spool::SpoolFile::receive(
+            &self.spool_directory,
+            &mut reader,
+        )
+        .await
+        {
+            Ok(spool_file) => match reader.finalize().await {
+                Ok(()) => Ok(spool_file),
+                Err(err) => Err(err),
+            },
+            Err(err) => {
+                reader.observe_error(err.clone());
+                Err(err)
+            }
+        };
+
+        let mut state = job.state.lock().unwrap();
+        state.pending -= 1;
+        match result {
+            Ok(spool_file) => {
+                state.passes[index] = Some(Pass {
+                    spool_file,
+                    kind,
+                    suffix,
+                    content_type,
+                    upload_context,
+                });
+            }
+            Err(err) => {
+                self.ctx.log_info(format!(
+                    "{}: duplex pass failed to arrive: {}",
+                    self.gate_name, err
+                ));
+            }
+        }
+        if state.closed && state.pending == 0 {
+            self.spawn_finish(&mut state);
+        }
+    }
+
+    async fn finish(
+        self: std::sync::Arc<Self>,
+        destination: std::sync::Arc<crate::destination::Destination>,
+        front: Option<Pass>,
+        back: Option<Pass>,
+    ) {
+        if let (Some(front), Some(back)) = (&front, &back) {
+            let front_input: (scan2blob::pdf::InputKind, std::path::PathBuf) =
+                (front.kind, front.spool_file.path().to_path_buf());
+            let back_input: (scan2blob::pdf::InputKind, std::path::PathBuf) =
+                (back.kind, back.spool_file.path().to_path_buf());
+            let blank_threshold: Option<f64> = if self.drop_blank_backs {
+                Some(self.blank_threshold)
+            } else {
+                None
+            };
+            let upload_context: crate::upload_context::UploadContext =
+                crate::upload_context::UploadContext::combine(&[
+                    front.upload_context.clone(),
+                    back.upload_context.clone(),
+                ])
+                .with_step(
+                    crate::upload_context::ProcessingStep::CollateDuplex,
+                );
+            match crate::destination::transform::upload_converted(
+                || {
+                    destination.write_file(
+                        upload_context,
+                        self.pdf_mime_type.suffix.clone(),
+                        self.pdf_mime_type.content_type.clone(),
+                    )
+                },
+                move |sink| {
+                    collate(front_input, back_input, blank_threshold, sink)
+                },
+            )
+            .await
+            {
+                Ok(crate::destination::transform::Conversion::Uploaded) => {
+                    return;
+                }
+                Ok(
+                    crate::destination::transform::Conversion::Unconvertible(
+                        err,
+                    ),
+                ) => {
+                    self.ctx.log_warn(format!(
+                        "{}: could not collate duplex passes for {}, uploading them separately: {}",
+                        self.gate_name, destination.name, err
+                    ));
+                }
+                Err(err) => {
+                    self.ctx.log_warn(format!(
+                        "{}: upload of collated duplex passes for {} failed, uploading them separately: {}",
+                        self.gate_name, destination.name, err
+                    ));
+                }
+            }
+        }
+
+        for pass in [front, back].into_iter().flatten() {
+            if let Err(err) = pass
+                .spool_file
+                .copy_to(destination.write_file(
+                    pass.upload_context,
+                    pass.suffix,
+                    pass.content_type,
+                ))
+                .await
+            {
+                self.ctx.log_info(format!(
+                    "{}: upload of duplex pass for {} failed: {}",
+                    self.gate_name, destination.name, err
+                ));
+            }
+        }
+    }
+}
+
+fn load(
+    input: (scan2blob::pdf::InputKind, std::path::PathBuf),
+) -> Result<lopdf::Document, scan2blob::error::WuffError> {
+    let (kind, path) = input;
+    let mut f: std::io::BufReader<std::fs::File> =
+        std::io::BufReader::new(std::fs::File::open(path)?);
+    scan2blob::pdf::load(kind, &mut f)
+}
+
+fn collate(
+    front: (scan2blob::pdf::InputKind, std::path::PathBuf),
+    back: (scan2blob::pdf::InputKind, std::path::PathBuf),
+    blank_threshold: Option<f64>,
+    mut sink: crate::destination::transform::ChannelSink,
+) -> Result<(), scan2blob::error::WuffError> {
+    // Both passes are in memory at once, so it's their total that counts.
+    scan2blob::pdf::check_in_memory_size(
+        std::fs::metadata(&front.1)?
+            .len()
+            .saturating_add(std::fs::metadata(&back.1)?.len()),
+    )?;
+    let front: lopdf::Document = load(front)?;
+    let back: lopdf::Document = load(back)?;
+    let num_sheets: u32 = scan2blob::pdf::num_pages(&front);
+    if scan2blob::pdf::num_pages(&back) != num_sheets {
+        return Err(scan2blob::error::WuffError::from(format!(
+            "front pass has {} pages but back pass has {}",
+            num_sheets,
+            scan2blob::pdf::num_pages(&back)
+        )));
+    }
+
+    let blank_backs: Vec<u32> = match blank_threshold {
+        Some(blank_threshold) => {
+            scan2blob::ink::blank_pages(&back, blank_threshold)
+        }
+        None => Vec::new(),
+    };
+    let mut pages: Vec<(usize, u32)> =
+        Vec::with_capacity(2 * num_sheets as usize);
+    for sheet in 1..=num_sheets {
+        pages.push((0, sheet));
+        let back_page_number: u32 = num_sheets + 1 - sheet;
+        if !blank_backs.contains(&back_page_number) {
+            pages.push((1, back_page_number));
+        }
+    }
+
+    let mut doc: lopdf::Document =
+        scan2blob::pdf::assemble(vec![front, back], &pages)?;
+    doc.save_to(&mut sink)?;
+    std::io::Write::flush(&mut sink)?;
+    Ok(())
+}
@@ -1,3 +1,9 @@
+pub mod audit;
+pub mod batch;
+pub mod category;
+pub mod duplex;
+pub mod recent_uploads;
+pub mod state_file;
 pub mod web;
 
 #[derive(serde::Deserialize)]
@@ -8,15 +14,60 @@ pub struct ConfigGate {
     pub timed_assertion_lifetime: u32,
     #[serde(default = "default_name_hint_lifetime")]
     pub name_hint_lifetime: u32,
+    pub name_hints: Option<ConfigNameHints>,
+    pub idle_timeout: Option<u32>,
+    pub state_file: Option<std::path::PathBuf>,
+    pub schedule: Option<ConfigSchedule>,
+    // How many uploads the web UI shows.
+    #[serde(default = "default_recent_uploads")]
+    pub recent_uploads: usize,
+    // How long after an upload's been committed it can still be discarded
+    // from the web UI, if at all.
+    pub discard_within: Option<u32>,
+    #[serde(default)]
+    pub categories: Vec<category::ConfigCategory>,
+    pub batching: Option<batch::ConfigBatching>,
+    pub duplex: Option<duplex::ConfigDuplex>,
     pub web_ui: Option<web::ConfigGateWeb>,
 }
 
+// How name hints are checked before they're allowed anywhere near a blob
+// name. By default, anything goes except for control characters, slashes
+// turn into dashes, and there's a limit on the length.
+#[derive(serde::Deserialize)]
+pub struct ConfigNameHints {
+    pub allowed_characters: Option<String>,
+    #[serde(default = "default_max_name_hint_length")]
+    pub max_length: usize,
+    #[serde(default)]
+    pub normalization: scan2blob::name_hint::Normalization,
+    #[serde(default)]
+    pub slashes: scan2blob::name_hint::Slashes,
+}
+
+// The gate opens by itself during any of the "open" windows, which look like
+// "Mon-Fri 08:00-18:00". If there's no time zone, it's the system's.
+#[derive(serde::Deserialize)]
+pub struct ConfigSchedule {
+    pub open: Vec<String>,
+    pub time_zone: Option<String>,
+}
+
 pub type ConfigGates = std::collections::HashMap<String, ConfigGate>;
 
 pub struct ConfigGateEnriched {
     pub default_open: bool,
     pub timed_assertion_lifetime: u32,
     pub name_hint_lifetime: u32,
+    pub name_hint_sanitizer: scan2blob::name_hint::Sanitizer,
+    pub idle_timeout: Option<u32>,
+    pub state_file: Option<std::path::PathBuf>,
+    pub schedule: Option<scan2blob::schedule::Schedule>,
+    pub recent_uploads: usize,
+    pub discard_within: Option<u32>,
+    pub categories: Vec<category::ConfigCategoryEnriched>,
+    pub batching: Option<batch::ConfigBatchingEnriched>,
+    pub duplex: Option<duplex::ConfigDuplexEnriched>,
     pub web_ui: Option<web::ConfigGateWebEnriched>,
 }
 
@@ -29,12 +80,95 @@ impl TryFrom<ConfigGate> for ConfigGateEnriched {
             default_open,
             timed_assertion_lifetime,
             name_hint_lifetime,
+            name_hints,
+            idle_timeout,
+            state_file,
+            schedule,
+            recent_uploads,
+            discard_within,
+            categories,
+            batching,
+            duplex,
             web_ui,
         } = config;
+        if idle_timeout == Some(0) {
+            return Err(scan2blob::error::WuffError::from(
+                "idle_timeout must be at least 1 second",
+            ));
+        }
+        // Going idle closes the gate explicitly, and then it stays closed
+        // until someone opens it, so it wouldn't be open by default at all.
+        if default_open && idle_timeout.is_some() {
+            return Err(scan2blob::error::WuffError::from(
+                "idle_timeout can't be used with default_open",
+            ));
+        }
+        if discard_within == Some(0) {
+            return Err(scan2blob::error::WuffError::from(
+                "discard_within must be at least 1 second",
+            ));
+        }
+        // Only the recent uploads can be discarded.
+        if discard_within.is_some() && recent_uploads == 0 {
+            return Err(scan2blob::error::WuffError::from(
+                "discard_within needs recent_uploads to be at least 1",
+            ));
+        }
+        let categories: Vec<category::ConfigCategoryEnriched> = categories
+            .into_iter()
+            .map(category::ConfigCategoryEnriched::try_from)
+            .collect::<Result<
+                Vec<category::ConfigCategoryEnriched>,
+                scan2blob::error::WuffError,
+            >>()?;
+        for (i, category) in categories.iter().enumerate() {
+            if categories[..i].iter().any(|c| c.name == category.name) {
+                return Err(scan2blob::error::WuffError::from(format!(
+                    "category {}: listed more than once",
+                    category.name
+                )));
+            }
+        }
+        let name_hints: ConfigNameHints =
+            name_hints.unwrap_or_else(|| ConfigNameHints {
+                allowed_characters: None,
+                max_length: default_max_name_hint_length(),
+                normalization: scan2blob::name_hint::Normalization::default(),
+                slashes: scan2blob::name_hint::Slashes::default(),
+            });
         Ok(Self {
             default_open,
             timed_assertion_lifetime,
             name_hint_lifetime,
+            name_hint_sanitizer: scan2blob::name_hint::Sanitizer::new(
+                name_hints.allowed_characters.as_deref(),
+                name_hints.max_length,
+                name_hints.normalization,
+                name_hints.slashes,
+            )?,
+            idle_timeout,
+            state_file,
+            schedule: if let Some(schedule) = schedule {
+                Some(scan2blob::schedule::Schedule::parse(
+                    &schedule.open,
+                    schedule.time_zone.as_deref(),
+                )?)
+            } else {
+                None
+            },
+            recent_uploads,
+            discard_within,
+            categories,
+            batching: if let Some(batching) = batching {
+                Some(batching.try_into()?)
+            } else {
+                None
+            },
+            duplex: if let Some(duplex) = duplex {
+                Some(duplex.try_into()?)
+            } else {
+                None
+            },
             web_ui: if let Some(web_ui) = web_ui {
                 Some(web_ui.try_into()?)
             } else {
@@ -61,6 +195,14 @@ fn default_name_hint_lifetime() -> u32 {
     600
 }
 
+fn default_max_name_hint_length() -> usize {
+    100
+}
+
+fn default_recent_uploads() -> usize {
+    10
+}
+
 // On a conceptual level, the way we want the system to behave is, it's as if
 // there can be two different kinds of gate-open assertions, one kind that
 // has an explicit expiration time, and a different kind that is associated
@@ -87,7 +229,10 @@ fn default_name_hint_lifetime() -> u32 {
 // 3. The currently active name-hint, if there is one, is stored along with
 //    its expiration time and an Option<u64> which may be used to indicate that
 //    this name-hint depends on a particular gate-open assertion that has a
-//    guard object.
+//    guard object. It's actually a queue of name-hints, one per upcoming
+//    document, and the one at the front is the one that's currently active.
+//    Once a document has been started with it, it's popped off and the next
+//    one becomes active, with a fresh expiration time.
 //
 // There's only one other enigma to explain, and that is the "sentinel". If
 // "sentinel" is true, we consider the gate to be open even if no other
@@ -98,22 +243,90 @@ fn default_name_hint_lifetime() -> u32 {
 // If we ever assert explicitly that the gate is closed, we set "sentinel" to
 // false. The next person to make a gate-open assertion after that, will cause
 // it to go back to whatever the default was.
+//
+// A timed gate-open assertion can also be limited to a certain number of
+// files, in which case the gate gets explicitly closed once that many have
+// come through. And a gate can have an idle timeout, in which case it gets
+// explicitly closed once it's been open for that long without any uploads
+// starting (or anyone asserting that it should be open). That's why a gate
+// that's open by default can't have one: it would only be open by default
+// until the first time it went idle. A gate doesn't count as idle while
+// there are any guarded assertions, since whoever's holding it open is
+// there, and is the one who decides when it closes, by letting go. An
+// explicit close does away with guarded assertions, too, though, and their
+// guards find out that it has.
+//
+// A gate can also have a schedule, in which case it's open during the
+// schedule's windows regardless of any assertions. Explicitly asserting that
+// the gate is closed during one of those windows keeps it closed until the
+// window ends (or until the next gate-open assertion, whichever is first).
+
+// What anyone who's subscribed to a gate gets told about. The state itself
+// isn't in here, since it's as easy for the subscriber to go and get it as it
+// would be for us to send it.
+#[derive(Clone, Debug)]
+pub enum GateEvent {
+    StateChanged,
+    // Including progress, while it's under way.
+    Upload(crate::upload_context::UploadEvent),
+}
+
+// If a subscriber falls this far behind, it misses some events, and finds out
+// that it did.
+const EVENTS_CAPACITY: usize = 64;
 
 pub struct GateAssertionGuard {
     gate: std::sync::Arc<Gate>,
     id: u64,
+    // Whoever's holding the gate open, if it goes in the audit log when
+    // they stop.
+    audit_as: Option<String>,
+}
+
+impl GateAssertionGuard {
+    // Finishes once the assertion is gone: either because the guard has been
+    // dropped, or because the gate has been explicitly closed, which does
+    // away with it even though the guard's still around. The guard doesn't
+    // have to stick around for this to find out.
+    pub fn released(&self) -> impl Future<Output = ()> + 'static {
+        let gate: std::sync::Arc<Gate> = std::sync::Arc::clone(&self.gate);
+        let id: u64 = self.id;
+        async move {
+            loop {
+                // This has to be made before the assertion's checked, so
+                // that it can't miss being told in between.
+                let notified = gate.assertions_released.notified();
+                if !gate.inner.read().unwrap().guarded_assertions.contains(&id)
+                {
+                    return;
+                }
+                notified.await;
+            }
+        }
+    }
 }
 
 impl Drop for GateAssertionGuard {
     fn drop(&mut self) {
-        let mut inner = self.gate.inner.write().unwrap();
-        let _ = inner.guarded_assertions.remove(&self.id);
-        if let Some(ref name_hint) = inner.name_hint {
-            if let Some(depends_on_guarded) = name_hint.depends_on_guarded {
-                if depends_on_guarded == self.id {
-                    inner.name_hint = None;
+        {
+            let mut inner = self.gate.inner.write().unwrap();
+            let _ = inner.guarded_assertions.remove(&self.id);
+            // The idle timeout starts over once it's been let go of.
+            inner.last_activity = std::time::Instant::now();
+            if let Some(ref name_hint) = inner.name_hint {
+                if let Some(depends_on_guarded) = name_hint.depends_on_guarded
+                {
+                    if depends_on_guarded == self.id {
+                        inner.name_hint = None;
+                    }
                 }
             }
+            self.gate.note_state_change(&inner);
+        }
+        self.gate.assertions_released.notify_waiters();
+        if let Some(ref audit_as) = self.audit_as {
+            self.gate
+                .audit(audit_as, String::from("stopped holding open"));
         }
     }
 }
@@ -121,7 +334,8 @@ impl Drop for GateAssertionGuard {
 struct GateNameHint {
     expires_at: std::time::Instant,
     depends_on_guarded: Option<u64>,
-    name_hint: String,
+    // Never empty. If there's nothing left in it, there's no name-hint.
+    name_hints: std::collections::VecDeque<String>,
 }
 
 pub struct GateInner {
@@ -129,7 +343,16 @@ pub struct GateInner {
     next_guarded_assertion_id: u64,
     guarded_assertions: std::collections::HashSet<u64>,
     expiring_assertion: Option<std::time::Instant>,
+    // These go along with the expiring assertion.
+    files_remaining: Option<u32>,
+    category: Option<String>,
     name_hint: Option<GateNameHint>,
+    schedule_closed_until: Option<jiff::Timestamp>,
+    // For signed links that are only good for so many uses, by their IDs.
+    link_uses: std::collections::HashMap<String, state_file::LinkUses>,
+    // This can be in the future, if the gate is closed and we know when it's
+    // going to open. The idle timeout only counts time the gate spends open.
+    last_activity: std::time::Instant,
 }
 
 pub struct Gate {
@@ -138,6 +361,21 @@ pub struct Gate {
     default_open: bool,
     timed_assertion_lifetime: std::time::Duration,
     name_hint_lifetime: std::time::Duration,
+    name_hint_sanitizer: scan2blob::name_hint::Sanitizer,
+    idle_timeout: Option<std::time::Duration>,
+    // Only if there's a state file.
+    state_snapshots: Option<tokio::sync::watch::Sender<state_file::Snapshot>>,
+    schedule: Option<scan2blob::schedule::Schedule>,
+    categories: Vec<category::Category>,
+    batcher: Option<std::sync::Arc<batch::Batcher>>,
+    duplexer: Option<std::sync::Arc<duplex::Duplexer>>,
+    events: tokio::sync::broadcast::Sender<GateEvent>,
+    recent_uploads: std::sync::Arc<recent_uploads::RecentUploads>,
+    // For discarding uploads, which could have gone to any of them.
+    destinations: crate::destination::Destinations,
+    audit_log: audit::AuditLog,
+    // Whenever any guarded assertions go away.
+    assertions_released: tokio::sync::Notify,
     inner: std::sync::RwLock<GateInner>,
 }
 
@@ -146,14 +384,73 @@ impl Gate {
         ctx: &std::sync::Arc<crate::ctx::Ctx>,
         name: &String,
         cfg: &ConfigGateEnriched,
+        destinations: &crate::destination::Destinations,
     ) -> Result<Self, scan2blob::error::WuffError> {
-        let inner: GateInner = GateInner {
+        let mut inner: GateInner = GateInner {
             sentinel: cfg.default_open,
             next_guarded_assertion_id: 0,
             guarded_assertions: std::collections::HashSet::new(),
             expiring_assertion: None,
+            files_remaining: None,
+            category: None,
             name_hint: None,
+            schedule_closed_until: None,
+            link_uses: std::collections::HashMap::new(),
+            last_activity: std::time::Instant::now(),
+        };
+        if let Some(ref state_file) = cfg.state_file {
+            match state_file::load(state_file) {
+                Ok(Some(restored)) => {
+                    inner.sentinel = restored.sentinel;
+                    inner.schedule_closed_until =
+                        restored.schedule_closed_until;
+                    inner.link_uses = restored.link_uses;
+                    inner.expiring_assertion = restored.expiring_assertion;
+                    inner.files_remaining = restored.files_remaining;
+                    // The category might not exist anymore.
+                    inner.category = restored.category.filter(|restored| {
+                        cfg.categories
+                            .iter()
+                            .any(|category| category.name == *restored)
+                    });
+                    inner.name_hint =
+                        restored.name_hint.map(|name_hint| GateNameHint {
+                            expires_at: name_hint.expires_at,
+                            depends_on_guarded: None,
+                            name_hints: name_hint.name_hints.into(),
+                        });
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    // Not worth refusing to start over. The gate just starts
+                    // out the way it would have if there were no state file.
+                    ctx.log_warn(format!(
+                        "{}: ignoring state file {}: {}",
+                        name,
+                        state_file.display(),
+                        err
+                    ));
+                }
+            }
+        }
+        let state_snapshots: Option<
+            tokio::sync::watch::Sender<state_file::Snapshot>,
+        > = if let Some(ref state_file) = cfg.state_file {
+            let (state_snapshots, receiver) =
+                tokio::sync::watch::channel(snapshot(&inner));
+            let async_spawner = ctx.base_ctx.get_async_spawner();
+            async_spawner.spawn(state_file::write_snapshots(
+                std::sync::Arc::clone(ctx),
+                name.clone(),
+                state_file.clone(),
+                receiver,
+            ));
+            Some(state_snapshots)
+        } else {
+            None
         };
+        let events: tokio::sync::broadcast::Sender<GateEvent> =
+            tokio::sync::broadcast::channel(EVENTS_CAPACITY).0;
         Ok(Self {
             ctx: std::sync::Arc::clone(ctx),
             name: name.clone(),
@@ -164,100 +461,506 @@ impl Gate {
             name_hint_lifetime: std::time::Duration::from_secs(
                 cfg.name_hint_lifetime as u64,
             ),
+            name_hint_sanitizer: cfg.name_hint_sanitizer.clone(),
+            idle_timeout: cfg.idle_timeout.map(|idle_timeout| {
+                std::time::Duration::from_secs(idle_timeout as u64)
+            }),
+            state_snapshots,
+            schedule: cfg.schedule.clone(),
+            categories:
+                cfg.categories
+                    .iter()
+                    .map(|category| {
+                        category::Category::new(category, destinations)
+                    })
+                    .collect::<Result<
+                        Vec<category::Category>,
+                        scan2blob::error::WuffError,
+                    >>()?,
+            batcher: if let Some(ref batching) = cfg.batching {
+                Some(std::sync::Arc::new(batch::Batcher::new(
+                    ctx, name, batching,
+                )?))
+            } else {
+                None
+            },
+            duplexer: if let Some(ref duplex) = cfg.duplex {
+                Some(std::sync::Arc::new(duplex::Duplexer::new(
+                    ctx, name, duplex,
+                )?))
+            } else {
+                None
+            },
+            recent_uploads: std::sync::Arc::new(
+                recent_uploads::RecentUploads::new(
+                    cfg.recent_uploads,
+                    cfg.discard_within.map(|discard_within| {
+                        std::time::Duration::from_secs(discard_within as u64)
+                    }),
+                    &events,
+                ),
+            ),
+            events,
+            destinations: destinations.clone(),
+            audit_log: audit::AuditLog::new(),
+            assertions_released: tokio::sync::Notify::new(),
             inner: std::sync::RwLock::new(inner),
         })
     }
 
     pub fn assert_gate_closed(&self) {
-        let mut inner = self.inner.write().unwrap();
+        self.close(&mut self.inner.write().unwrap());
+        self.after_closing();
+    }
+
+    // The part of closing the gate that has to happen under the lock.
+    fn close(&self, inner: &mut GateInner) {
         inner.sentinel = false;
         inner.guarded_assertions.clear();
         inner.expiring_assertion = None;
+        inner.files_remaining = None;
+        inner.category = None;
         inner.name_hint = None;
+        if let Some(ref schedule) = self.schedule {
+            let now: jiff::Timestamp = jiff::Timestamp::now();
+            if schedule.is_open(now) {
+                // If the window never ends, there's nothing to be done.
+                inner.schedule_closed_until = schedule
+                    .next_change(now)
+                    .map(|end_of_window| end_of_window.timestamp());
+            }
+        }
+        self.note_state_change(inner);
+    }
+
+    // And the part that has to happen after it, without it.
+    fn after_closing(&self) {
+        // Anyone who was holding the gate open isn't anymore.
+        self.assertions_released.notify_waiters();
+        // Whatever the scanner sent while the gate was open is as complete as
+        // it's going to get.
+        self.finish_batches();
+        if let Some(ref duplexer) = self.duplexer {
+            duplexer.disarm();
+        }
     }
 
-    pub fn assert_gate_open_timed(&self) {
+    pub fn finish_batches(&self) {
+        if let Some(ref batcher) = self.batcher {
+            batcher.finish_all();
+            let _ = self.events.send(GateEvent::StateChanged);
+        }
+    }
+
+    // The next upload, and the one after it from the same place, will be
+    // collated together as the front and back sides of the same stack of
+    // pages.
+    pub fn arm_duplex(&self) -> Result<(), scan2blob::error::WuffError> {
+        let Some(ref duplexer) = self.duplexer else {
+            return Err(scan2blob::error::WuffError::from(
+                "duplex is not enabled for this gate",
+            ));
+        };
+        duplexer.arm();
+        let _ = self.events.send(GateEvent::StateChanged);
+        Ok(())
+    }
+
+    // None if this gate doesn't do duplex at all. Otherwise, how many passes
+    // we're still waiting for (which is zero when duplex isn't armed).
+    pub fn duplex_passes_expected(&self) -> Option<usize> {
+        self.duplexer
+            .as_ref()
+            .map(|duplexer| duplexer.passes_expected().unwrap_or(0))
+    }
+
+    // None if this gate doesn't do batching at all.
+    pub fn num_held_files(&self) -> Option<usize> {
+        self.batcher
+            .as_ref()
+            .map(|batcher| batcher.num_held_files())
+    }
+
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<GateEvent> {
+        self.events.subscribe()
+    }
+
+    // Newest first.
+    pub fn recent_uploads(&self) -> Vec<crate::upload_context::UploadEvent> {
+        self.recent_uploads.get()
+    }
+
+    // Whoever did something to the gate, and what it was, goes in the audit
+    // log and in the logs.
+    pub fn audit(&self, username: &str, action: String) {
+        self.ctx
+            .log_info(format!("{}: {}: {}", self.name, username, action));
+        self.audit_log.record(username, action);
+    }
+
+    // Newest first.
+    pub fn audit_log(&self) -> Vec<audit::AuditEntry> {
+        self.audit_log.get()
+    }
+
+    // For undoing a mis-scan. Only something that was recently committed
+    // through this gate can be discarded, and only for a little while.
+    pub async fn discard(
+        &self,
+        blob_name: &str,
+    ) -> Result<(), scan2blob::error::WuffError> {
+        let destination: String =
+            self.recent_uploads.check_discardable(blob_name)?;
+        let Some(destination) = self.destinations.get(&destination) else {
+            return Err(scan2blob::error::WuffError::from(format!(
+                "{}: destination not found",
+                destination
+            )));
+        };
+        destination.delete_blob(blob_name).await?;
+        self.ctx.log_info(format!(
+            "{}: discarded {} from {}",
+            self.name, blob_name, destination.name
+        ));
+        self.recent_uploads.mark_discarded(blob_name);
+        Ok(())
+    }
+
+    // Called with the lock held, so that the state file (if there is one)
+    // always ends up reflecting the most recent change, although it only gets
+    // written out later, without it. Subscribers get told about it, too,
+    // although by the time they come and look, there might have been another
+    // one.
+    fn note_state_change(&self, inner: &GateInner) {
+        let _ = self.events.send(GateEvent::StateChanged);
+        if let Some(ref state_snapshots) = self.state_snapshots {
+            let _ = state_snapshots.send_replace(snapshot(inner));
+        }
+    }
+
+    // If there's a maximum number of files, the gate closes once that many
+    // have come through, even if there's time left. If there's a category, it
+    // has to be one of this gate's. If anything's wrong with any of the name
+    // hints, the gate is left the way it was. Without a lifetime, it stays open
+    // for the gate's timed_assertion_lifetime.
+    pub fn assert_gate_open_timed_with_name_hints(
+        &self,
+        name_hints: Vec<String>,
+        max_files: Option<u32>,
+        category: Option<String>,
+        lifetime: Option<std::time::Duration>,
+    ) -> Result<(), scan2blob::error::WuffError> {
+        let name_hints: Vec<String> = self.sanitize_name_hints(&name_hints)?;
+        if let Some(ref category) = category
+            && self.get_category(category).is_none()
+        {
+            return Err(scan2blob::error::WuffError::from(format!(
+                "{}: no such category",
+                category
+            )));
+        }
         let mut inner = self.inner.write().unwrap();
-        inner.sentinel = self.default_open;
-        inner.expiring_assertion =
-            Some(std::time::Instant::now() + self.timed_assertion_lifetime);
+        self.open_timed(&mut inner, name_hints, max_files, category, lifetime);
+        self.note_state_change(&inner);
+        Ok(())
     }
 
-    pub fn assert_gate_open_timed_with_name_hint(
+    // For a signed link, which opens the gate the same way as anything else
+    // that opens it for a while. If the link is only good for so many uses,
+    // this counts as one of them, but only if it does open the gate. Returns
+    // false, without opening it, if the link has been used up.
+    pub fn assert_gate_open_with_link(
         &self,
-        name_hint: Option<String>,
-    ) {
+        link: &scan2blob::signed_link::SignedLink,
+    ) -> Result<bool, scan2blob::error::WuffError> {
+        let name_hints: Vec<String> = self.sanitize_name_hints(
+            &link.name_hint.iter().cloned().collect::<Vec<String>>(),
+        )?;
         let mut inner = self.inner.write().unwrap();
+        inner
+            .link_uses
+            .retain(|_, link_uses| !link_uses.is_expired());
+        if let Some(uses) = link.uses {
+            let link_uses: &mut state_file::LinkUses = inner
+                .link_uses
+                .entry(link.id.clone())
+                .or_insert(state_file::LinkUses {
+                    used: 0,
+                    expires: link.expires,
+                });
+            if link_uses.used >= uses {
+                return Ok(false);
+            }
+            link_uses.used += 1;
+        }
+        self.open_timed(&mut inner, name_hints, None, None, None);
+        self.note_state_change(&inner);
+        Ok(true)
+    }
+
+    // Whether the gate's state survives a restart.
+    pub fn keeps_state(&self) -> bool {
+        self.state_snapshots.is_some()
+    }
+
+    // The name hints and category have already been checked.
+    fn open_timed(
+        &self,
+        inner: &mut GateInner,
+        name_hints: Vec<String>,
+        max_files: Option<u32>,
+        category: Option<String>,
+        lifetime: Option<std::time::Duration>,
+    ) {
         inner.sentinel = self.default_open;
+        inner.schedule_closed_until = None;
+        inner.last_activity = std::time::Instant::now();
         let now: std::time::Instant = std::time::Instant::now();
         let expiration: std::time::Instant =
-            now + self.timed_assertion_lifetime;
+            now + lifetime.unwrap_or(self.timed_assertion_lifetime);
         inner.expiring_assertion = Some(expiration);
-        inner.name_hint = if let Some(name_hint) = name_hint {
+        inner.files_remaining = max_files;
+        inner.category = category;
+        inner.name_hint = if !name_hints.is_empty() {
             let name_hint_expiration: std::time::Instant =
                 std::cmp::min(now + self.name_hint_lifetime, expiration);
             Some(GateNameHint {
                 expires_at: name_hint_expiration,
                 depends_on_guarded: None,
-                name_hint,
+                name_hints: name_hints.into(),
             })
         } else {
             None
         };
     }
 
-    pub fn assert_gate_open_guarded(
+    pub fn assert_gate_open_guarded_with_name_hints(
         self: &std::sync::Arc<Self>,
-    ) -> GateAssertionGuard {
+        name_hints: Vec<String>,
+    ) -> Result<GateAssertionGuard, scan2blob::error::WuffError> {
+        let name_hints: Vec<String> = self.sanitize_name_hints(&name_hints)?;
         let mut inner = self.inner.write().unwrap();
         inner.sentinel = self.default_open;
+        inner.schedule_closed_until = None;
+        inner.last_activity = std::time::Instant::now();
         let id: u64 = inner.next_guarded_assertion_id;
         inner.next_guarded_assertion_id += 1;
         assert!(inner.guarded_assertions.insert(id));
-
-        GateAssertionGuard {
-            gate: std::sync::Arc::clone(self),
-            id,
-        }
-    }
-
-    pub fn assert_gate_open_guarded_with_name_hint(
-        self: &std::sync::Arc<Self>,
-        name_hint: Option<String>,
-    ) -> GateAssertionGuard {
-        let mut inner = self.inner.write().unwrap();
-        inner.sentinel = self.default_open;
-        let id: u64 = inner.next_guarded_assertion_id;
-        inner.next_guarded_assertion_id += 1;
-        assert!(inner.guarded_assertions.insert(id));
-        inner.name_hint = if let Some(name_hint) = name_hint {
+        inner.name_hint = if !name_hints.is_empty() {
             let name_hint_expiration: std::time::Instant =
                 std::time::Instant::now() + self.name_hint_lifetime;
             Some(GateNameHint {
                 expires_at: name_hint_expiration,
                 depends_on_guarded: Some(id),
-                name_hint,
+                name_hints: name_hints.into(),
             })
         } else {
             None
         };
+        self.note_state_change(&inner);
 
-        GateAssertionGuard {
+        Ok(GateAssertionGuard {
             gate: std::sync::Arc::clone(self),
             id,
+            audit_as: None,
+        })
+    }
+
+    // For as long as somebody stays connected: the gate stays open until
+    // the guard goes away, and who it was goes in the audit log, both when
+    // they start holding it open and when they stop.
+    pub fn hold_open(
+        self: &std::sync::Arc<Self>,
+        username: &str,
+        name_hints: Vec<String>,
+    ) -> Result<GateAssertionGuard, scan2blob::error::WuffError> {
+        let mut action: String = String::from("holding open");
+        if !name_hints.is_empty() {
+            action.push_str(&format!(", name hints {:?}", name_hints));
+        }
+        let mut guard: GateAssertionGuard =
+            self.assert_gate_open_guarded_with_name_hints(name_hints)?;
+        self.audit(username, action);
+        guard.audit_as = Some(username.to_string());
+        Ok(guard)
+    }
+
+    pub fn sanitize_name_hints(
+        &self,
+        name_hints: &[String],
+    ) -> Result<Vec<String>, scan2blob::error::WuffError> {
+        name_hints
+            .iter()
+            .map(|name_hint| self.name_hint_sanitizer.sanitize(name_hint))
+            .collect()
+    }
+
+    // Called once a document has been started with the given name-hint, so
+    // that the next document gets the next one. If the name-hints have been
+    // changed in the meantime, it's left alone.
+    pub fn consume_name_hint(&self, consumed: &str) {
+        let mut inner = self.inner.write().unwrap();
+        let expiring_assertion: Option<std::time::Instant> =
+            inner.expiring_assertion;
+        let Some(ref mut name_hint) = inner.name_hint else {
+            return;
+        };
+        if name_hint.name_hints.front().map(String::as_str) != Some(consumed) {
+            return;
+        }
+        let _ = name_hint.name_hints.pop_front();
+        if name_hint.name_hints.is_empty() {
+            inner.name_hint = None;
+        } else {
+            // The next one gets as long as the first one did. It's the time
+            // in between documents that the lifetime is meant to cover, not
+            // the time it takes to get through all of them.
+            let mut expires_at: std::time::Instant =
+                std::time::Instant::now() + self.name_hint_lifetime;
+            if name_hint.depends_on_guarded.is_none() {
+                if let Some(expiring_assertion) = expiring_assertion {
+                    expires_at = std::cmp::min(expires_at, expiring_assertion);
+                }
+            }
+            name_hint.expires_at = expires_at;
+        }
+        self.note_state_change(&inner);
+    }
+
+    pub fn category_names(&self) -> Vec<String> {
+        self.categories
+            .iter()
+            .map(|category| category.name.clone())
+            .collect()
+    }
+
+    fn get_category(&self, name: &str) -> Option<&category::Category> {
+        self.categories
+            .iter()
+            .find(|category| category.name == name)
+    }
+
+    // The category that was picked when the gate was opened, if any, and if
+    // that's still in effect.
+    pub fn current_category(&self) -> Option<String> {
+        let inner = self.inner.read().unwrap();
+        if inner.expiring_assertion? <= std::time::Instant::now() {
+            return None;
+        }
+        inner.category.clone()
+    }
+
+    // How many more files the gate will let through before it closes, if
+    // there's a limit.
+    pub fn files_remaining(&self) -> Option<u32> {
+        let inner = self.inner.read().unwrap();
+        if inner.expiring_assertion? <= std::time::Instant::now() {
+            return None;
+        }
+        inner.files_remaining
+    }
+
+    // Called whenever an upload starts. Returns true if that used up the
+    // last of the files that the gate was opened for.
+    fn note_upload(&self) -> bool {
+        let mut inner = self.inner.write().unwrap();
+        let now: std::time::Instant = std::time::Instant::now();
+        inner.last_activity = now;
+        let Some(files_remaining) = inner.files_remaining else {
+            return false;
+        };
+        // Once the timed assertion has expired, the count that went with it
+        // doesn't mean anything anymore. If the gate's open now, it's for
+        // some other reason, which isn't limited to so many files.
+        if inner
+            .expiring_assertion
+            .is_none_or(|expiring_assertion| expiring_assertion <= now)
+        {
+            return false;
+        }
+        let files_remaining: u32 = files_remaining.saturating_sub(1);
+        inner.files_remaining = Some(files_remaining);
+        self.note_state_change(&inner);
+        files_remaining == 0
+    }
+
+    // Closes the gate whenever it's been open for the idle timeout without
+    // anything happening.
+    async fn watch_idle(
+        self: std::sync::Arc<Self>,
+        idle_timeout: std::time::Duration,
+    ) {
+        loop {
+            let deadline: std::time::Instant =
+                self.inner.read().unwrap().last_activity + idle_timeout;
+            tokio::time::sleep_until(deadline.into()).await;
+            let _ =
+                self.close_if_idle(idle_timeout, std::time::Instant::now());
+        }
+    }
+
+    // Deciding that the gate's been idle for long enough, and closing it,
+    // happen under the one lock, so that nobody can open it (or start
+    // holding it open) in between and have it closed on them straight away.
+    // Returns whether it did close it.
+    fn close_if_idle(
+        &self,
+        idle_timeout: std::time::Duration,
+        now: std::time::Instant,
+    ) -> bool {
+        {
+            let mut inner = self.inner.write().unwrap();
+            if inner.last_activity + idle_timeout > now {
+                // Something happened while we were asleep.
+                return false;
+            }
+            if !inner.guarded_assertions.is_empty() {
+                // Somebody's holding it open, which is as good as something
+                // happening.
+                inner.last_activity = now;
+                return false;
+            }
+            let (state, _next_change_time) = self.current_state_of(&inner);
+            if state.is_some() {
+                self.close(&mut inner);
+            }
+            // The gate's closed now, one way or another, so the clock starts
+            // over whenever it next opens. If we know when that's going to
+            // be, because of the schedule, then good. If we don't, then it'll
+            // be because someone opened it, which counts as activity anyway.
+            let (_state, time_until_open) = self.current_state_of(&inner);
+            inner.last_activity = now + time_until_open.unwrap_or_default();
+            if state.is_none() {
+                return false;
+            }
         }
+        self.ctx.log_info(format!(
+            "{}: closing, after {} seconds without any uploads",
+            self.name,
+            idle_timeout.as_secs()
+        ));
+        self.after_closing();
+        true
     }
 
     pub fn get_current_state(&self) -> Option<Option<String>> {
         let (state, _next_change_time) = self.get_current_state_extended();
-        state
+        state.map(|name_hints| name_hints.into_iter().next())
     }
 
+    // The name-hints are the whole queue, starting with the one that's
+    // currently active.
     pub fn get_current_state_extended(
         &self,
-    ) -> (Option<Option<String>>, Option<std::time::Duration>) {
+    ) -> (Option<Vec<String>>, Option<std::time::Duration>) {
+        self.current_state_of(&self.inner.read().unwrap())
+    }
+
+    fn current_state_of(
+        &self,
+        inner: &GateInner,
+    ) -> (Option<Vec<String>>, Option<std::time::Duration>) {
         let mut time_until_gate_closes: Option<std::time::Duration> = None;
-        let inner = self.inner.read().unwrap();
         let now: std::time::Instant = std::time::Instant::now();
         let mut gate_open: bool =
             inner.sentinel || !inner.guarded_assertions.is_empty();
@@ -270,10 +973,44 @@ impl Gate {
                     time_until_gate_closes = time_left;
                 }
             }
+            if let Some((scheduled_open, time_until_schedule_changes)) =
+                self.scheduled_state(inner)
+            {
+                if scheduled_open {
+                    // Whichever keeps the gate open for longer wins. If the
+                    // schedule says it's open forever, then it's open
+                    // forever.
+                    time_until_gate_closes = match (
+                        gate_open,
+                        time_until_gate_closes,
+                        time_until_schedule_changes,
+                    ) {
+                        (true, Some(a), Some(b)) => Some(std::cmp::max(a, b)),
+                        (_, _, b) => b,
+                    };
+                    gate_open = true;
+                } else if !gate_open {
+                    return (None, time_until_schedule_changes);
+                }
+            }
         }
         if !gate_open {
             return (None, None);
         }
+        if let Some(idle_timeout) = self.idle_timeout
+            && inner.guarded_assertions.is_empty()
+        {
+            let time_until_idle: std::time::Duration = (inner.last_activity
+                + idle_timeout)
+                .saturating_duration_since(now);
+            time_until_gate_closes = Some(
+                if let Some(time_until_gate_closes) = time_until_gate_closes {
+                    std::cmp::min(time_until_gate_closes, time_until_idle)
+                } else {
+                    time_until_idle
+                },
+            );
+        }
         if let Some(ref name_hint) = inner.name_hint {
             if let Some(mut time_until_something_happens) =
                 name_hint.expires_at.checked_duration_since(now)
@@ -285,34 +1022,168 @@ impl Gate {
                     );
                 }
                 return (
-                    Some(Some(name_hint.name_hint.clone())),
+                    Some(name_hint.name_hints.iter().cloned().collect()),
                     Some(time_until_something_happens),
                 );
             }
         }
-        (Some(None), time_until_gate_closes)
+        (Some(Vec::new()), time_until_gate_closes)
     }
 
-    pub fn try_write_file(
+    // Whether the schedule (if there is one) says the gate should be open
+    // right now, taking into account that it might have been explicitly
+    // closed, and how long until that changes.
+    fn scheduled_state(
         &self,
-        orig_filename: &str,
+        inner: &GateInner,
+    ) -> Option<(bool, Option<std::time::Duration>)> {
+        let schedule: &scan2blob::schedule::Schedule =
+            self.schedule.as_ref()?;
+        let now: jiff::Timestamp = jiff::Timestamp::now();
+        let until = |t: jiff::Timestamp| -> std::time::Duration {
+            std::time::Duration::try_from(t.duration_since(now))
+                .unwrap_or_default()
+        };
+        if let Some(closed_until) = inner.schedule_closed_until {
+            if closed_until > now {
+                let next_open: Option<jiff::Timestamp> = if schedule
+                    .is_open(closed_until)
+                {
+                    Some(closed_until)
+                } else {
+                    schedule.next_change(closed_until).map(|t| t.timestamp())
+                };
+                return Some((false, next_open.map(until)));
+            }
+        }
+        Some((
+            schedule.is_open(now),
+            schedule.next_change(now).map(|t| until(t.timestamp())),
+        ))
+    }
+
+    // The next time the schedule (if there is one) opens or closes the gate,
+    // and which of the two it'll be.
+    pub fn next_scheduled_change(&self) -> Option<(bool, jiff::Zoned)> {
+        let schedule: &scan2blob::schedule::Schedule =
+            self.schedule.as_ref()?;
+        let now: jiff::Timestamp = jiff::Timestamp::now();
+        let next_change: jiff::Zoned = schedule.next_change(now)?;
+        Some((!schedule.is_open(now), next_change))
+    }
+
+    pub fn try_write_file(
+        self: &std::sync::Arc<Self>,
+        mut upload_context: crate::upload_context::UploadContext,
         destination: &std::sync::Arc<crate::destination::Destination>,
     ) -> Option<scan2blob::chunker::Writer> {
         let Some(name_hint) = self.get_current_state() else {
             return None;
         };
-        let Some(mime_type) = self.ctx.config.mime_types.get(orig_filename)
+        let Some(mime_type) = self
+            .ctx
+            .config
+            .mime_types
+            .get(&upload_context.orig_filenames[0])
         else {
             return None;
         };
-        Some(destination.write_file(
-            name_hint,
+        upload_context.gate = self.name.clone();
+        upload_context.name_hint = name_hint.clone();
+        upload_context.recent_uploads =
+            Some(std::sync::Arc::clone(&self.recent_uploads));
+        let mut destination: &std::sync::Arc<crate::destination::Destination> =
+            destination;
+        if let Some(category) = self.current_category()
+            && let Some(category) = self.get_category(&category)
+        {
+            if let Some(ref category_destination) = category.destination {
+                destination = category_destination;
+            }
+            upload_context.category = Some(category.name.clone());
+            upload_context.sub_prefix = category.prefix.clone();
+            upload_context.template = category.template.clone();
+        }
+        let writer: scan2blob::chunker::Writer =
+            self.route_file(destination, upload_context, mime_type, name_hint);
+        // The file that used up the last of them still gets in. It's only
+        // once it's on its way that the gate closes.
+        if self.note_upload() {
+            self.ctx.log_info(format!(
+                "{}: closing, since it was only open for so many files",
+                self.name
+            ));
+            self.assert_gate_closed();
+        }
+        Some(writer)
+    }
+
+    fn route_file(
+        self: &std::sync::Arc<Self>,
+        destination: &std::sync::Arc<crate::destination::Destination>,
+        upload_context: crate::upload_context::UploadContext,
+        mime_type: crate::mime_types::ConfigMimeTypeEnriched,
+        name_hint: Option<String>,
+    ) -> scan2blob::chunker::Writer {
+        // Duplex jobs and batches take care of consuming the name-hint
+        // themselves, since only they know when a new document starts.
+        if let Some(ref duplexer) = self.duplexer
+            && duplexer.applies_to(&mime_type.content_type)
+            && let Some(writer) = duplexer.try_write_file(
+                self,
+                destination,
+                upload_context.clone(),
+                mime_type.suffix.clone(),
+                mime_type.content_type.clone(),
+            )
+        {
+            return writer;
+        }
+        if let Some(ref batcher) = self.batcher
+            && batcher.applies_to(&mime_type.content_type)
+        {
+            return batcher.write_file(
+                self,
+                destination,
+                upload_context,
+                mime_type.suffix,
+                mime_type.content_type,
+            );
+        }
+        let writer: scan2blob::chunker::Writer = destination.write_file(
+            upload_context,
             mime_type.suffix,
             mime_type.content_type,
-        ))
+        );
+        if let Some(name_hint) = name_hint {
+            self.consume_name_hint(&name_hint);
+        }
+        writer
     }
 }
 
+// Only the parts of the state that make sense to keep go in the state file.
+fn snapshot(inner: &GateInner) -> state_file::Snapshot {
+    let name_hint: Option<(
+        std::time::Instant,
+        &std::collections::VecDeque<String>,
+    )> = match inner.name_hint {
+        Some(ref name_hint) if name_hint.depends_on_guarded.is_none() => {
+            Some((name_hint.expires_at, &name_hint.name_hints))
+        }
+        _ => None,
+    };
+    state_file::Snapshot::new(
+        inner.sentinel,
+        inner.schedule_closed_until,
+        inner.expiring_assertion,
+        inner.files_remaining,
+        inner.category.as_deref(),
+        name_hint,
+        &inner.link_uses,
+    )
+}
+
 pub struct Gates {
     gates: std::collections::HashMap<String, std::sync::Arc<Gate>>,
 }
@@ -320,22 +1191,328 @@ pub struct Gates {
 impl Gates {
     pub fn new(
         ctx: &std::sync::Arc<crate::ctx::Ctx>,
+        destinations: &crate::destination::Destinations,
     ) -> Result<Self, scan2blob::error::WuffError> {
         let mut gates: std::collections::HashMap<
             String,
             std::sync::Arc<Gate>,
         > = std::collections::HashMap::new();
         for (gate_name, gate_cfg) in &ctx.config.gates {
-            let gate: Gate = Gate::new(ctx, gate_name, gate_cfg)?;
-            assert!(
-                gates
-                    .insert(gate_name.clone(), std::sync::Arc::new(gate))
-                    .is_none()
-            );
+            let gate: std::sync::Arc<Gate> = std::sync::Arc::new(Gate::new(
+                ctx,
+                gate_name,
+                gate_cfg,
+                destinations,
+            )?);
+            if let Some(idle_timeout) = gate.idle_timeout {
+                let async_spawner = ctx.base_ctx.get_async_spawner();
+                async_spawner.spawn(
+                    std::sync::Arc::clone(&gate).watch_idle(idle_timeout),
+                );
+            }
+            assert!(gates.insert(gate_name.clone(), gate).is_none());
         }
         Ok(Self { gates })
     }
     pub fn get(&self, name: &str) -> Option<std::sync::Arc<Gate>> {
         self.gates.get(name).cloned()
     }
+
+    // In order of name.
+    pub fn all(&self) -> Vec<std::sync::Arc<Gate>> {
+        let mut gates: Vec<std::sync::Arc<Gate>> =
+            self.gates.values().cloned().collect();
+        gates.sort_by(|a, b| a.name.cmp(&b.name));
+        gates
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+
+    // A gate on its own, with a destination called "test" that nothing
+    // actually gets uploaded to, which is all that the gate's state needs.
+    pub(crate) fn new_gate(cfg: serde_json::Value) -> std::sync::Arc<Gate> {
+        let cmdline_matches: clap::ArgMatches =
+            crate::ctx::make_cmdline_parser()
+                .try_get_matches_from(["scan2blob", "-f"])
+                .unwrap();
+        let logger: std::sync::Arc<crate::ctx::Logger> =
+            std::sync::Arc::new(crate::ctx::Logger::new(&cmdline_matches));
+        let config: crate::ctx::Config =
+            serde_json::from_value(serde_json::json!({
+                "listeners": [],
+                "gates": {"test": cfg},
+                "destinations": {
+                    "test": {
+                        "storage_account": "test",
+                        "container": "test",
+                        "sas": "sv=test&sig=test",
+                        "prefix": "",
+                    },
+                },
+            }))
+            .unwrap();
+        let ctx: std::sync::Arc<crate::ctx::Ctx> = std::sync::Arc::new(
+            crate::ctx::Ctx::new(&logger, config.try_into().unwrap()),
+        );
+        let destinations: crate::destination::Destinations =
+            crate::destination::Destinations::new(&ctx).unwrap();
+        std::sync::Arc::new(
+            Gate::new(
+                &ctx,
+                &String::from("test"),
+                &ctx.config.gates["test"],
+                &destinations,
+            )
+            .unwrap(),
+        )
+    }
+
+    // For building other things that go along with a gate made by
+    // new_gate().
+    pub(crate) fn ctx(gate: &Gate) -> std::sync::Arc<crate::ctx::Ctx> {
+        std::sync::Arc::clone(&gate.ctx)
+    }
+
+    // As if the gate's timed assertion had run out just now.
+    fn expire(gate: &Gate) {
+        gate.inner.write().unwrap().expiring_assertion =
+            Some(std::time::Instant::now());
+    }
+
+    #[test]
+    fn open_for_so_many_files() {
+        let gate: std::sync::Arc<Gate> = new_gate(serde_json::json!({}));
+        gate.assert_gate_open_timed_with_name_hints(
+            Vec::new(),
+            Some(2),
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(gate.files_remaining(), Some(2));
+        assert!(!gate.note_upload());
+        assert_eq!(gate.files_remaining(), Some(1));
+        assert!(gate.note_upload());
+        assert_eq!(gate.files_remaining(), Some(0));
+    }
+
+    #[test]
+    fn expired_file_count_is_left_alone() {
+        let gate: std::sync::Arc<Gate> = new_gate(serde_json::json!({}));
+        gate.assert_gate_open_timed_with_name_hints(
+            Vec::new(),
+            Some(1),
+            None,
+            None,
+        )
+        .unwrap();
+        expire(&gate);
+        assert_eq!(gate.files_remaining(), None);
+
+        // Whoever's holding it open now isn't limited to one file.
+        let _guard: GateAssertionGuard = gate
+            .assert_gate_open_guarded_with_name_hints(Vec::new())
+            .unwrap();
+        assert!(!gate.note_upload());
+        assert!(!gate.note_upload());
+        assert!(gate.get_current_state().is_some());
+    }
+
+    #[test]
+    fn category_goes_with_timed_assertion() {
+        let gate: std::sync::Arc<Gate> = new_gate(serde_json::json!({
+            "categories": [{"name": "taxes", "prefix": "taxes/"}],
+        }));
+        assert!(
+            gate.assert_gate_open_timed_with_name_hints(
+                Vec::new(),
+                None,
+                Some(String::from("receipts")),
+                None,
+            )
+            .is_err()
+        );
+        gate.assert_gate_open_timed_with_name_hints(
+            Vec::new(),
+            Some(3),
+            Some(String::from("taxes")),
+            None,
+        )
+        .unwrap();
+        assert_eq!(gate.current_category(), Some(String::from("taxes")));
+        expire(&gate);
+        assert_eq!(gate.current_category(), None);
+        assert_eq!(gate.files_remaining(), None);
+
+        gate.assert_gate_open_timed_with_name_hints(
+            Vec::new(),
+            None,
+            Some(String::from("taxes")),
+            None,
+        )
+        .unwrap();
+        gate.assert_gate_closed();
+        assert_eq!(gate.current_category(), None);
+    }
+
+    #[test]
+    fn guard_released_by_drop() {
+        let gate: std::sync::Arc<Gate> = new_gate(serde_json::json!({}));
+        let guard: GateAssertionGuard = gate
+            .assert_gate_open_guarded_with_name_hints(Vec::new())
+            .unwrap();
+        let mut released = std::pin::pin!(guard.released());
+        assert!(futures::FutureExt::now_or_never(&mut released).is_none());
+        assert!(gate.get_current_state().is_some());
+        drop(guard);
+        assert!(futures::FutureExt::now_or_never(&mut released).is_some());
+        assert!(gate.get_current_state().is_none());
+    }
+
+    #[test]
+    fn guard_released_by_close() {
+        let gate: std::sync::Arc<Gate> = new_gate(serde_json::json!({}));
+        let guard: GateAssertionGuard = gate
+            .assert_gate_open_guarded_with_name_hints(Vec::new())
+            .unwrap();
+        let other_guard: GateAssertionGuard = gate
+            .assert_gate_open_guarded_with_name_hints(Vec::new())
+            .unwrap();
+        let mut released = std::pin::pin!(guard.released());
+        assert!(futures::FutureExt::now_or_never(&mut released).is_none());
+
+        // One of them letting go doesn't release the other.
+        drop(other_guard);
+        assert!(futures::FutureExt::now_or_never(&mut released).is_none());
+        assert!(gate.get_current_state().is_some());
+
+        gate.assert_gate_closed();
+        assert!(futures::FutureExt::now_or_never(&mut released).is_some());
+        assert!(gate.get_current_state().is_none());
+        // It's already been released, and dropping it now changes nothing.
+        assert!(futures::FutureExt::now_or_never(guard.released()).is_some());
+        drop(guard);
+        assert!(gate.get_current_state().is_none());
+    }
+
+    #[test]
+    fn name_hint_queue() {
+        let gate: std::sync::Arc<Gate> = new_gate(serde_json::json!({}));
+        gate.assert_gate_open_timed_with_name_hints(
+            vec![String::from("one"), String::from("two")],
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(gate.get_current_state(), Some(Some(String::from("one"))));
+
+        // Only the one at the front can be consumed.
+        gate.consume_name_hint("two");
+        assert_eq!(gate.get_current_state(), Some(Some(String::from("one"))));
+
+        gate.consume_name_hint("one");
+        let (state, _next_change_time) = gate.get_current_state_extended();
+        assert_eq!(state, Some(vec![String::from("two")]));
+
+        gate.consume_name_hint("two");
+        assert_eq!(gate.get_current_state(), Some(None));
+    }
+
+    #[test]
+    fn name_hints_go_with_their_assertion() {
+        let gate: std::sync::Arc<Gate> = new_gate(serde_json::json!({}));
+        let guard: GateAssertionGuard = gate
+            .assert_gate_open_guarded_with_name_hints(vec![
+                String::from("one"),
+                String::from("two"),
+            ])
+            .unwrap();
+        gate.consume_name_hint("one");
+        assert_eq!(gate.get_current_state(), Some(Some(String::from("two"))));
+        drop(guard);
+        assert_eq!(gate.get_current_state(), None);
+
+        // A timed assertion's name hints don't outlast it, either.
+        gate.assert_gate_open_timed_with_name_hints(
+            vec![String::from("three"), String::from("four")],
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        expire(&gate);
+        assert_eq!(gate.get_current_state(), None);
+        let (_state, next_change_time) = gate.get_current_state_extended();
+        assert_eq!(next_change_time, None);
+    }
+
+    #[test]
+    fn name_hints_are_sanitized() {
+        let gate: std::sync::Arc<Gate> = new_gate(serde_json::json!({}));
+        assert!(
+            gate.assert_gate_open_timed_with_name_hints(
+                vec![String::from("ok"), String::from("not\nok")],
+                None,
+                None,
+                None,
+            )
+            .is_err()
+        );
+        // The gate's left the way it was.
+        assert_eq!(gate.get_current_state(), None);
+    }
+
+    #[test]
+    fn idle_timeout() {
+        let gate: std::sync::Arc<Gate> =
+            new_gate(serde_json::json!({"idle_timeout": 60}));
+        let idle_timeout: std::time::Duration =
+            std::time::Duration::from_secs(60);
+        gate.assert_gate_open_timed_with_name_hints(
+            Vec::new(),
+            None,
+            None,
+            Some(std::time::Duration::from_secs(3600)),
+        )
+        .unwrap();
+        let (_state, time_until_closed) = gate.get_current_state_extended();
+        assert!(time_until_closed.unwrap() <= idle_timeout);
+
+        // Not yet.
+        let now: std::time::Instant = std::time::Instant::now();
+        assert!(!gate.close_if_idle(idle_timeout, now));
+        assert!(gate.get_current_state().is_some());
+
+        let later: std::time::Instant = now + 2 * idle_timeout;
+        assert!(gate.close_if_idle(idle_timeout, later));
+        assert!(gate.get_current_state().is_none());
+    }
+
+    #[test]
+    fn held_gate_is_never_idle() {
+        let gate: std::sync::Arc<Gate> =
+            new_gate(serde_json::json!({"idle_timeout": 60}));
+        let idle_timeout: std::time::Duration =
+            std::time::Duration::from_secs(60);
+        let guard: GateAssertionGuard = gate
+            .assert_gate_open_guarded_with_name_hints(Vec::new())
+            .unwrap();
+        let (_state, time_until_closed) = gate.get_current_state_extended();
+        assert_eq!(time_until_closed, None);
+
+        let later: std::time::Instant =
+            std::time::Instant::now() + 2 * idle_timeout;
+        assert!(!gate.close_if_idle(idle_timeout, later));
+        assert!(gate.get_current_state().is_some());
+
+        // Once it's let go of, nothing's keeping it open, so there's nothing
+        // left for the idle timeout to do.
+        drop(guard);
+        assert!(gate.get_current_state().is_none());
+        assert!(!gate.close_if_idle(idle_timeout, later));
+    }
 }
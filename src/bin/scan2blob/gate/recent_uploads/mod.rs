@@ -0,0 +1,120 @@
+// The last so many blobs that were uploaded through a gate, and how each of
+// them is getting on, for the web UI to show. Everything that gets reported
+// here also goes out to whoever's subscribed to the gate.
+//
+// If the gate lets uploads be discarded, this is also where we find out
+// whether one can be: it has to be one of these, and it has to have been
+// committed recently enough.
+
+#[derive(Debug)]
+pub struct RecentUploads {
+    max_uploads: usize,
+    discard_within: Option<std::time::Duration>,
+    // Oldest first. Each one is whatever was most recently reported about it.
+    uploads: std::sync::Mutex<
+        std::collections::VecDeque<crate::upload_context::UploadEvent>,
+    >,
+    events: tokio::sync::broadcast::Sender<super::GateEvent>,
+}
+
+impl RecentUploads {
+    pub fn new(
+        max_uploads: usize,
+        discard_within: Option<std::time::Duration>,
+        events: &tokio::sync::broadcast::Sender<super::GateEvent>,
+    ) -> Self {
+        Self {
+            max_uploads,
+            discard_within,
+            uploads: std::sync::Mutex::new(std::collections::VecDeque::new()),
+            events: events.clone(),
+        }
+    }
+
+    pub fn report(&self, mut event: crate::upload_context::UploadEvent) {
+        if let crate::upload_context::UploadStage::Committed = event.stage {
+            event.discardable_until = self.discard_within.map(|d| {
+                (std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    + d)
+                    .as_secs()
+            });
+        }
+        {
+            let mut uploads = self.uploads.lock().unwrap();
+            if let Some(upload) = uploads
+                .iter_mut()
+                .rev()
+                .find(|upload| upload.blob_name == event.blob_name)
+            {
+                *upload = event.clone();
+            } else if let crate::upload_context::UploadStage::Started =
+                event.stage
+            {
+                // Anything else is about an upload that's already fallen off
+                // the end.
+                uploads.push_back(event.clone());
+                while uploads.len() > self.max_uploads {
+                    let _ = uploads.pop_front();
+                }
+            }
+        }
+        // It's fine if nobody's listening.
+        let _ = self.events.send(super::GateEvent::Upload(event));
+    }
+
+    // Newest first.
+    pub fn get(&self) -> Vec<crate::upload_context::UploadEvent> {
+        self.uploads.lock().unwrap().iter().rev().cloned().collect()
+    }
+
+    // The name of the destination that the blob is in, if it's all right to
+    // discard it.
+    pub fn check_discardable(
+        &self,
+        blob_name: &str,
+    ) -> Result<String, scan2blob::error::WuffError> {
+        let now: u64 = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let uploads = self.uploads.lock().unwrap();
+        let Some(upload) = uploads
+            .iter()
+            .rev()
+            .find(|upload| upload.blob_name == blob_name)
+        else {
+            return Err(scan2blob::error::WuffError::from(format!(
+                "{}: not a recent upload",
+                blob_name
+            )));
+        };
+        match upload.discardable_until {
+            Some(discardable_until) if discardable_until > now => {
+                Ok(upload.destination.clone())
+            }
+            _ => Err(scan2blob::error::WuffError::from(format!(
+                "{}: can't be discarded (anymore)",
+                blob_name
+            ))),
+        }
+    }
+
+    pub fn mark_discarded(&self, blob_name: &str) {
+        let event: crate::upload_context::UploadEvent = {
+            let mut uploads = self.uploads.lock().unwrap();
+            let Some(upload) = uploads
+                .iter_mut()
+                .rev()
+                .find(|upload| upload.blob_name == blob_name)
+            else {
+                return;
+            };
+            upload.stage = crate::upload_context::UploadStage::Discarded;
+            upload.discardable_until = None;
+            upload.clone()
+        };
+        let _ = self.events.send(super::GateEvent::Upload(event));
+    }
+}
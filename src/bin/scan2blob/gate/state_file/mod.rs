@@ -0,0 +1,339 @@
+// A gate's state can optionally be kept in a file, so that it survives the
+// daemon being restarted. Only the parts of the state that make sense on
+// their own are kept: whether the gate has been explicitly closed (and, if
+// that was during one of its schedule's windows, until when), the timed
+// assertion (along with how many files it's good for, if that's limited, and
+// the category, if one was picked), the name hints if they go along with
+// the timed assertion, and how many times each signed link that's only good
+// for so many uses has been used.
+// Guarded assertions (and any name hint that depends on one) belong to
+// whoever holds the guard, and don't outlive the process.
+//
+// Expiration times are stored as wall-clock times, in milliseconds since the
+// epoch, since an Instant means nothing to a different process. So is the
+// time until which the schedule is overridden, for the sake of consistency.
+//
+// The gate takes a snapshot of its state with its lock held, which doesn't
+// involve the disk, and a task of its own writes the snapshots out. If the
+// state changes again while a snapshot is being written, it's only the latest
+// one that gets written next, so the file always ends up reflecting the most
+// recent change.
+
+// Once the link has expired, there's no need to remember it anymore.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct LinkUses {
+    pub used: u32,
+    // Seconds since the epoch, the same as in the link.
+    pub expires: Option<u64>,
+}
+
+impl LinkUses {
+    pub fn is_expired(&self) -> bool {
+        self.expires.is_some_and(|expires| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+                >= expires
+        })
+    }
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+struct PersistedNameHint {
+    expires_at: u64,
+    name_hints: Vec<String>,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+struct PersistedGateState {
+    sentinel: bool,
+    #[serde(default)]
+    schedule_closed_until: Option<u64>,
+    expiring_assertion: Option<u64>,
+    #[serde(default)]
+    files_remaining: Option<u32>,
+    #[serde(default)]
+    category: Option<String>,
+    name_hint: Option<PersistedNameHint>,
+    // By the link's ID.
+    #[serde(default)]
+    link_uses: std::collections::HashMap<String, LinkUses>,
+}
+
+pub struct RestoredNameHint {
+    pub expires_at: std::time::Instant,
+    pub name_hints: Vec<String>,
+}
+
+pub struct RestoredGateState {
+    pub sentinel: bool,
+    pub schedule_closed_until: Option<jiff::Timestamp>,
+    pub expiring_assertion: Option<std::time::Instant>,
+    pub files_remaining: Option<u32>,
+    pub category: Option<String>,
+    pub name_hint: Option<RestoredNameHint>,
+    pub link_uses: std::collections::HashMap<String, LinkUses>,
+}
+
+fn to_wall_clock(t: std::time::Instant) -> u64 {
+    let wall_clock: std::time::SystemTime = std::time::SystemTime::now()
+        + t.saturating_duration_since(std::time::Instant::now());
+    wall_clock
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+// None if it's already in the past.
+fn from_wall_clock(t: u64) -> Option<std::time::Instant> {
+    let wall_clock: std::time::SystemTime =
+        std::time::UNIX_EPOCH + std::time::Duration::from_millis(t);
+    let time_left: std::time::Duration = wall_clock
+        .duration_since(std::time::SystemTime::now())
+        .ok()?;
+    Some(std::time::Instant::now() + time_left)
+}
+
+#[derive(Clone)]
+pub struct Snapshot(PersistedGateState);
+
+impl Snapshot {
+    pub fn new(
+        sentinel: bool,
+        schedule_closed_until: Option<jiff::Timestamp>,
+        expiring_assertion: Option<std::time::Instant>,
+        files_remaining: Option<u32>,
+        category: Option<&str>,
+        name_hint: Option<(
+            std::time::Instant,
+            &std::collections::VecDeque<String>,
+        )>,
+        link_uses: &std::collections::HashMap<String, LinkUses>,
+    ) -> Self {
+        Self(PersistedGateState {
+            sentinel,
+            schedule_closed_until: schedule_closed_until.map(
+                |schedule_closed_until| {
+                    schedule_closed_until.as_millisecond().max(0) as u64
+                },
+            ),
+            expiring_assertion: expiring_assertion.map(to_wall_clock),
+            files_remaining,
+            category: category.map(|category| category.to_string()),
+            name_hint: name_hint.map(|(expires_at, name_hints)| {
+                PersistedNameHint {
+                    expires_at: to_wall_clock(expires_at),
+                    name_hints: name_hints.iter().cloned().collect(),
+                }
+            }),
+            link_uses: link_uses.clone(),
+        })
+    }
+
+    fn serialize(&self) -> Result<Vec<u8>, scan2blob::error::WuffError> {
+        Ok(serde_json::to_vec(&self.0)?)
+    }
+}
+
+async fn save(
+    path: &std::path::Path,
+    snapshot: &Snapshot,
+) -> Result<(), scan2blob::error::WuffError> {
+    let serialized: Vec<u8> = snapshot.serialize()?;
+    // Write it somewhere else and then move it into place, so that a crash
+    // halfway through doesn't leave us with half a file.
+    let mut tmp_path: std::ffi::OsString = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    tokio::fs::write(&tmp_path, serialized).await?;
+    tokio::fs::rename(&tmp_path, path).await?;
+    Ok(())
+}
+
+// Runs for as long as the gate has anything to send it.
+pub async fn write_snapshots(
+    ctx: std::sync::Arc<crate::ctx::Ctx>,
+    gate_name: String,
+    path: std::path::PathBuf,
+    mut snapshots: tokio::sync::watch::Receiver<Snapshot>,
+) {
+    while snapshots.changed().await.is_ok() {
+        let snapshot: Snapshot = snapshots.borrow_and_update().clone();
+        if let Err(err) = save(&path, &snapshot).await {
+            ctx.log_warn(format!(
+                "{}: could not save state to {}: {}",
+                gate_name,
+                path.display(),
+                err
+            ));
+        }
+    }
+}
+
+// Returns None if there's no saved state. Anything that's expired in the
+// meantime is left out.
+pub fn load(
+    path: &std::path::Path,
+) -> Result<Option<RestoredGateState>, scan2blob::error::WuffError> {
+    let serialized: Vec<u8> = match std::fs::read(path) {
+        Ok(serialized) => serialized,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(None);
+        }
+        Err(err) => {
+            return Err(err.into());
+        }
+    };
+    restore(&serialized).map(Some)
+}
+
+fn restore(
+    serialized: &[u8],
+) -> Result<RestoredGateState, scan2blob::error::WuffError> {
+    let state: PersistedGateState = serde_json::from_slice(serialized)?;
+    let expiring_assertion: Option<std::time::Instant> =
+        state.expiring_assertion.and_then(from_wall_clock);
+    Ok(RestoredGateState {
+        sentinel: state.sentinel,
+        // If it's in the past, the window it was closed for is over.
+        schedule_closed_until: state
+            .schedule_closed_until
+            .and_then(|schedule_closed_until| {
+                jiff::Timestamp::from_millisecond(schedule_closed_until as i64)
+                    .ok()
+            })
+            .filter(|schedule_closed_until| {
+                *schedule_closed_until > jiff::Timestamp::now()
+            }),
+        expiring_assertion,
+        files_remaining: if expiring_assertion.is_some() {
+            state.files_remaining
+        } else {
+            None
+        },
+        category: if expiring_assertion.is_some() {
+            state.category
+        } else {
+            None
+        },
+        // The name hints go along with the timed assertion, so if that's
+        // gone, so are the name hints.
+        name_hint: if expiring_assertion.is_some() {
+            state.name_hint.and_then(|name_hint| {
+                if name_hint.name_hints.is_empty() {
+                    return None;
+                }
+                Some(RestoredNameHint {
+                    expires_at: from_wall_clock(name_hint.expires_at)?,
+                    name_hints: name_hint.name_hints,
+                })
+            })
+        } else {
+            None
+        },
+        link_uses: state
+            .link_uses
+            .into_iter()
+            .filter(|(_, link_uses)| !link_uses.is_expired())
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Close enough, given that a little time passes in between.
+    fn roughly(a: std::time::Instant, b: std::time::Instant) -> bool {
+        a.max(b) - a.min(b) < std::time::Duration::from_millis(50)
+    }
+
+    #[test]
+    fn round_trip() {
+        let now: std::time::Instant = std::time::Instant::now();
+        let expiring_assertion: std::time::Instant =
+            now + std::time::Duration::from_secs(3600);
+        let name_hint_expires_at: std::time::Instant =
+            now + std::time::Duration::from_secs(600);
+        let name_hints: std::collections::VecDeque<String> =
+            [String::from("lease"), String::from("invoice")].into();
+        let schedule_closed_until: jiff::Timestamp = jiff::Timestamp::now()
+            .checked_add(jiff::SignedDuration::from_hours(2))
+            .unwrap();
+        let link_uses: std::collections::HashMap<String, LinkUses> = [
+            (
+                String::from("forever"),
+                LinkUses {
+                    used: 1,
+                    expires: None,
+                },
+            ),
+            (
+                String::from("expired"),
+                LinkUses {
+                    used: 2,
+                    expires: Some(1),
+                },
+            ),
+        ]
+        .into();
+        let snapshot: Snapshot = Snapshot::new(
+            false,
+            Some(schedule_closed_until),
+            Some(expiring_assertion),
+            Some(3),
+            Some("bills"),
+            Some((name_hint_expires_at, &name_hints)),
+            &link_uses,
+        );
+        let restored: RestoredGateState =
+            restore(&snapshot.serialize().unwrap()).unwrap();
+        assert!(!restored.sentinel);
+        assert_eq!(
+            restored.schedule_closed_until.unwrap().as_millisecond(),
+            schedule_closed_until.as_millisecond()
+        );
+        assert!(roughly(
+            restored.expiring_assertion.unwrap(),
+            expiring_assertion
+        ));
+        assert_eq!(restored.files_remaining, Some(3));
+        assert_eq!(restored.category.as_deref(), Some("bills"));
+        let name_hint: RestoredNameHint = restored.name_hint.unwrap();
+        assert!(roughly(name_hint.expires_at, name_hint_expires_at));
+        assert_eq!(name_hint.name_hints, ["lease", "invoice"]);
+        // The one that's expired has been forgotten.
+        assert_eq!(restored.link_uses.len(), 1);
+        assert_eq!(restored.link_uses["forever"], link_uses["forever"]);
+    }
+
+    #[test]
+    fn expired() {
+        // The timed assertion ran out while nobody was looking, and took
+        // everything that goes along with it with it. So did the window the
+        // gate had been closed for.
+        let mut snapshot: Snapshot = Snapshot::new(
+            true,
+            Some(
+                jiff::Timestamp::now()
+                    .checked_sub(jiff::SignedDuration::from_hours(2))
+                    .unwrap(),
+            ),
+            None,
+            Some(3),
+            Some("bills"),
+            Some((std::time::Instant::now(), &[String::from("lease")].into())),
+            &std::collections::HashMap::new(),
+        );
+        snapshot.0.expiring_assertion =
+            Some(to_wall_clock(std::time::Instant::now()) - 1000);
+        let restored: RestoredGateState =
+            restore(&snapshot.serialize().unwrap()).unwrap();
+        assert!(restored.sentinel);
+        assert!(restored.schedule_closed_until.is_none());
+        assert!(restored.expiring_assertion.is_none());
+        assert!(restored.files_remaining.is_none());
+        assert!(restored.category.is_none());
+        assert!(restored.name_hint.is_none());
+    }
+}
@@ -1,16 +1,96 @@
 // Copied and pasted from
 // https://github.com/rustls/hyper-rustls/blob/main/examples/server.rs
+//
+// A web UI either belongs to a gate, and serves just that one, or stands on
+// its own and serves all of the gates (or some of them), each under
+// "/gate/<name>/", with a list of them at "/".
+//
+// Browsers log in at "/login" and get a session cookie. Anything else (API
+// clients, curl, and so on) can use HTTP basic auth instead, on every
+// request, or an API token.
+//
+// Admins can also make signed links (see scan2blob::signed_link), which go
+// to "/link" and unlock a gate without anyone having to log in. That's what
+// the QR code on the admin page is.
+//
+// POSTing to a gate's "hold" holds it open for as long as the response keeps
+// coming, which is the same as the gate's event stream, and only stops once
+// the client hangs up.
+
+pub mod sessions;
+pub mod tokens;
+pub mod users;
 
 #[derive(Debug, serde::Deserialize)]
 struct GateWebAppArgs {
-    open: bool,
+    open: Option<bool>,
+    // A single name hint is just the same as a queue of one. If there are
+    // both, the single one goes first.
+    name_hint: Option<String>,
+    #[serde(default)]
+    name_hints: Vec<String>,
+    // Close the gate again after this many files.
+    max_files: Option<u32>,
+    category: Option<String>,
+    // The name of a recently uploaded blob to get rid of.
+    discard: Option<String>,
+    #[serde(default)]
+    finish_batch: bool,
+    #[serde(default)]
+    duplex: bool,
+    // Has to be there, for anything that changes anything, if whoever's
+    // asking logged in through the login form.
+    csrf_token: Option<String>,
+    make_link: Option<GateWebAppMakeLink>,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct GateWebAppHoldArgs {
+    name_hint: Option<String>,
+    csrf_token: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct GateWebAppMakeLink {
     name_hint: Option<String>,
+    // How long it lasts, and how many times it can be used. At least one of
+    // these has to be there.
+    hours: Option<u32>,
+    uses: Option<u32>,
+}
+
+impl GateWebAppArgs {
+    // Just looking doesn't need any role in particular, beyond being able to
+    // see the gate at all.
+    fn role_needed(&self) -> users::Role {
+        if self.discard.is_some()
+            || !self.name_hints.is_empty()
+            || self.make_link.is_some()
+        {
+            users::Role::Admin
+        } else if self.open.is_some() || self.finish_batch {
+            users::Role::Operator
+        } else {
+            users::Role::Viewer
+        }
+    }
 }
 
 #[derive(Debug, serde::Deserialize)]
 struct GateWebAppArgsCgi {
-    open: String,
+    open: Option<String>,
     name_hint: Option<String>,
+    // One per line.
+    name_hints: Option<String>,
+    max_files: Option<String>,
+    category: Option<String>,
+    discard: Option<String>,
+    finish_batch: Option<String>,
+    csrf_token: Option<String>,
+    make_link: Option<String>,
+    link_name_hint: Option<String>,
+    link_hours: Option<String>,
+    link_uses: Option<String>,
 }
 
 impl TryFrom<GateWebAppArgsCgi> for GateWebAppArgs {
@@ -19,15 +99,18 @@ impl TryFrom<GateWebAppArgsCgi> for GateWebAppArgs {
     fn try_from(
         cgi_args: GateWebAppArgsCgi,
     ) -> Result<Self, scan2blob::error::WuffError> {
-        let open: bool = match cgi_args.open.as_str() {
-            "Locked" => false,
-            "Unlocked" => true,
-            _ => {
-                return Err(scan2blob::error::WuffError::from(
-                    "Invalid \"open\" value",
-                ));
-            }
-        };
+        let (open, duplex): (Option<bool>, bool) =
+            match cgi_args.open.as_deref() {
+                None => (None, false),
+                Some("Locked") => (Some(false), false),
+                Some("Unlocked") => (Some(true), false),
+                Some("Duplex") => (Some(true), true),
+                _ => {
+                    return Err(scan2blob::error::WuffError::from(
+                        "Invalid \"open\" value",
+                    ));
+                }
+            };
         let name_hint: Option<String> =
             if let Some(name_hint) = cgi_args.name_hint {
                 if name_hint.trim().is_empty() {
@@ -38,7 +121,76 @@ impl TryFrom<GateWebAppArgsCgi> for GateWebAppArgs {
             } else {
                 None
             };
-        Ok(Self { open, name_hint })
+        let name_hints: Vec<String> =
+            if let Some(name_hints) = cgi_args.name_hints {
+                name_hints
+                    .lines()
+                    .filter(|name_hint| !name_hint.trim().is_empty())
+                    .map(|name_hint| name_hint.to_string())
+                    .collect()
+            } else {
+                Vec::new()
+            };
+        let max_files: Option<u32> = match cgi_args.max_files.as_deref() {
+            None => None,
+            Some(max_files) if max_files.trim().is_empty() => None,
+            Some(max_files) => match max_files.trim().parse::<u32>() {
+                Ok(max_files) if max_files > 0 => Some(max_files),
+                _ => {
+                    return Err(scan2blob::error::WuffError::from(
+                        "Invalid \"max_files\" value",
+                    ));
+                }
+            },
+        };
+        // The pick list's first choice is an empty one, for no category.
+        let category: Option<String> =
+            cgi_args.category.filter(|category| !category.is_empty());
+        let discard: Option<String> =
+            cgi_args.discard.filter(|discard| !discard.is_empty());
+        // It's a submit button, so it's there if it was pressed, and not if
+        // it wasn't.
+        let finish_batch: bool = cgi_args.finish_batch.is_some();
+        let make_link: Option<GateWebAppMakeLink> =
+            if cgi_args.make_link.is_some() {
+                let number = |name: &str,
+                              value: Option<String>|
+                 -> Result<
+                    Option<u32>,
+                    scan2blob::error::WuffError,
+                > {
+                    match value.as_deref().map(str::trim) {
+                        None | Some("") => Ok(None),
+                        Some(value) => match value.parse::<u32>() {
+                            Ok(value) if value > 0 => Ok(Some(value)),
+                            _ => Err(scan2blob::error::WuffError::from(
+                                format!("Invalid \"{}\" value", name),
+                            )),
+                        },
+                    }
+                };
+                Some(GateWebAppMakeLink {
+                    name_hint: cgi_args
+                        .link_name_hint
+                        .filter(|name_hint| !name_hint.trim().is_empty()),
+                    hours: number("link_hours", cgi_args.link_hours)?,
+                    uses: number("link_uses", cgi_args.link_uses)?,
+                })
+            } else {
+                None
+            };
+        Ok(Self {
+            open,
+            name_hint,
+            name_hints,
+            max_files,
+            category,
+            discard,
+            finish_batch,
+            duplex,
+            csrf_token: cgi_args.csrf_token,
+            make_link,
+        })
     }
 }
 
@@ -46,30 +198,192 @@ impl TryFrom<GateWebAppArgsCgi> for GateWebAppArgs {
 struct GateWebAppResponse {
     error: Option<String>,
     open: bool,
+    // The one that the next document will get, and then the ones after that.
     name_hint: Option<String>,
+    name_hints: Vec<String>,
     next_change_time: Option<u64>,
+    files_remaining: Option<u32>,
+    category: Option<String>,
+    // The choices for `category`, if this gate has any.
+    categories: Vec<String>,
+    held_files: Option<usize>,
+    duplex_passes_expected: Option<usize>,
+    next_scheduled_change: Option<GateWebAppScheduledChange>,
+    // Newest first.
+    recent_uploads: Vec<GateWebAppUpload>,
+    // Whoever's asking's role, which decides what they get to do.
+    role: users::Role,
+    // Only for admins.
+    audit_log: Option<Vec<GateWebAppAuditEntry>>,
+    // The one that was just made, if one was.
+    link: Option<GateWebAppLink>,
+    #[serde(skip)]
+    links_enabled: bool,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct GateWebAppLink {
+    url: String,
+    expires: Option<u64>,
+    uses: Option<u32>,
+    // The URL as a QR code.
+    #[serde(skip)]
+    svg: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct GateWebAppScheduledChange {
+    opens: bool,
+    time: u64,
+    // The same time, in the schedule's time zone, for people to read.
+    #[serde(skip)]
+    local_time: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum GateWebAppUploadStatus {
+    InProgress,
+    Committed,
+    Failed,
+    Discarded,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct GateWebAppAuditEntry {
+    time: u64,
+    username: String,
+    action: String,
+    #[serde(skip)]
+    local_time: String,
+}
+
+impl From<crate::gate::audit::AuditEntry> for GateWebAppAuditEntry {
+    fn from(entry: crate::gate::audit::AuditEntry) -> Self {
+        Self {
+            time: entry.time,
+            local_time: local_time(entry.time, "%a %H:%M:%S"),
+            username: entry.username,
+            action: entry.action,
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+struct GateWebAppUpload {
+    time: u64,
+    // So far, if it's still in progress.
+    size: u64,
+    blob_name: String,
+    status: GateWebAppUploadStatus,
+    error: Option<String>,
+    // Until when it can be discarded, if it can.
+    discardable_until: Option<u64>,
+    #[serde(skip)]
+    local_time: String,
+}
+
+impl From<crate::upload_context::UploadEvent> for GateWebAppUpload {
+    fn from(upload: crate::upload_context::UploadEvent) -> Self {
+        let status: GateWebAppUploadStatus = match upload.stage {
+            crate::upload_context::UploadStage::Started
+            | crate::upload_context::UploadStage::Progress => {
+                GateWebAppUploadStatus::InProgress
+            }
+            crate::upload_context::UploadStage::Committed => {
+                GateWebAppUploadStatus::Committed
+            }
+            crate::upload_context::UploadStage::Failed => {
+                GateWebAppUploadStatus::Failed
+            }
+            crate::upload_context::UploadStage::Discarded => {
+                GateWebAppUploadStatus::Discarded
+            }
+        };
+        Self {
+            time: upload.started,
+            size: upload.size,
+            blob_name: upload.blob_name,
+            status,
+            error: upload.error,
+            discardable_until: upload.discardable_until,
+            local_time: local_time(upload.started, "%H:%M:%S"),
+        }
+    }
+}
+
+// Seconds since the epoch, in the system's time zone, for people to read.
+fn local_time(time: u64, format: &str) -> String {
+    match jiff::Timestamp::from_second(time as i64) {
+        Ok(t) => t
+            .to_zoned(jiff::tz::TimeZone::system())
+            .strftime(format)
+            .to_string(),
+        Err(_) => String::new(),
+    }
+}
+
+// The same as formatSize() in the script.
+fn format_size(size: u64) -> String {
+    if size < 1000 {
+        format!("{} B", size)
+    } else if size < 1000000 {
+        format!("{:.1} kB", (size as f64) / 1000.0)
+    } else {
+        format!("{:.1} MB", (size as f64) / 1000000.0)
+    }
 }
 
 impl GateWebAppResponse {
-    fn to_browser(&self, web_listener: &GateWebListener) -> String {
+    // If there's a CSRF token, it goes in every form, and there's a button
+    // for logging out.
+    fn to_browser(
+        &self,
+        gate_name: &str,
+        dashboard: bool,
+        csrf_token: Option<&str>,
+    ) -> String {
         let mut page: String = String::new();
         page.push_str(r#"<html><head><title>"#);
-        Self::html_escape(&mut page, &web_listener.gate.name);
-        page.push_str(r#"</title></head><body><h1>"#);
-        Self::html_escape(&mut page, &web_listener.gate.name);
+        Self::html_escape(&mut page, gate_name);
+        page.push_str(r#"</title></head><body>"#);
+        if dashboard {
+            page.push_str(r#"<p><a href="/">All gates</a></p>"#);
+        }
+        page.push_str(r#"<h1>"#);
+        Self::html_escape(&mut page, gate_name);
         page.push_str(r#"</h1>"#);
         if let Some(ref error) = self.error {
             page.push_str(r#"<p style="color:red;"><strong>Error: "#);
             Self::html_escape(&mut page, error);
             page.push_str(r#"</strong></p><hr/>"#);
         }
-        page.push_str(r#"<form method="post" action="/"><table><tr>"#);
-        page.push_str(r#"<td colspan="2">Current status: "#);
+        // The duplex button goes in between the other two, if there is one.
+        let num_columns: usize = if self.duplex_passes_expected.is_some() {
+            3
+        } else {
+            2
+        };
+        page.push_str(
+            r#"<form id="controls" method="post" action="./"><table><tr>"#,
+        );
+        csrf_input(&mut page, csrf_token);
+        page.push_str(&format!(
+            r#"<td colspan="{}">Current status: <span id="status">"#,
+            num_columns
+        ));
         if self.open {
             page.push_str(r#"unlocked"#);
         } else {
             page.push_str(r#"locked"#);
         }
+        if let Some(files_remaining) = self.files_remaining {
+            page.push_str(&format!(
+                r#" (for the next {} file{})"#,
+                files_remaining,
+                if files_remaining == 1 { "" } else { "s" }
+            ));
+        }
         if let Some(next_change_time) = self.next_change_time {
             let initial_value_secs: u64 = next_change_time.saturating_sub(
                 std::time::SystemTime::now()
@@ -79,42 +393,303 @@ impl GateWebAppResponse {
             );
             let initial_value_mins: f64 = (initial_value_secs as f64) / 60.0;
             page.push_str(&format!(
-                r#" (valid for next <span id="next_change_time">{}</span> minutes)"#,
+                r#"</span><span id="next_change"> (valid for next {} minutes)"#,
                 initial_value_mins.round() as u64
             ));
+        } else {
+            page.push_str(r#"</span><span id="next_change">"#);
+        }
+        page.push_str(r#"</span></td></tr>"#);
+        if self.role >= users::Role::Operator {
+            self.controls_to_browser(&mut page, num_columns);
+        }
+        match self.duplex_passes_expected {
+            Some(2) => {
+                page.push_str(&format!(
+                    r#"<tr><td colspan="{}">Duplex: waiting for fronts</td></tr>"#,
+                    num_columns
+                ));
+            }
+            Some(1) => {
+                page.push_str(&format!(
+                    r#"<tr><td colspan="{}">Duplex: waiting for backs</td></tr>"#,
+                    num_columns
+                ));
+            }
+            _ => {}
+        }
+        if let Some(held_files) = self.held_files {
+            page.push_str(&format!(
+                r#"<tr><td colspan="{}">Files held for batch: {}</td></tr>"#,
+                num_columns, held_files
+            ));
+            if held_files > 0 && self.role >= users::Role::Operator {
+                page.push_str(&format!(
+                    r#"<tr><td colspan="{}" align="center">"#,
+                    num_columns
+                ));
+                page.push_str(
+                    r#"<input type="submit" name="finish_batch" value="Finish batch"/>"#,
+                );
+                page.push_str(r#"</td></tr>"#);
+            }
+        }
+        if let Some(ref next_scheduled_change) = self.next_scheduled_change {
+            page.push_str(&format!(
+                r#"<tr><td colspan="{}">Schedule: {} at "#,
+                num_columns,
+                if next_scheduled_change.opens {
+                    "unlocks"
+                } else {
+                    "locks"
+                }
+            ));
+            Self::html_escape(&mut page, &next_scheduled_change.local_time);
+            page.push_str(r#"</td></tr>"#);
+        }
+        page.push_str(r#"</table></form>"#);
+        if !self.recent_uploads.is_empty() {
+            page.push_str(r#"<hr/><table><tr><th align="left">Time</th>"#);
+            page.push_str(r#"<th align="right">Size</th>"#);
+            page.push_str(r#"<th align="left">Blob</th>"#);
+            page.push_str(r#"<th align="left">Status</th><th></th></tr>"#);
+            let now: u64 = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            for upload in &self.recent_uploads {
+                page.push_str(r#"<tr data-blob-name=""#);
+                Self::html_escape(&mut page, &upload.blob_name);
+                page.push('"');
+                if let Some(discardable_until) = upload.discardable_until {
+                    page.push_str(&format!(
+                        r#" data-discardable-until="{}""#,
+                        discardable_until
+                    ));
+                }
+                page.push_str(r#"><td>"#);
+                Self::html_escape(&mut page, &upload.local_time);
+                page.push_str(r#"</td><td align="right">"#);
+                page.push_str(&format_size(upload.size));
+                page.push_str(r#"</td><td>"#);
+                Self::html_escape(&mut page, &upload.blob_name);
+                page.push_str(r#"</td><td>"#);
+                match upload.status {
+                    GateWebAppUploadStatus::InProgress => {
+                        page.push_str(r#"in progress"#);
+                    }
+                    GateWebAppUploadStatus::Committed => {
+                        page.push_str(r#"committed"#);
+                    }
+                    GateWebAppUploadStatus::Failed => {
+                        page.push_str(r#"failed: "#);
+                        Self::html_escape(
+                            &mut page,
+                            upload.error.as_deref().unwrap_or_default(),
+                        );
+                    }
+                    GateWebAppUploadStatus::Discarded => {
+                        page.push_str(r#"discarded"#);
+                    }
+                }
+                page.push_str(r#"</td><td>"#);
+                if let Some(discardable_until) = upload.discardable_until
+                    && discardable_until > now
+                    && self.role >= users::Role::Admin
+                {
+                    page.push_str(
+                        r#"<form method="post" action="./" style="margin:0;">"#,
+                    );
+                    csrf_input(&mut page, csrf_token);
+                    page.push_str(
+                        r#"<button type="submit" name="discard" value=""#,
+                    );
+                    Self::html_escape(&mut page, &upload.blob_name);
+                    page.push_str(r#"">Discard</button></form>"#);
+                }
+                page.push_str(r#"</td></tr>"#);
+            }
+            page.push_str(r#"</table>"#);
+        }
+        if let Some(ref audit_log) = self.audit_log
+            && !audit_log.is_empty()
+        {
+            page.push_str(r#"<hr/><table><tr><th align="left">When</th>"#);
+            page.push_str(r#"<th align="left">Who</th>"#);
+            page.push_str(r#"<th align="left">What</th></tr>"#);
+            for entry in audit_log {
+                page.push_str(r#"<tr><td>"#);
+                Self::html_escape(&mut page, &entry.local_time);
+                page.push_str(r#"</td><td>"#);
+                Self::html_escape(&mut page, &entry.username);
+                page.push_str(r#"</td><td>"#);
+                Self::html_escape(&mut page, &entry.action);
+                page.push_str(r#"</td></tr>"#);
+            }
+            page.push_str(r#"</table>"#);
         }
-        page.push_str(r#"</td></tr><tr><td colspan="2">Name hint: "#);
+        if self.role >= users::Role::Admin && self.links_enabled {
+            self.links_to_browser(&mut page, csrf_token);
+        }
+        logout_form(&mut page, csrf_token);
+        // The page keeps itself up to date, by listening for changes to the
+        // gate's state and reloading whenever there's been one. Unless
+        // someone's in the middle of filling in the form, in which case just
+        // the status gets updated.
+        page.push_str("<script>\nlet state = ");
         page.push_str(
-            r#"<input type="text" size="32" name="name_hint" value=""#,
+            &serde_json::to_string(self)
+                .expect("serde_json")
+                .replace("</", "<\\/"),
         );
-        if let Some(name_hint) = self.name_hint.as_ref() {
-            Self::html_escape(&mut page, name_hint);
+        page.push_str(";\n");
+        page.push_str(SCRIPT);
+        page.push_str("</script>");
+        page.push_str(r#"</body></html>"#);
+        page
+    }
+
+    // The link that was just made, if there is one, and a form for making
+    // another.
+    fn links_to_browser(&self, page: &mut String, csrf_token: Option<&str>) {
+        page.push_str(r#"<hr/>"#);
+        if let Some(ref link) = self.link {
+            // It's our SVG, so there's nothing in it that needs escaping.
+            page.push_str(r#"<p>"#);
+            page.push_str(&link.svg);
+            page.push_str(r#"</p><p><a href=""#);
+            Self::html_escape(page, &link.url);
+            page.push_str(r#"">"#);
+            Self::html_escape(page, &link.url);
+            page.push_str(r#"</a></p><p>"#);
+            if let Some(expires) = link.expires {
+                page.push_str(r#"Good until "#);
+                Self::html_escape(
+                    page,
+                    &local_time(expires, "%a %b %-d %H:%M"),
+                );
+            } else {
+                page.push_str(
+                    r#"Good for as long as the secret stays the same"#,
+                );
+            }
+            if let Some(uses) = link.uses {
+                page.push_str(&format!(
+                    r#", for {} use{}"#,
+                    uses,
+                    if uses == 1 { "" } else { "s" }
+                ));
+            }
+            page.push_str(r#".</p>"#);
         }
-        page.push_str(r#""/><br></td></tr><tr><td align="left">"#);
-        page.push_str(r#"<input type="submit" name="open" value="Locked"/>"#);
-        page.push_str(r#"</td><td align="right">"#);
+        page.push_str(r#"<form method="post" action="./">"#);
+        csrf_input(page, csrf_token);
         page.push_str(
-            r#"<input type="submit" name="open" value="Unlocked"/>"#,
+            r#"<table><tr><td colspan="2">Make a link that unlocks this gate, without logging in:</td></tr>"#,
         );
-        page.push_str(r#"</td></tr></table></form>"#);
-        if let Some(next_change_time) = self.next_change_time {
-            page.push_str("<script>");
-            page.push_str("setInterval(updateNextChangeTime, 10000);\n");
-            page.push_str("function updateNextChangeTime() {\n");
-            page.push_str("const now = Date.now() / 1000;\n");
+        page.push_str(
+            r#"<tr><td>Name hint:</td><td><input type="text" size="24" name="link_name_hint"/></td></tr>"#,
+        );
+        page.push_str(
+            r#"<tr><td>Good for:</td><td><input type="text" size="4" name="link_hours" value="24"/> hours</td></tr>"#,
+        );
+        page.push_str(
+            r#"<tr><td>Uses:</td><td><input type="text" size="4" name="link_uses" value="1"/></td></tr>"#,
+        );
+        page.push_str(
+            r#"<tr><td colspan="2" align="right"><input type="submit" name="make_link" value="Make link"/></td></tr>"#,
+        );
+        page.push_str(r#"</table></form>"#);
+    }
+
+    // The parts of the page that are for changing things. Only admins get to
+    // fill in a whole queue of name hints; operators get just the one.
+    fn controls_to_browser(&self, page: &mut String, num_columns: usize) {
+        if self.role >= users::Role::Admin {
             page.push_str(&format!(
-                "const next_change_time = Math.max(0, {next_change_time} - now);\n"
+                r#"<tr><td colspan="{}">Name hints, one per document:<br>"#,
+                num_columns
             ));
             page.push_str(
-                "const elem = document.getElementById(\"next_change_time\");\n",
+                r#"<textarea rows="5" cols="32" name="name_hints">"#,
             );
+            for name_hint in &self.name_hints {
+                Self::html_escape(page, name_hint);
+                page.push('\n');
+            }
+            page.push_str(r#"</textarea><br></td></tr>"#);
+        } else {
+            page.push_str(&format!(
+                r#"<tr><td colspan="{}">Name hint: "#,
+                num_columns
+            ));
             page.push_str(
-                "elem.textContent = Math.round(next_change_time / 60).toString()\n",
+                r#"<input type="text" size="24" name="name_hint"/></td></tr>"#,
             );
-            page.push_str("}</script>");
         }
-        page.push_str(r#"</body></html>"#);
-        page
+        if !self.categories.is_empty() {
+            page.push_str(&format!(
+                r#"<tr><td colspan="{}">Category: <select name="category"><option value="">(none)</option>"#,
+                num_columns
+            ));
+            for category in &self.categories {
+                page.push_str(r#"<option value=""#);
+                Self::html_escape(page, category);
+                page.push('"');
+                if self.category.as_ref() == Some(category) {
+                    page.push_str(r#" selected"#);
+                }
+                page.push('>');
+                Self::html_escape(page, category);
+                page.push_str(r#"</option>"#);
+            }
+            page.push_str(r#"</select></td></tr>"#);
+        }
+        page.push_str(&format!(
+            r#"<tr><td colspan="{}">Lock again after "#,
+            num_columns
+        ));
+        page.push_str(r#"<input type="text" size="4" name="max_files"/>"#);
+        page.push_str(r#" files</td></tr><tr><td align="left">"#);
+        page.push_str(r#"<input type="submit" name="open" value="Locked"/>"#);
+        if self.duplex_passes_expected.is_some() {
+            page.push_str(r#"</td><td align="center">"#);
+            page.push_str(
+                r#"<input type="submit" name="open" value="Duplex"/>"#,
+            );
+        }
+        page.push_str(r#"</td><td align="right">"#);
+        page.push_str(
+            r#"<input type="submit" name="open" value="Unlocked"/>"#,
+        );
+        page.push_str(r#"</td></tr>"#);
+    }
+
+    // The next change time is worked out from how long there is to go until
+    // then, so it can come out a second different without anything having
+    // actually changed. The recent uploads don't count, since they have
+    // events of their own.
+    fn is_same_state(&self, other: &Self) -> bool {
+        let same_next_change_time: bool =
+            match (self.next_change_time, other.next_change_time) {
+                (Some(a), Some(b)) => a.abs_diff(b) <= 1,
+                (a, b) => a == b,
+            };
+        let scheduled_change = |response: &Self| -> Option<(bool, u64)> {
+            response
+                .next_scheduled_change
+                .as_ref()
+                .map(|change| (change.opens, change.time))
+        };
+        same_next_change_time
+            && self.open == other.open
+            && self.name_hints == other.name_hints
+            && self.files_remaining == other.files_remaining
+            && self.category == other.category
+            && self.categories == other.categories
+            && self.held_files == other.held_files
+            && self.duplex_passes_expected == other.duplex_passes_expected
+            && scheduled_change(self) == scheduled_change(other)
     }
 
     fn html_escape(page: &mut String, s: &str) {
@@ -130,6 +705,254 @@ impl GateWebAppResponse {
     }
 }
 
+// What a web UI that serves more than one gate shows at "/": every gate that
+// whoever's asking can see.
+#[derive(Debug, serde::Serialize)]
+struct WebUiDashboardResponse {
+    gates: Vec<WebUiDashboardGate>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct WebUiDashboardGate {
+    name: String,
+    open: bool,
+    name_hint: Option<String>,
+    role: users::Role,
+}
+
+impl WebUiDashboardResponse {
+    fn to_browser(&self, csrf_token: Option<&str>) -> String {
+        let mut page: String = String::new();
+        // There's no event stream for all of the gates at once, so this is
+        // the old-fashioned way of keeping it up to date.
+        page.push_str(r#"<html><head><title>Gates</title>"#);
+        page.push_str(r#"<meta http-equiv="refresh" content="30"/>"#);
+        page.push_str(r#"</head><body><h1>Gates</h1>"#);
+        if self.gates.is_empty() {
+            page.push_str(r#"<p>There aren't any gates here for you.</p>"#);
+        } else {
+            page.push_str(r#"<table><tr><th align="left">Gate</th>"#);
+            page.push_str(r#"<th align="left">Status</th>"#);
+            page.push_str(r#"<th align="left">Name hint</th></tr>"#);
+            for gate in &self.gates {
+                page.push_str(&format!(
+                    r#"<tr><td><a href="gate/{}/">"#,
+                    scan2blob::util::percent_encode(&gate.name)
+                ));
+                GateWebAppResponse::html_escape(&mut page, &gate.name);
+                page.push_str(r#"</a></td><td>"#);
+                if gate.open {
+                    page.push_str(r#"unlocked"#);
+                } else {
+                    page.push_str(r#"locked"#);
+                }
+                page.push_str(r#"</td><td>"#);
+                if let Some(ref name_hint) = gate.name_hint {
+                    GateWebAppResponse::html_escape(&mut page, name_hint);
+                }
+                page.push_str(r#"</td></tr>"#);
+            }
+            page.push_str(r#"</table>"#);
+        }
+        logout_form(&mut page, csrf_token);
+        page.push_str(r#"</body></html>"#);
+        page
+    }
+}
+
+fn csrf_input(page: &mut String, csrf_token: Option<&str>) {
+    if let Some(csrf_token) = csrf_token {
+        page.push_str(r#"<input type="hidden" name="csrf_token" value=""#);
+        GateWebAppResponse::html_escape(page, csrf_token);
+        page.push_str(r#""/>"#);
+    }
+}
+
+// Only for sessions. There's no logging out of HTTP basic auth, or of an API
+// token.
+fn logout_form(page: &mut String, csrf_token: Option<&str>) {
+    if csrf_token.is_some() {
+        page.push_str(r#"<hr/><form method="post" action="/logout">"#);
+        csrf_input(page, csrf_token);
+        page.push_str(r#"<input type="submit" value="Log out"/></form>"#);
+    }
+}
+
+// What someone who's followed a signed link sees. If there's an action,
+// there's a button that POSTs to it.
+fn link_page(title: &str, message: &str, action: Option<&str>) -> String {
+    let mut page: String = String::new();
+    page.push_str(r#"<html><head><title>"#);
+    GateWebAppResponse::html_escape(&mut page, title);
+    // It's most likely going to be on a phone.
+    page.push_str(
+        r#"</title><meta name="viewport" content="width=device-width, initial-scale=1"/>"#,
+    );
+    page.push_str(r#"</head><body><h1>"#);
+    GateWebAppResponse::html_escape(&mut page, title);
+    page.push_str(r#"</h1><p>"#);
+    GateWebAppResponse::html_escape(&mut page, message);
+    page.push_str(r#"</p>"#);
+    if let Some(action) = action {
+        page.push_str(r#"<form method="post" action=""#);
+        GateWebAppResponse::html_escape(&mut page, action);
+        page.push_str(r#""><input type="submit" value="Unlock"/></form>"#);
+    }
+    page.push_str(r#"</body></html>"#);
+    page
+}
+
+fn login_page(error: Option<&str>) -> String {
+    let mut page: String = String::new();
+    page.push_str(r#"<html><head><title>Log in</title></head><body>"#);
+    page.push_str(r#"<h1>Log in</h1>"#);
+    if let Some(error) = error {
+        page.push_str(r#"<p style="color:red;"><strong>"#);
+        GateWebAppResponse::html_escape(&mut page, error);
+        page.push_str(r#"</strong></p>"#);
+    }
+    page.push_str(r#"<form method="post" action="/login"><table>"#);
+    page.push_str(r#"<tr><td>Username:</td><td>"#);
+    page.push_str(r#"<input type="text" name="username" autofocus/>"#);
+    page.push_str(r#"</td></tr><tr><td>Password:</td><td>"#);
+    page.push_str(r#"<input type="password" name="password"/></td></tr>"#);
+    // Only for users who have a TOTP secret, and everyone else can leave it
+    // empty.
+    page.push_str(r#"<tr><td>Code:</td><td>"#);
+    page.push_str(r#"<input type="text" name="code" inputmode="numeric" "#);
+    page.push_str(r#"autocomplete="one-time-code"/></td></tr>"#);
+    page.push_str(r#"<tr><td colspan="2" align="right">"#);
+    page.push_str(r#"<input type="submit" value="Log in"/></td></tr>"#);
+    page.push_str(r#"</table></form></body></html>"#);
+    page
+}
+
+#[derive(serde::Deserialize)]
+struct LoginArgs {
+    username: String,
+    password: String,
+    #[serde(default)]
+    code: String,
+}
+
+const SESSION_COOKIE: &str = "scan2blob_session";
+
+// The same CSRF token can come in a header instead, for API clients that
+// happen to be using a session.
+const CSRF_TOKEN_HEADER: &str = "x-csrf-token";
+
+// This goes along with the initial state, which is put in front of it. The
+// comparison is the same as is_same_state(), give or take the things that
+// the page doesn't show. Progress on uploads that are already on the page
+// gets shown where they are, and anything else about them, or about new
+// ones, needs the page to be reloaded. Discard buttons go away by themselves
+// once it's too late.
+const SCRIPT: &str = r#"let edited = state.link !== null;
+document.getElementById("controls").addEventListener(
+  "input", () => { edited = true; });
+function showStatus() {
+  let status = state.open ? "unlocked" : "locked";
+  const n = state.files_remaining;
+  if (n !== null) {
+    status += " (for the next " + n + " file" + (n === 1 ? "" : "s") + ")";
+  }
+  document.getElementById("status").textContent = status;
+  updateNextChangeTime();
+}
+function updateNextChangeTime() {
+  const elem = document.getElementById("next_change");
+  if (state.next_change_time === null) {
+    elem.textContent = "";
+    return;
+  }
+  const secs = Math.max(0, state.next_change_time - Date.now() / 1000);
+  elem.textContent = " (valid for next " + Math.round(secs / 60) + " minutes)";
+}
+setInterval(updateNextChangeTime, 10000);
+function hideDiscardButtons() {
+  const now = Date.now() / 1000;
+  for (const row of document.querySelectorAll("tr[data-discardable-until]")) {
+    if (Number(row.dataset.discardableUntil) <= now) {
+      row.cells[4].textContent = "";
+    }
+  }
+}
+setInterval(hideDiscardButtons, 10000);
+function sameState(a, b) {
+  const ta = a.next_change_time;
+  const tb = b.next_change_time;
+  if ((ta === null) !== (tb === null) ||
+      (ta !== null && Math.abs(ta - tb) > 1)) {
+    return false;
+  }
+  const strip = (s) => JSON.stringify(
+    { ...s, error: null, next_change_time: null, recent_uploads: null,
+      audit_log: null, link: null });
+  return strip(a) === strip(b);
+}
+function formatSize(size) {
+  if (size < 1000) {
+    return size + " B";
+  } else if (size < 1000000) {
+    return (size / 1000).toFixed(1) + " kB";
+  } else {
+    return (size / 1000000).toFixed(1) + " MB";
+  }
+}
+const events = new EventSource("events");
+events.addEventListener("state", (event) => {
+  const newState = JSON.parse(event.data);
+  if (sameState(state, newState)) {
+    return;
+  }
+  state = newState;
+  if (edited) {
+    showStatus();
+  } else {
+    location.replace("./");
+  }
+});
+events.addEventListener("upload", (event) => {
+  const upload = JSON.parse(event.data);
+  for (const row of document.querySelectorAll("tr[data-blob-name]")) {
+    if (row.dataset.blobName === upload.blob_name) {
+      row.cells[1].textContent = formatSize(upload.size);
+      if (upload.stage === "committed") {
+        row.cells[3].textContent = "committed";
+      } else if (upload.stage === "failed") {
+        row.cells[3].textContent = "failed: " + upload.error;
+      } else if (upload.stage === "discarded") {
+        row.cells[3].textContent = "discarded";
+        row.cells[4].textContent = "";
+      }
+      if (upload.stage === "started" || upload.stage === "progress" ||
+          edited) {
+        return;
+      }
+      break;
+    }
+  }
+  if (!edited) {
+    location.replace("./");
+  }
+});
+"#;
+
+// How long an event stream can go without anything being sent down it,
+// before a comment gets sent instead, so that nothing in between decides the
+// connection's dead.
+const EVENT_STREAM_KEEPALIVE: std::time::Duration =
+    std::time::Duration::from_secs(30);
+
+// How many messages can be waiting to go down an event stream.
+const EVENT_STREAM_BUFFER: usize = 16;
+
+// Once the gate's state is due to change by itself, like when an assertion
+// expires, we give it this much longer, so that it's definitely changed by
+// the time we look.
+const EVENT_STREAM_SLACK: std::time::Duration =
+    std::time::Duration::from_millis(100);
+
 struct GateWebListenerEachPort {
     web_listener: std::sync::Arc<GateWebListener>,
     listen_on: std::net::SocketAddr,
@@ -161,14 +984,32 @@ pub struct ConfigGateWeb {
     listen_on: Vec<std::net::SocketAddr>,
     certificate_chain: scan2blob::util::LiteralOrFile,
     private_key: scan2blob::util::LiteralOrFile,
-    users: std::collections::HashMap<String, String>,
+    users: std::collections::HashMap<String, users::ConfigWebUser>,
+    // How long someone stays logged in, in seconds.
+    #[serde(default = "default_session_lifetime")]
+    session_lifetime: u32,
+    // By name, which is only for the logs.
+    #[serde(default)]
+    api_tokens: std::collections::HashMap<String, tokens::ConfigApiToken>,
+    // For signing links. Without one, there aren't any. Changing it makes
+    // all of the links there are so far stop working.
+    link_secret: Option<scan2blob::util::LiteralOrEnvironmentVariable>,
+    // Where links point, e.g. "https://scans.example.com:8443". Whatever
+    // Host a request came in with isn't to be trusted to end up in a link
+    // that's handed to someone else, so it has to be spelled out, and it's
+    // required along with link_secret.
+    link_base_url: Option<String>,
 }
 
 pub struct ConfigGateWebEnriched {
     listen_on: Vec<std::net::SocketAddr>,
     certificate_chain: String,
     private_key: String,
-    users: std::collections::HashMap<String, String>,
+    users: std::collections::HashMap<String, users::WebUser>,
+    session_lifetime: u32,
+    api_tokens: std::collections::HashMap<String, tokens::ApiToken>,
+    link_secret: Option<String>,
+    link_base_url: Option<String>,
 }
 
 impl TryFrom<ConfigGateWeb> for ConfigGateWebEnriched {
@@ -181,12 +1022,120 @@ impl TryFrom<ConfigGateWeb> for ConfigGateWebEnriched {
             certificate_chain,
             private_key,
             users,
+            session_lifetime,
+            api_tokens,
+            link_secret,
+            link_base_url,
         } = config;
+        if session_lifetime == 0 {
+            return Err(scan2blob::error::WuffError::from(
+                "session_lifetime must be at least 1",
+            ));
+        }
+        let link_secret: Option<String> =
+            if let Some(link_secret) = link_secret {
+                Some(link_secret.try_into()?)
+            } else {
+                None
+            };
+        if let Some(ref link_secret) = link_secret
+            && link_secret.len() < 16
+        {
+            return Err(scan2blob::error::WuffError::from(
+                "link_secret should be at least 16 characters long",
+            ));
+        }
+        let link_base_url: Option<String> = match (&link_secret, link_base_url)
+        {
+            (Some(_), None) => {
+                return Err(scan2blob::error::WuffError::from(
+                    "link_secret needs a link_base_url for links to point at",
+                ));
+            }
+            (_, Some(link_base_url)) => {
+                if !(link_base_url.starts_with("https://")
+                    || link_base_url.starts_with("http://"))
+                {
+                    return Err(scan2blob::error::WuffError::from(
+                        "link_base_url must start with https:// or http://",
+                    ));
+                }
+                Some(link_base_url.trim_end_matches('/').to_string())
+            }
+            (None, None) => None,
+        };
         Ok(Self {
             listen_on,
             certificate_chain: certificate_chain.try_into()?,
             private_key: private_key.try_into()?,
-            users,
+            users: users
+                .into_iter()
+                .map(|(username, user)| {
+                    let user: users::WebUser = user.try_into().map_err(
+                        |e: scan2blob::error::WuffError| {
+                            scan2blob::error::WuffError::from(format!(
+                                "web user {}: {}",
+                                username, e
+                            ))
+                        },
+                    )?;
+                    Ok((username, user))
+                })
+                .collect::<Result<
+                    std::collections::HashMap<String, users::WebUser>,
+                    scan2blob::error::WuffError,
+                >>()?,
+            session_lifetime,
+            api_tokens: api_tokens
+                .into_iter()
+                .map(|(name, api_token)| {
+                    let api_token: tokens::ApiToken = api_token
+                        .try_into()
+                        .map_err(|e: scan2blob::error::WuffError| {
+                            scan2blob::error::WuffError::from(format!(
+                                "api token {}: {}",
+                                name, e
+                            ))
+                        })?;
+                    Ok((name, api_token))
+                })
+                .collect::<Result<
+                    std::collections::HashMap<String, tokens::ApiToken>,
+                    scan2blob::error::WuffError,
+                >>()?,
+            link_secret,
+            link_base_url,
+        })
+    }
+}
+
+fn default_session_lifetime() -> u32 {
+    12 * 60 * 60
+}
+
+// The web UI that stands on its own. It's the same as a gate's, plus which
+// gates it serves, if not all of them.
+#[derive(serde::Deserialize)]
+pub struct ConfigWebUi {
+    #[serde(flatten)]
+    listener: ConfigGateWeb,
+    gates: Option<Vec<String>>,
+}
+
+pub struct ConfigWebUiEnriched {
+    pub listener: ConfigGateWebEnriched,
+    pub gates: Option<Vec<String>>,
+}
+
+impl TryFrom<ConfigWebUi> for ConfigWebUiEnriched {
+    type Error = scan2blob::error::WuffError;
+    fn try_from(
+        config: ConfigWebUi,
+    ) -> Result<Self, scan2blob::error::WuffError> {
+        let ConfigWebUi { listener, gates } = config;
+        Ok(Self {
+            listener: listener.try_into()?,
+            gates,
         })
     }
 }
@@ -197,25 +1146,108 @@ pub struct GateWebListener {
     rustls_acceptor: tokio_rustls::TlsAcceptor,
     hyper_builder:
         hyper_util::server::conn::auto::Builder<hyper_util::rt::TokioExecutor>,
-    users: std::collections::HashMap<String, String>,
-    http_basic_auth: scan2blob::http_basic_auth::HttpBasicAuth,
+    users: std::collections::HashMap<String, users::WebUser>,
+    // By hash, along with each one's name.
+    api_tokens: std::collections::HashMap<String, (String, tokens::ApiToken)>,
+    sessions: sessions::Sessions,
+    link_secret: Option<String>,
+    // With no trailing slash.
+    link_base_url: Option<String>,
+    // The time step of the last TOTP code that each user logged in with, so
+    // that nobody who happens to see one can use it again while it's still
+    // good.
+    totp_steps: std::sync::Mutex<std::collections::HashMap<String, u64>>,
+    http_auth: scan2blob::http_auth::HttpAuth,
     http_accept_header: scan2blob::http_accept_header::HttpAcceptHeader,
+    // In order of name.
+    gates: Vec<std::sync::Arc<crate::gate::Gate>>,
+    // If not, there's only one gate, and it's served at "/".
+    dashboard: bool,
+}
+
+// Who sent a request. If they logged in through the login form, rather than
+// using HTTP basic auth or an API token, then anything that changes anything
+// has to come with their session's CSRF token.
+struct Requester {
+    // For an API token, this is just for the logs.
+    username: String,
+    // The token and the session it's for.
+    session: Option<(String, sessions::Session)>,
+    // If there's one of these, it's what decides what can be done, rather
+    // than whichever user it is.
+    api_token: Option<tokens::ApiToken>,
+}
+
+impl Requester {
+    fn csrf_token(&self) -> Option<&str> {
+        self.session
+            .as_ref()
+            .map(|(_token, session)| session.csrf_token.as_str())
+    }
+}
+
+// Which gate a request is about, who sent it, and what they can do with it.
+struct GateRoute {
     gate: std::sync::Arc<crate::gate::Gate>,
+    username: String,
+    csrf_token: Option<String>,
+    role: users::Role,
+    // The path to the gate's page, ending with "/".
+    base: String,
 }
 
 impl GateWebListener {
     pub fn new(
         ctx: &std::sync::Arc<crate::ctx::Ctx>,
-        gate: &std::sync::Arc<crate::gate::Gate>,
+        mut gates: Vec<std::sync::Arc<crate::gate::Gate>>,
+        dashboard: bool,
         config: &ConfigGateWebEnriched,
     ) -> Result<Self, scan2blob::error::WuffError> {
-        let mut certificate_chain_data: std::io::Cursor<&[u8]> =
-            std::io::Cursor::new(config.certificate_chain.as_bytes());
-        let mut private_key_data: std::io::Cursor<&[u8]> =
-            std::io::Cursor::new(config.private_key.as_bytes());
-        let mut certificate_chain: Vec<
-            rustls_pki_types::CertificateDer<'static>,
-        > = Vec::new();
+        assert!(dashboard || gates.len() == 1);
+        gates.sort_by(|a, b| a.name.cmp(&b.name));
+        for (username, user) in &config.users {
+            for gate_name in user.gate_names() {
+                if !ctx.config.gates.contains_key(gate_name) {
+                    return Err(scan2blob::error::WuffError::from(format!(
+                        "web user {}: no such gate {}",
+                        username, gate_name
+                    )));
+                }
+            }
+        }
+        let mut api_tokens: std::collections::HashMap<
+            String,
+            (String, tokens::ApiToken),
+        > = std::collections::HashMap::new();
+        for (name, api_token) in &config.api_tokens {
+            for gate_name in api_token.gate_names() {
+                if !ctx.config.gates.contains_key(gate_name) {
+                    return Err(scan2blob::error::WuffError::from(format!(
+                        "api token {}: no such gate {}",
+                        name, gate_name
+                    )));
+                }
+            }
+            if api_tokens
+                .insert(
+                    api_token.hash.clone(),
+                    (name.clone(), api_token.clone()),
+                )
+                .is_some()
+            {
+                return Err(scan2blob::error::WuffError::from(format!(
+                    "api token {}: same hash as another one",
+                    name
+                )));
+            }
+        }
+        let mut certificate_chain_data: std::io::Cursor<&[u8]> =
+            std::io::Cursor::new(config.certificate_chain.as_bytes());
+        let mut private_key_data: std::io::Cursor<&[u8]> =
+            std::io::Cursor::new(config.private_key.as_bytes());
+        let mut certificate_chain: Vec<
+            rustls_pki_types::CertificateDer<'static>,
+        > = Vec::new();
         for cert in rustls_pemfile::certs(&mut certificate_chain_data) {
             certificate_chain.push(cert?);
         }
@@ -241,8 +1273,8 @@ impl GateWebListener {
         let hyper_builder: hyper_util::server::conn::auto::Builder<
             hyper_util::rt::TokioExecutor,
         > = hyper_util::server::conn::auto::Builder::new(hyper_executor);
-        let http_basic_auth: scan2blob::http_basic_auth::HttpBasicAuth =
-            scan2blob::http_basic_auth::HttpBasicAuth::new();
+        let http_auth: scan2blob::http_auth::HttpAuth =
+            scan2blob::http_auth::HttpAuth::new();
         let http_accept_header: scan2blob::http_accept_header::HttpAcceptHeader =
             scan2blob::http_accept_header::HttpAcceptHeader::new();
         Ok(Self {
@@ -251,16 +1283,28 @@ impl GateWebListener {
             rustls_acceptor,
             hyper_builder,
             users: config.users.clone(),
-            http_basic_auth,
+            api_tokens,
+            sessions: sessions::Sessions::new(std::time::Duration::from_secs(
+                config.session_lifetime as u64,
+            )),
+            link_secret: config.link_secret.clone(),
+            link_base_url: config.link_base_url.clone(),
+            totp_steps: std::sync::Mutex::new(std::collections::HashMap::new()),
+            http_auth,
             http_accept_header,
-            gate: std::sync::Arc::clone(gate),
+            gates,
+            dashboard,
         })
     }
 
     pub fn start(self: &std::sync::Arc<Self>) {
         for listen_on in &self.listen_on {
             self.ctx.spawn_critical(
-                format!("web ui for gate {}", self.gate.name),
+                if self.dashboard {
+                    String::from("web ui")
+                } else {
+                    format!("web ui for gate {}", self.gates[0].name)
+                },
                 GateWebListenerEachPort {
                     web_listener: std::sync::Arc::clone(self),
                     listen_on: *listen_on,
@@ -301,44 +1345,175 @@ impl GateWebListener {
 
     fn calculate_redirect(
         &self,
-        req: hyper::Request<hyper::body::Incoming>,
+        req: &hyper::Request<hyper::body::Incoming>,
+        path: &str,
     ) -> String {
         let Some(host_header) = req.headers().get(hyper::header::HOST) else {
-            return "/".to_string();
+            return path.to_string();
         };
         let Ok(host_header) = host_header.to_str() else {
-            return "/".to_string();
+            return path.to_string();
         };
-        format!("https://{}/", host_header)
+        format!("https://{}{}", host_header, path)
+    }
+
+    // If the user can't see the gate, it's as if it weren't there.
+    fn get_gate(
+        &self,
+        requester: &Requester,
+        gate_name: &str,
+    ) -> Option<GateRoute> {
+        let gate: &std::sync::Arc<crate::gate::Gate> =
+            self.gates.iter().find(|gate| gate.name == gate_name)?;
+        let role: users::Role = self.role(requester, gate_name)?;
+        Some(GateRoute {
+            gate: std::sync::Arc::clone(gate),
+            username: requester.username.clone(),
+            csrf_token: requester.csrf_token().map(str::to_string),
+            role,
+            base: if self.dashboard {
+                format!(
+                    "/gate/{}/",
+                    scan2blob::util::percent_encode(gate_name)
+                )
+            } else {
+                String::from("/")
+            },
+        })
+    }
+
+    // Heuristic: browsers will tend to explicitly ask for text/html (not
+    // using wildcards, but actually literally text/html), earlier in the
+    // list than they ask for application/json.
+    fn is_browser(&self, headers: &hyper::HeaderMap) -> bool {
+        if let Some(accept_header) = headers.get(hyper::header::ACCEPT) {
+            if let Ok(accept_header) = accept_header.to_str() {
+                for mime_type in self.http_accept_header.parse(accept_header) {
+                    match mime_type {
+                        (Some("text"), Some("html")) => {
+                            return true;
+                        }
+                        (Some("application"), Some("json")) => {
+                            return false;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        false
     }
 
     async fn handle_request(
         self: std::sync::Arc<Self>,
         mut req: hyper::Request<hyper::body::Incoming>,
-    ) -> Result<hyper::Response<String>, hyper::http::Error> {
+    ) -> Result<hyper::Response<ResponseBody>, hyper::http::Error> {
         let body: Vec<u8> = Body::new(req.body_mut(), 10000).await;
-        let uri: &hyper::Uri = req.uri();
-        let headers: &hyper::HeaderMap = req.headers();
 
-        let authorized: bool = if let Some(auth_header) =
-            headers.get(hyper::header::AUTHORIZATION)
-        {
-            self.check_auth(auth_header)
-        } else {
-            false
-        };
+        if req.uri().path() == "/login" {
+            return self.handle_login(&req, &body);
+        }
+        // Signed links are their own authentication.
+        if req.uri().path() == "/link" {
+            return self.handle_link(&req);
+        }
 
-        if !authorized {
+        let Some(requester) = self.authenticate(req.headers()) else {
+            // Browsers get sent to the login form, and anything else gets
+            // asked for HTTP basic auth or an API token.
+            if self.is_browser(req.headers()) {
+                return hyper::Response::builder()
+                    .status(hyper::StatusCode::FOUND)
+                    .header(hyper::header::CACHE_CONTROL, "no-store")
+                    .header(
+                        hyper::header::LOCATION,
+                        self.calculate_redirect(&req, "/login"),
+                    )
+                    .body("".into());
+            }
             return hyper::Response::builder()
                 .status(hyper::StatusCode::UNAUTHORIZED)
                 .header(
                     hyper::header::WWW_AUTHENTICATE,
                     "Basic realm=\"scan2blob\"",
                 )
+                .header(
+                    hyper::header::WWW_AUTHENTICATE,
+                    "Bearer realm=\"scan2blob\"",
+                )
                 .body("".into());
+        };
+
+        if req.uri().path() == "/logout" {
+            return self.handle_logout(&req, &body, &requester);
+        }
+
+        // Which gate it's about, and whatever's left of the path after that,
+        // which is None if the path is the gate's, but without the "/" on the
+        // end.
+        let path: &str = req.uri().path();
+        let (route, rest): (Option<GateRoute>, Option<String>) = if !self
+            .dashboard
+        {
+            (
+                self.get_gate(&requester, &self.gates[0].name),
+                path.strip_prefix('/').map(str::to_string),
+            )
+        } else if path == "/" {
+            return self.handle_dashboard(&req, &requester);
+        } else if let Some(gate_path) = path.strip_prefix("/gate/") {
+            let (gate_name, rest): (&str, Option<String>) = match gate_path
+                .split_once('/')
+            {
+                Some((gate_name, rest)) => (gate_name, Some(rest.to_string())),
+                None => (gate_path, None),
+            };
+            (
+                scan2blob::util::percent_decode(gate_name).and_then(
+                    |gate_name| self.get_gate(&requester, &gate_name),
+                ),
+                rest,
+            )
+        } else {
+            return hyper::Response::builder()
+                .status(hyper::StatusCode::FOUND)
+                .header(hyper::header::CACHE_CONTROL, "no-store")
+                .header(
+                    hyper::header::LOCATION,
+                    self.calculate_redirect(&req, "/"),
+                )
+                .body("".into());
+        };
+        let Some(route) = route else {
+            return hyper::Response::builder()
+                .status(hyper::StatusCode::NOT_FOUND)
+                .header(hyper::header::CONTENT_TYPE, "text/plain")
+                .body("No such gate".into());
+        };
+
+        self.handle_gate_request(req, body, route, rest).await
+    }
+
+    async fn handle_gate_request(
+        self: std::sync::Arc<Self>,
+        req: hyper::Request<hyper::body::Incoming>,
+        body: Vec<u8>,
+        route: GateRoute,
+        rest: Option<String>,
+    ) -> Result<hyper::Response<ResponseBody>, hyper::http::Error> {
+        // Anything else that's asked for gets redirected back to the gate's
+        // page, but this one's special.
+        if rest.as_deref() == Some("events") {
+            return self.start_event_stream(route);
+        }
+        if rest.as_deref() == Some("hold") {
+            return self.start_hold(&req, &body, route);
         }
 
-        let (args, error, mut need_redirect) = match headers
+        let uri: &hyper::Uri = req.uri();
+        let headers: &hyper::HeaderMap = req.headers();
+
+        let (args, mut error, mut need_redirect) = match headers
             .get(hyper::header::CONTENT_TYPE)
             .map(AsRef::<[u8]>::as_ref)
         {
@@ -386,75 +1561,155 @@ impl GateWebListener {
                 }
             }
         };
-        match uri.path() {
-            "" | "/" => {}
-            _ => {
-                need_redirect = true;
-            }
+        if rest.as_deref() != Some("") {
+            need_redirect = true;
         }
 
-        if let Some(args) = args {
-            if args.open {
-                self.gate
-                    .assert_gate_open_timed_with_name_hint(args.name_hint);
-            } else {
-                self.gate.assert_gate_closed();
+        let gate: &crate::gate::Gate = &route.gate;
+        let username: &str = &route.username;
+        // Why nothing got changed, if it didn't, and there's a better status
+        // code for it than BAD_REQUEST.
+        let mut refused: Option<hyper::StatusCode> = None;
+        if let Some(ref args) = args
+            && args.role_needed() > users::Role::Viewer
+        {
+            let csrf_token: Option<&str> =
+                args.csrf_token.as_deref().or_else(|| {
+                    headers
+                        .get(CSRF_TOKEN_HEADER)
+                        .and_then(|csrf_token| csrf_token.to_str().ok())
+                });
+            if req.method() != hyper::Method::POST {
+                // Otherwise, just getting the browser to follow a link would
+                // be enough.
+                error = Some(scan2blob::error::WuffError::from(
+                    "Changes have to be POSTed",
+                ));
+                refused = Some(hyper::StatusCode::METHOD_NOT_ALLOWED);
+            } else if let Some(ref expected) = route.csrf_token
+                && csrf_token != Some(expected.as_str())
+            {
+                error = Some(scan2blob::error::WuffError::from(
+                    "Missing or wrong CSRF token (try reloading the page)",
+                ));
+                refused = Some(hyper::StatusCode::FORBIDDEN);
+            } else if args.role_needed() > route.role {
+                error = Some(scan2blob::error::WuffError::from(format!(
+                    "That needs the {} role, and yours is {}",
+                    args.role_needed().name(),
+                    route.role.name()
+                )));
+                refused = Some(hyper::StatusCode::FORBIDDEN);
             }
         }
-
-        let (state, next_change_time) = self.gate.get_current_state_extended();
-        let error: Option<String> = error.map(|e| format!("{}", e));
-        let next_change_time: Option<u64> = next_change_time.map(|d| {
-            (std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                + d)
-                .as_secs()
-        });
-        let response: GateWebAppResponse = if let Some(name_hint) = state {
-            GateWebAppResponse {
-                open: true,
-                name_hint,
-                error,
-                next_change_time,
+        let mut link: Option<GateWebAppLink> = None;
+        if refused.is_none()
+            && let Some(args) = args
+        {
+            if let Some(ref make_link) = args.make_link {
+                match self.make_link(gate, make_link) {
+                    Ok(made) => {
+                        let mut action: String = String::from("made a link");
+                        if let Some(ref name_hint) = make_link.name_hint {
+                            action.push_str(&format!(
+                                ", name hint {:?}",
+                                name_hint
+                            ));
+                        }
+                        if let Some(hours) = make_link.hours {
+                            action.push_str(&format!(", for {} hours", hours));
+                        }
+                        if let Some(uses) = make_link.uses {
+                            action.push_str(&format!(", for {} uses", uses));
+                        }
+                        gate.audit(username, action);
+                        link = Some(made);
+                    }
+                    Err(err) => {
+                        error = Some(err);
+                    }
+                }
             }
-        } else {
-            GateWebAppResponse {
-                open: false,
-                name_hint: None,
-                error,
-                next_change_time,
+            if args.finish_batch {
+                gate.finish_batches();
+                gate.audit(username, String::from("finished batch"));
             }
-        };
-
-        // Heuristic: browsers will tend to explicitly ask for text/html (not
-        // using wildcards, but actually literally text/html), earlier in the
-        // list than they ask for application/json.
-        let mut is_browser: bool = false;
-        if let Some(accept_header) = headers.get(hyper::header::ACCEPT) {
-            if let Ok(accept_header) = accept_header.to_str() {
-                for mime_type in self.http_accept_header.parse(accept_header) {
-                    match mime_type {
-                        (Some("text"), Some("html")) => {
-                            is_browser = true;
-                            break;
-                        }
-                        (Some("application"), Some("json")) => {
-                            break;
+            if let Some(ref discard) = args.discard {
+                if let Err(err) = gate.discard(discard).await {
+                    error = Some(err);
+                } else {
+                    gate.audit(username, format!("discarded {}", discard));
+                }
+            }
+            match args.open {
+                Some(true) => {
+                    let mut name_hints: Vec<String> = args.name_hints;
+                    if let Some(name_hint) = args.name_hint {
+                        name_hints.insert(0, name_hint);
+                    }
+                    let mut action: String = String::from("unlocked");
+                    if !name_hints.is_empty() {
+                        action.push_str(&format!(
+                            ", name hints {:?}",
+                            name_hints
+                        ));
+                    }
+                    if let Some(ref category) = args.category {
+                        action.push_str(&format!(", category {}", category));
+                    }
+                    if let Some(max_files) = args.max_files {
+                        action.push_str(&format!(", for {} files", max_files));
+                    }
+                    if let Err(err) = gate
+                        .assert_gate_open_timed_with_name_hints(
+                            name_hints,
+                            args.max_files,
+                            args.category,
+                            None,
+                        )
+                    {
+                        error = Some(err);
+                    } else {
+                        gate.audit(username, action);
+                        if args.duplex {
+                            // Arming duplex only makes sense with the gate
+                            // open, which is why the duplex button opens
+                            // it, too.
+                            if let Err(err) = gate.arm_duplex() {
+                                error = Some(err);
+                            } else {
+                                gate.audit(
+                                    username,
+                                    String::from("armed duplex"),
+                                );
+                            }
                         }
-                        _ => {}
                     }
                 }
+                Some(false) => {
+                    gate.assert_gate_closed();
+                    gate.audit(username, String::from("locked"));
+                }
+                None => {}
             }
         }
 
-        let status_code: hyper::StatusCode = if response.error.is_some() {
+        let mut response: GateWebAppResponse = self.current_response(
+            gate,
+            route.role,
+            error.map(|e| format!("{}", e)),
+        );
+        response.link = link;
+
+        let status_code: hyper::StatusCode = if let Some(refused) = refused {
+            refused
+        } else if response.error.is_some() {
             hyper::StatusCode::BAD_REQUEST
         } else {
             hyper::StatusCode::OK
         };
 
-        if is_browser {
+        if self.is_browser(headers) {
             const CACHE_CONTROL: &str = "no-store";
             if need_redirect {
                 if let Some(error) = response.error {
@@ -463,7 +1718,7 @@ impl GateWebListener {
                         .status(status_code)
                         .header(hyper::header::CONTENT_TYPE, "text/plain")
                         .header(hyper::header::CACHE_CONTROL, CACHE_CONTROL)
-                        .body(error)
+                        .body(error.into())
                 } else {
                     hyper::Response::builder()
                         .status(hyper::StatusCode::FOUND)
@@ -471,16 +1726,32 @@ impl GateWebListener {
                         .header(hyper::header::CACHE_CONTROL, CACHE_CONTROL)
                         .header(
                             hyper::header::LOCATION,
-                            self.calculate_redirect(req),
+                            self.calculate_redirect(&req, &route.base),
+                        )
+                        .body(
+                            response
+                                .to_browser(
+                                    &gate.name,
+                                    self.dashboard,
+                                    route.csrf_token.as_deref(),
+                                )
+                                .into(),
                         )
-                        .body(response.to_browser(self.as_ref()))
                 }
             } else {
                 hyper::Response::builder()
                     .status(status_code)
                     .header(hyper::header::CONTENT_TYPE, "text/html")
                     .header(hyper::header::CACHE_CONTROL, CACHE_CONTROL)
-                    .body(response.to_browser(self.as_ref()))
+                    .body(
+                        response
+                            .to_browser(
+                                &gate.name,
+                                self.dashboard,
+                                route.csrf_token.as_deref(),
+                            )
+                            .into(),
+                    )
             }
         } else {
             hyper::Response::builder()
@@ -488,27 +1759,772 @@ impl GateWebListener {
                 .header(hyper::header::CONTENT_TYPE, "application/json")
                 .body(
                     serde_json::to_string_pretty(&response)
-                        .expect("serde_json"),
+                        .expect("serde_json")
+                        .into(),
                 )
         }
     }
 
-    fn check_auth(&self, auth_header: &hyper::header::HeaderValue) -> bool {
-        let Ok(auth_header) = auth_header.to_str() else {
-            return false;
+    fn make_link(
+        &self,
+        gate: &crate::gate::Gate,
+        args: &GateWebAppMakeLink,
+    ) -> Result<GateWebAppLink, scan2blob::error::WuffError> {
+        let (Some(link_secret), Some(link_base_url)) =
+            (&self.link_secret, &self.link_base_url)
+        else {
+            return Err(scan2blob::error::WuffError::from(
+                "There's no link_secret, so there can't be any links",
+            ));
+        };
+        if args.hours.is_none() && args.uses.is_none() {
+            return Err(scan2blob::error::WuffError::from(
+                "A link has to run out sometime: give it a number of hours, a number of uses, or both",
+            ));
+        }
+        // Without a state file, how many times it's been used is forgotten
+        // whenever the server restarts, so a number of uses on its own would
+        // never run out.
+        if args.hours.is_none() && !gate.keeps_state() {
+            return Err(scan2blob::error::WuffError::from(
+                "This gate has no state_file, so a link needs a number of hours",
+            ));
+        }
+        // Better to find out about a bad name hint now than when someone
+        // tries to use the link.
+        let name_hint: Option<String> =
+            if let Some(ref name_hint) = args.name_hint {
+                gate.sanitize_name_hints(std::slice::from_ref(name_hint))?
+                    .pop()
+            } else {
+                None
+            };
+        let expires: Option<u64> = args.hours.map(|hours| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+                + (hours as u64) * 60 * 60
+        });
+        let signed_link: scan2blob::signed_link::SignedLink =
+            scan2blob::signed_link::SignedLink {
+                gate: gate.name.clone(),
+                // It only has to be unique, not unguessable, and the shorter
+                // the link is, the smaller the QR code.
+                id: scan2blob::util::random_token()[..16].to_string(),
+                expires,
+                uses: args.uses,
+                name_hint,
+            };
+        let url: String = format!(
+            "{}/link?{}",
+            link_base_url,
+            signed_link.to_query(link_secret.as_bytes())
+        );
+        let svg: String = qrcode::QrCode::new(url.as_bytes())
+            .map_err(|e| scan2blob::error::WuffError::from(format!("{}", e)))?
+            .render::<qrcode::render::svg::Color>()
+            .min_dimensions(240, 240)
+            .build();
+        // It's going in the middle of a page, where the XML declaration that
+        // it starts with doesn't belong.
+        let svg: String = match svg.find("<svg") {
+            Some(start) => svg[start..].to_string(),
+            None => svg,
         };
+        Ok(GateWebAppLink {
+            url,
+            expires,
+            uses: args.uses,
+            svg,
+        })
+    }
 
-        let Some((username, plaintext)) =
-            self.http_basic_auth.parse(auth_header)
+    // Following the link just asks whether to unlock the gate, and it's the
+    // button on that page that actually does it, so that nothing happens
+    // over GET. That also means that anything that looks at links before
+    // they're followed, to show a preview, doesn't use one up.
+    fn handle_link(
+        &self,
+        req: &hyper::Request<hyper::body::Incoming>,
+    ) -> Result<hyper::Response<ResponseBody>, hyper::http::Error> {
+        let respond = |status: hyper::StatusCode, page: String| {
+            hyper::Response::builder()
+                .status(status)
+                .header(hyper::header::CONTENT_TYPE, "text/html")
+                .header(hyper::header::CACHE_CONTROL, "no-store")
+                .body(page.into())
+        };
+        let Some(ref link_secret) = self.link_secret else {
+            return respond(
+                hyper::StatusCode::NOT_FOUND,
+                link_page("scan2blob", "There aren't any links here", None),
+            );
+        };
+        let query: &str = req.uri().query().unwrap_or_default();
+        let signed_link: scan2blob::signed_link::SignedLink =
+            match scan2blob::signed_link::SignedLink::from_query(
+                query,
+                link_secret.as_bytes(),
+            ) {
+                Ok(signed_link) => signed_link,
+                Err(err) => {
+                    return respond(
+                        hyper::StatusCode::FORBIDDEN,
+                        link_page("scan2blob", &err.message, None),
+                    );
+                }
+            };
+        let Some(gate) =
+            self.gates.iter().find(|gate| gate.name == signed_link.gate)
         else {
-            return false;
+            return respond(
+                hyper::StatusCode::NOT_FOUND,
+                link_page("scan2blob", "No such gate", None),
+            );
+        };
+        if signed_link.is_expired() {
+            return respond(
+                hyper::StatusCode::GONE,
+                link_page(&gate.name, "This link has expired", None),
+            );
+        }
+
+        if req.method() != hyper::Method::POST {
+            let question: String =
+                if let Some(ref name_hint) = signed_link.name_hint {
+                    format!("Unlock this gate, for {:?}?", name_hint)
+                } else {
+                    String::from("Unlock this gate?")
+                };
+            return respond(
+                hyper::StatusCode::OK,
+                link_page(
+                    &gate.name,
+                    &question,
+                    Some(&format!("/link?{}", query)),
+                ),
+            );
+        }
+
+        match gate.assert_gate_open_with_link(&signed_link) {
+            Ok(true) => {}
+            Ok(false) => {
+                return respond(
+                    hyper::StatusCode::GONE,
+                    link_page(&gate.name, "This link has been used up", None),
+                );
+            }
+            Err(err) => {
+                return respond(
+                    hyper::StatusCode::BAD_REQUEST,
+                    link_page(&gate.name, &err.message, None),
+                );
+            }
+        }
+        let mut action: String = String::from("unlocked");
+        if let Some(ref name_hint) = signed_link.name_hint {
+            action.push_str(&format!(", name hint {:?}", name_hint));
+        }
+        gate.audit(&format!("link {}", signed_link.id), action);
+        respond(
+            hyper::StatusCode::OK,
+            link_page(&gate.name, "Unlocked. Go ahead and scan.", None),
+        )
+    }
+
+    fn handle_login(
+        &self,
+        req: &hyper::Request<hyper::body::Incoming>,
+        body: &[u8],
+    ) -> Result<hyper::Response<ResponseBody>, hyper::http::Error> {
+        if req.method() != hyper::Method::POST {
+            return hyper::Response::builder()
+                .status(hyper::StatusCode::OK)
+                .header(hyper::header::CONTENT_TYPE, "text/html")
+                .header(hyper::header::CACHE_CONTROL, "no-store")
+                .body(login_page(None).into());
+        }
+        let username: Option<String> =
+            match serde_urlencoded::from_bytes::<LoginArgs>(body) {
+                Ok(args) => match self.users.get(&args.username) {
+                    Some(user)
+                        if scan2blob::pwhash::verify(
+                            &args.password,
+                            &user.password,
+                        ) && self.check_totp(
+                            &args.username,
+                            user,
+                            &args.code,
+                        ) =>
+                    {
+                        Some(args.username)
+                    }
+                    _ => {
+                        self.ctx.log_warn(format!(
+                            "web ui: failed login for {}",
+                            args.username
+                        ));
+                        None
+                    }
+                },
+                Err(_) => None,
+            };
+        let Some(username) = username else {
+            return hyper::Response::builder()
+                .status(hyper::StatusCode::UNAUTHORIZED)
+                .header(hyper::header::CONTENT_TYPE, "text/html")
+                .header(hyper::header::CACHE_CONTROL, "no-store")
+                .body(
+                    login_page(Some("Wrong username, password or code"))
+                        .into(),
+                );
         };
+        self.ctx.log_info(format!("web ui: {} logged in", username));
+        let token: String = self.sessions.create(&username);
+        hyper::Response::builder()
+            .status(hyper::StatusCode::FOUND)
+            .header(hyper::header::CACHE_CONTROL, "no-store")
+            .header(
+                hyper::header::SET_COOKIE,
+                // Lax rather than Strict, so that following a link to here
+                // from somewhere else doesn't mean logging in again. That's
+                // safe, since nothing changes anything over GET.
+                format!(
+                    "{}={}; Path=/; Max-Age={}; Secure; HttpOnly; SameSite=Lax",
+                    SESSION_COOKIE,
+                    token,
+                    self.sessions.lifetime().as_secs()
+                ),
+            )
+            .header(hyper::header::LOCATION, self.calculate_redirect(req, "/"))
+            .body("".into())
+    }
 
-        let Some(password) = self.users.get(&username) else {
+    fn handle_logout(
+        &self,
+        req: &hyper::Request<hyper::body::Incoming>,
+        body: &[u8],
+        requester: &Requester,
+    ) -> Result<hyper::Response<ResponseBody>, hyper::http::Error> {
+        let Some((ref token, ref session)) = requester.session else {
+            return hyper::Response::builder()
+                .status(hyper::StatusCode::BAD_REQUEST)
+                .header(hyper::header::CONTENT_TYPE, "text/plain")
+                .body("Only sessions can be logged out of".into());
+        };
+        // Logging someone out isn't the worst thing another site could do to
+        // them, but it's still not something it should be able to do.
+        let csrf_token: Option<String> =
+            serde_urlencoded::from_bytes::<GateWebAppArgsCgi>(body)
+                .ok()
+                .and_then(|args| args.csrf_token);
+        if req.method() != hyper::Method::POST
+            || csrf_token.as_deref() != Some(session.csrf_token.as_str())
+        {
+            return hyper::Response::builder()
+                .status(hyper::StatusCode::FOUND)
+                .header(hyper::header::CACHE_CONTROL, "no-store")
+                .header(
+                    hyper::header::LOCATION,
+                    self.calculate_redirect(req, "/"),
+                )
+                .body("".into());
+        }
+        self.sessions.remove(token);
+        self.ctx
+            .log_info(format!("web ui: {} logged out", requester.username));
+        hyper::Response::builder()
+            .status(hyper::StatusCode::FOUND)
+            .header(hyper::header::CACHE_CONTROL, "no-store")
+            .header(
+                hyper::header::SET_COOKIE,
+                format!(
+                    "{}=; Path=/; Max-Age=0; Secure; HttpOnly; SameSite=Lax",
+                    SESSION_COOKIE
+                ),
+            )
+            .header(
+                hyper::header::LOCATION,
+                self.calculate_redirect(req, "/login"),
+            )
+            .body("".into())
+    }
+
+    fn handle_dashboard(
+        &self,
+        req: &hyper::Request<hyper::body::Incoming>,
+        requester: &Requester,
+    ) -> Result<hyper::Response<ResponseBody>, hyper::http::Error> {
+        let mut response: WebUiDashboardResponse =
+            WebUiDashboardResponse { gates: Vec::new() };
+        for gate in &self.gates {
+            let Some(role) = self.role(requester, &gate.name) else {
+                continue;
+            };
+            let state: Option<Option<String>> = gate.get_current_state();
+            response.gates.push(WebUiDashboardGate {
+                name: gate.name.clone(),
+                open: state.is_some(),
+                name_hint: state.flatten(),
+                role,
+            });
+        }
+
+        if self.is_browser(req.headers()) {
+            hyper::Response::builder()
+                .status(hyper::StatusCode::OK)
+                .header(hyper::header::CONTENT_TYPE, "text/html")
+                .header(hyper::header::CACHE_CONTROL, "no-store")
+                .body(response.to_browser(requester.csrf_token()).into())
+        } else {
+            hyper::Response::builder()
+                .status(hyper::StatusCode::OK)
+                .header(hyper::header::CONTENT_TYPE, "application/json")
+                .body(
+                    serde_json::to_string_pretty(&response)
+                        .expect("serde_json")
+                        .into(),
+                )
+        }
+    }
+
+    fn start_event_stream(
+        self: std::sync::Arc<Self>,
+        route: GateRoute,
+    ) -> Result<hyper::Response<ResponseBody>, hyper::http::Error> {
+        let (tx, rx) = tokio::sync::mpsc::channel(EVENT_STREAM_BUFFER);
+        // Subscribing now, rather than once the task gets going, means that
+        // nothing gets missed in between.
+        let events: tokio::sync::broadcast::Receiver<crate::gate::GateEvent> =
+            route.gate.subscribe();
+        let async_spawner = self.ctx.base_ctx.get_async_spawner();
+        async_spawner.spawn(
+            std::sync::Arc::clone(&self)
+                .stream_events(route.gate, route.role, events, tx),
+        );
+        hyper::Response::builder()
+            .status(hyper::StatusCode::OK)
+            .header(hyper::header::CONTENT_TYPE, "text/event-stream")
+            .header(hyper::header::CACHE_CONTROL, "no-store")
+            .body(ResponseBody::Stream(rx))
+    }
+
+    fn start_hold(
+        self: std::sync::Arc<Self>,
+        req: &hyper::Request<hyper::body::Incoming>,
+        body: &[u8],
+        route: GateRoute,
+    ) -> Result<hyper::Response<ResponseBody>, hyper::http::Error> {
+        let refuse = |status_code: hyper::StatusCode, error: String| {
+            hyper::Response::builder()
+                .status(status_code)
+                .header(hyper::header::CONTENT_TYPE, "text/plain")
+                .header(hyper::header::CACHE_CONTROL, "no-store")
+                .body(error.into())
+        };
+        let headers: &hyper::HeaderMap = req.headers();
+        let args: Result<GateWebAppHoldArgs, scan2blob::error::WuffError> =
+            match headers
+                .get(hyper::header::CONTENT_TYPE)
+                .map(AsRef::<[u8]>::as_ref)
+            {
+                Some(b"application/json") => serde_json::from_slice(body)
+                    .map_err(scan2blob::error::WuffError::from),
+                Some(b"application/x-www-form-urlencoded") => {
+                    serde_urlencoded::from_bytes(body)
+                        .map_err(scan2blob::error::WuffError::from)
+                }
+                _ => serde_urlencoded::from_str(
+                    req.uri().query().unwrap_or_default(),
+                )
+                .map_err(scan2blob::error::WuffError::from),
+            };
+        let args: GateWebAppHoldArgs = match args {
+            Ok(args) => args,
+            Err(err) => {
+                return refuse(hyper::StatusCode::BAD_REQUEST, err.message);
+            }
+        };
+        let csrf_token: Option<&str> =
+            args.csrf_token.as_deref().or_else(|| {
+                headers
+                    .get(CSRF_TOKEN_HEADER)
+                    .and_then(|csrf_token| csrf_token.to_str().ok())
+            });
+        if req.method() != hyper::Method::POST {
+            return refuse(
+                hyper::StatusCode::METHOD_NOT_ALLOWED,
+                String::from("Changes have to be POSTed"),
+            );
+        }
+        if let Some(ref expected) = route.csrf_token
+            && csrf_token != Some(expected.as_str())
+        {
+            return refuse(
+                hyper::StatusCode::FORBIDDEN,
+                String::from("Missing or wrong CSRF token"),
+            );
+        }
+        if route.role < users::Role::Operator {
+            return refuse(
+                hyper::StatusCode::FORBIDDEN,
+                format!(
+                    "That needs the {} role, and yours is {}",
+                    users::Role::Operator.name(),
+                    route.role.name()
+                ),
+            );
+        }
+        let guard: crate::gate::GateAssertionGuard = match route
+            .gate
+            .hold_open(&route.username, args.name_hint.into_iter().collect())
+        {
+            Ok(guard) => guard,
+            Err(err) => {
+                return refuse(hyper::StatusCode::BAD_REQUEST, err.message);
+            }
+        };
+        let (tx, rx) = tokio::sync::mpsc::channel(EVENT_STREAM_BUFFER);
+        let events: tokio::sync::broadcast::Receiver<crate::gate::GateEvent> =
+            route.gate.subscribe();
+        let async_spawner = self.ctx.base_ctx.get_async_spawner();
+        async_spawner.spawn(
+            std::sync::Arc::clone(&self)
+                .stream_events_while_holding(route, events, tx, guard),
+        );
+        hyper::Response::builder()
+            .status(hyper::StatusCode::OK)
+            .header(hyper::header::CONTENT_TYPE, "text/event-stream")
+            .header(hyper::header::CACHE_CONTROL, "no-store")
+            .body(ResponseBody::Stream(rx))
+    }
+
+    // The event stream on its own only finds out that the client's gone
+    // when it next has something to send, which could be a while, so this
+    // also watches for that, so that the gate closes right away. If someone
+    // closes the gate instead, that's the end of holding it open, so the
+    // stream ends, after telling the client what the gate's state is now.
+    async fn stream_events_while_holding(
+        self: std::sync::Arc<Self>,
+        route: GateRoute,
+        events: tokio::sync::broadcast::Receiver<crate::gate::GateEvent>,
+        tx: tokio::sync::mpsc::Sender<bytes::Bytes>,
+        guard: crate::gate::GateAssertionGuard,
+    ) {
+        let gate: std::sync::Arc<crate::gate::Gate> =
+            std::sync::Arc::clone(&route.gate);
+        let hung_up: tokio::sync::mpsc::Sender<bytes::Bytes> = tx.clone();
+        let released: bool = futures::select! {
+            _ = futures::FutureExt::fuse(std::sync::Arc::clone(&self)
+                .stream_events(route.gate, route.role, events, tx)
+            ) => false,
+            _ = futures::FutureExt::fuse(hung_up.closed()) => false,
+            _ = futures::FutureExt::fuse(guard.released()) => true,
+        };
+        drop(guard);
+        if released {
+            let state: GateWebAppResponse =
+                self.current_response(&gate, route.role, None);
+            let _ = hung_up
+                .send(
+                    format!(
+                        "event: state\ndata: {}\n\n",
+                        serde_json::to_string(&state).expect("serde_json")
+                    )
+                    .into(),
+                )
+                .await;
+        }
+    }
+
+    // Sends the gate's state (the same as the JSON response) as a "state"
+    // event to begin with, and again whenever it changes, and an "upload"
+    // event whenever a blob starts being uploaded, gets committed, or fails.
+    // This keeps going until the other end goes away.
+    async fn stream_events(
+        self: std::sync::Arc<Self>,
+        gate: std::sync::Arc<crate::gate::Gate>,
+        role: users::Role,
+        mut events: tokio::sync::broadcast::Receiver<crate::gate::GateEvent>,
+        tx: tokio::sync::mpsc::Sender<bytes::Bytes>,
+    ) {
+        let mut last_state: Option<GateWebAppResponse> = None;
+        let mut last_sent: std::time::Instant = std::time::Instant::now();
+        loop {
+            let state: GateWebAppResponse =
+                self.current_response(&gate, role, None);
+            let state_changed: bool = if let Some(ref last_state) = last_state
+            {
+                !state.is_same_state(last_state)
+            } else {
+                true
+            };
+            let message: Option<String> = if state_changed {
+                let message: String = format!(
+                    "event: state\ndata: {}\n\n",
+                    serde_json::to_string(&state).expect("serde_json")
+                );
+                last_state = Some(state);
+                Some(message)
+            } else if last_sent.elapsed() >= EVENT_STREAM_KEEPALIVE {
+                // Anything starting with a colon is a comment.
+                Some(String::from(":\n\n"))
+            } else {
+                None
+            };
+            if let Some(message) = message {
+                if tx.send(message.into()).await.is_err() {
+                    return;
+                }
+                last_sent = std::time::Instant::now();
+            }
+
+            // Whatever happens first: something happens to the gate, the
+            // gate's state changes by itself, or it's time for a keepalive.
+            let (_state, next_change_time) = gate.get_current_state_extended();
+            let mut timeout: std::time::Duration = (last_sent
+                + EVENT_STREAM_KEEPALIVE)
+                .saturating_duration_since(std::time::Instant::now());
+            if let Some(next_change_time) = next_change_time {
+                timeout = std::cmp::min(
+                    timeout,
+                    next_change_time + EVENT_STREAM_SLACK,
+                );
+            }
+            match tokio::time::timeout(timeout, events.recv()).await {
+                Ok(Ok(crate::gate::GateEvent::Upload(upload))) => {
+                    let message: String = format!(
+                        "event: upload\ndata: {}\n\n",
+                        serde_json::to_string(&upload).expect("serde_json")
+                    );
+                    if tx.send(message.into()).await.is_err() {
+                        return;
+                    }
+                    last_sent = std::time::Instant::now();
+                }
+                Ok(Ok(crate::gate::GateEvent::StateChanged)) => {}
+                // If we've missed some, then whatever the state is now is
+                // still the state, and the same goes for the uploads, so
+                // they all get sent again.
+                Ok(Err(tokio::sync::broadcast::error::RecvError::Lagged(
+                    _,
+                ))) => {
+                    for upload in gate.recent_uploads().into_iter().rev() {
+                        let message: String = format!(
+                            "event: upload\ndata: {}\n\n",
+                            serde_json::to_string(&upload)
+                                .expect("serde_json")
+                        );
+                        if tx.send(message.into()).await.is_err() {
+                            return;
+                        }
+                    }
+                    last_sent = std::time::Instant::now();
+                }
+                Ok(Err(tokio::sync::broadcast::error::RecvError::Closed)) => {
+                    return;
+                }
+                Err(_) => {}
+            }
+        }
+    }
+
+    fn current_response(
+        &self,
+        gate: &crate::gate::Gate,
+        role: users::Role,
+        error: Option<String>,
+    ) -> GateWebAppResponse {
+        let audit_log: Option<Vec<GateWebAppAuditEntry>> =
+            if role >= users::Role::Admin {
+                Some(
+                    gate.audit_log()
+                        .into_iter()
+                        .map(GateWebAppAuditEntry::from)
+                        .collect(),
+                )
+            } else {
+                None
+            };
+        let (state, next_change_time) = gate.get_current_state_extended();
+        let recent_uploads: Vec<GateWebAppUpload> = gate
+            .recent_uploads()
+            .into_iter()
+            .map(GateWebAppUpload::from)
+            .collect();
+        let next_change_time: Option<u64> = next_change_time.map(|d| {
+            (std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                + d)
+                .as_secs()
+        });
+        let files_remaining: Option<u32> = gate.files_remaining();
+        let categories: Vec<String> = gate.category_names();
+        let held_files: Option<usize> = gate.num_held_files();
+        let duplex_passes_expected: Option<usize> =
+            gate.duplex_passes_expected();
+        let next_scheduled_change: Option<GateWebAppScheduledChange> = gate
+            .next_scheduled_change()
+            .map(|(opens, time)| GateWebAppScheduledChange {
+                opens,
+                time: time.timestamp().as_second() as u64,
+                local_time: time.strftime("%a %b %-d %H:%M %Z").to_string(),
+            });
+        if let Some(name_hints) = state {
+            GateWebAppResponse {
+                open: true,
+                category: gate.current_category(),
+                categories,
+                name_hint: name_hints.first().cloned(),
+                name_hints,
+                error,
+                next_change_time,
+                files_remaining,
+                held_files,
+                duplex_passes_expected,
+                next_scheduled_change,
+                recent_uploads,
+                role,
+                audit_log,
+                link: None,
+                links_enabled: self.link_secret.is_some(),
+            }
+        } else {
+            GateWebAppResponse {
+                open: false,
+                category: None,
+                categories,
+                name_hint: None,
+                name_hints: Vec::new(),
+                error,
+                next_change_time,
+                files_remaining,
+                held_files,
+                duplex_passes_expected,
+                next_scheduled_change,
+                recent_uploads,
+                role,
+                audit_log,
+                link: None,
+                links_enabled: self.link_secret.is_some(),
+            }
+        }
+    }
+
+    // None if the requester can't even see the gate.
+    fn role(
+        &self,
+        requester: &Requester,
+        gate_name: &str,
+    ) -> Option<users::Role> {
+        if let Some(ref api_token) = requester.api_token {
+            api_token.role(gate_name)
+        } else {
+            self.users[&requester.username].role(gate_name)
+        }
+    }
+
+    // An Authorization header, if there's one, and otherwise a session
+    // cookie.
+    fn authenticate(&self, headers: &hyper::HeaderMap) -> Option<Requester> {
+        if let Some(auth_header) = headers.get(hyper::header::AUTHORIZATION) {
+            return self.check_auth(auth_header);
+        }
+        let cookie_header: &str =
+            headers.get(hyper::header::COOKIE)?.to_str().ok()?;
+        let token: &str =
+            scan2blob::http_cookie::get(cookie_header, SESSION_COOKIE)?;
+        let session: sessions::Session = self.sessions.get(token)?;
+        Some(Requester {
+            username: session.username.clone(),
+            session: Some((token.to_string(), session)),
+            api_token: None,
+        })
+    }
+
+    // Users without a TOTP secret don't need a code.
+    fn check_totp(
+        &self,
+        username: &str,
+        user: &users::WebUser,
+        code: &str,
+    ) -> bool {
+        let Some(ref secret) = user.totp else {
+            return true;
+        };
+        let Some(step) = scan2blob::totp::verify(
+            secret,
+            code,
+            std::time::SystemTime::now(),
+        ) else {
             return false;
         };
+        let mut totp_steps = self.totp_steps.lock().unwrap();
+        if totp_steps
+            .get(username)
+            .is_some_and(|last_step| step <= *last_step)
+        {
+            self.ctx.log_warn(format!(
+                "web ui: {} tried to reuse a TOTP code",
+                username
+            ));
+            return false;
+        }
+        let _ = totp_steps.insert(username.to_string(), step);
+        true
+    }
 
-        scan2blob::pwhash::verify(&plaintext, password)
+    fn check_auth(
+        &self,
+        auth_header: &hyper::header::HeaderValue,
+    ) -> Option<Requester> {
+        let auth_header: &str = auth_header.to_str().ok()?;
+        match self.http_auth.parse(auth_header)? {
+            scan2blob::http_auth::HttpCredentials::Basic {
+                username,
+                password,
+            } => {
+                let user: &users::WebUser = self.users.get(&username)?;
+                // There'd have to be a new code with every request, which
+                // isn't something that a script can do. Those can have an API
+                // token instead.
+                if user.totp.is_some() {
+                    self.ctx.log_debug(format!(
+                        "web ui: {} has TOTP, and can't use basic auth",
+                        username
+                    ));
+                    return None;
+                }
+                if scan2blob::pwhash::verify(&password, &user.password) {
+                    Some(Requester {
+                        username,
+                        session: None,
+                        api_token: None,
+                    })
+                } else {
+                    None
+                }
+            }
+            scan2blob::http_auth::HttpCredentials::Bearer(token) => {
+                let (name, api_token) = self
+                    .api_tokens
+                    .get(&scan2blob::api_token::hash(&token))?;
+                if api_token.is_expired() {
+                    self.ctx.log_debug(format!(
+                        "web ui: api token {} has expired",
+                        name
+                    ));
+                    return None;
+                }
+                Some(Requester {
+                    username: format!("token {}", name),
+                    session: None,
+                    api_token: Some(api_token.clone()),
+                })
+            }
+        }
     }
 }
 
@@ -556,3 +2572,43 @@ impl<'a> std::future::Future for Body<'a> {
         std::task::Poll::Ready(temp)
     }
 }
+
+// What goes back to the client: usually all of it at once, but for an event
+// stream, a bit at a time, for as long as the connection lasts.
+enum ResponseBody {
+    Full(Option<bytes::Bytes>),
+    Stream(tokio::sync::mpsc::Receiver<bytes::Bytes>),
+}
+
+impl From<String> for ResponseBody {
+    fn from(s: String) -> Self {
+        Self::Full(Some(s.into()))
+    }
+}
+
+impl From<&str> for ResponseBody {
+    fn from(s: &str) -> Self {
+        Self::Full(Some(bytes::Bytes::copy_from_slice(s.as_bytes())))
+    }
+}
+
+impl hyper::body::Body for ResponseBody {
+    type Data = bytes::Bytes;
+    type Error = std::convert::Infallible;
+
+    fn poll_frame(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<
+        Option<Result<hyper::body::Frame<bytes::Bytes>, Self::Error>>,
+    > {
+        match self.get_mut() {
+            Self::Full(data) => std::task::Poll::Ready(
+                data.take().map(|data| Ok(hyper::body::Frame::data(data))),
+            ),
+            Self::Stream(rx) => rx.poll_recv(cx).map(|data| {
+                data.map(|data| Ok(hyper::body::Frame::data(data)))
+            }),
+        }
+    }
+}
@@ -0,0 +1,65 @@
+// Who's logged in to a web UI through its login form. Each session has a
+// token that goes in a cookie, and a separate CSRF token that goes in every
+// form on the page, so that it's not enough for some other site to get the
+// browser to send the cookie along: it has to know the CSRF token, too, and
+// it has no way of finding that out.
+//
+// Sessions are only kept in memory, so restarting logs everyone out.
+
+#[derive(Clone, Debug)]
+pub struct Session {
+    pub username: String,
+    pub csrf_token: String,
+    expires_at: std::time::Instant,
+}
+
+pub struct Sessions {
+    lifetime: std::time::Duration,
+    // By session token.
+    sessions: std::sync::Mutex<std::collections::HashMap<String, Session>>,
+}
+
+impl Sessions {
+    pub fn new(lifetime: std::time::Duration) -> Self {
+        Self {
+            lifetime,
+            sessions: std::sync::Mutex::new(std::collections::HashMap::new()),
+        }
+    }
+
+    pub fn lifetime(&self) -> std::time::Duration {
+        self.lifetime
+    }
+
+    // The new session's token.
+    pub fn create(&self, username: &str) -> String {
+        let now: std::time::Instant = std::time::Instant::now();
+        let token: String = scan2blob::util::random_token();
+        let mut sessions = self.sessions.lock().unwrap();
+        // Nothing else gets rid of the ones that nobody logged out of.
+        sessions.retain(|_, session| session.expires_at > now);
+        let _ = sessions.insert(
+            token.clone(),
+            Session {
+                username: username.to_string(),
+                csrf_token: scan2blob::util::random_token(),
+                expires_at: now + self.lifetime,
+            },
+        );
+        token
+    }
+
+    pub fn get(&self, token: &str) -> Option<Session> {
+        let sessions = self.sessions.lock().unwrap();
+        let session: &Session = sessions.get(token)?;
+        if session.expires_at > std::time::Instant::now() {
+            Some(session.clone())
+        } else {
+            None
+        }
+    }
+
+    pub fn remove(&self, token: &str) {
+        let _ = self.sessions.lock().unwrap().remove(token);
+    }
+}
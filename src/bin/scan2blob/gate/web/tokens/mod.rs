@@ -0,0 +1,90 @@
+// API tokens, for things like home automation that talk to the JSON API and
+// would rather not have a password. Each one is scoped to particular gates,
+// and for each of those, to either just looking at it, or opening and closing
+// it, too. Tokens can expire.
+//
+// Only the token's hash is in the config; see scan2blob::api_token.
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    Status,
+    Toggle,
+}
+
+impl Scope {
+    pub fn role(&self) -> super::users::Role {
+        match self {
+            Scope::Status => super::users::Role::Viewer,
+            Scope::Toggle => super::users::Role::Operator,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct ConfigApiToken {
+    hash: String,
+    gates: std::collections::HashMap<String, Scope>,
+    // An RFC 3339 timestamp, like "2026-01-01T00:00:00Z".
+    expires: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct ApiToken {
+    pub hash: String,
+    gates: std::collections::HashMap<String, Scope>,
+    expires: Option<jiff::Timestamp>,
+}
+
+impl TryFrom<ConfigApiToken> for ApiToken {
+    type Error = scan2blob::error::WuffError;
+    fn try_from(
+        config: ConfigApiToken,
+    ) -> Result<Self, scan2blob::error::WuffError> {
+        let ConfigApiToken {
+            hash,
+            gates,
+            expires,
+        } = config;
+        let hash: String = hash.to_ascii_lowercase();
+        if !scan2blob::api_token::is_hash(&hash) {
+            return Err(scan2blob::error::WuffError::from(
+                "hash should be 64 hex digits (try scan2blob-mktoken)",
+            ));
+        }
+        let expires: Option<jiff::Timestamp> = if let Some(expires) = expires {
+            Some(expires.parse::<jiff::Timestamp>().map_err(|e| {
+                scan2blob::error::WuffError::from(format!(
+                    "expires {:?}: {}",
+                    expires, e
+                ))
+            })?)
+        } else {
+            None
+        };
+        Ok(Self {
+            hash,
+            gates,
+            expires,
+        })
+    }
+}
+
+impl ApiToken {
+    // None if the token can't even see the gate.
+    pub fn role(&self, gate_name: &str) -> Option<super::users::Role> {
+        self.gates.get(gate_name).map(Scope::role)
+    }
+
+    pub fn gate_names(&self) -> impl Iterator<Item = &String> {
+        self.gates.keys()
+    }
+
+    pub fn is_expired(&self) -> bool {
+        if let Some(expires) = self.expires {
+            jiff::Timestamp::now() >= expires
+        } else {
+            false
+        }
+    }
+}
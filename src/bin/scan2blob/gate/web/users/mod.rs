@@ -0,0 +1,111 @@
+// Who can log in to a web UI, and what they can do with which gates once
+// they have. A user can either be just a password hash, for someone who's an
+// operator of every gate that the web UI has (which is all that anyone could
+// do before there were roles), or a password hash along with a list of gates
+// and which role they have for each one, and/or a TOTP secret that they have
+// to give a code from whenever they log in. Nobody's an admin of a gate
+// unless they've been listed as one.
+
+// In order, so that each role can do everything that the ones before it can.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    serde::Deserialize,
+    serde::Serialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    // See what state the gate is in, and nothing else.
+    Viewer,
+    // Open and close it, with a name hint if they want.
+    Operator,
+    // Discard uploads, fill in a whole queue of name hints, and see who's
+    // been doing what.
+    Admin,
+}
+
+impl Role {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Operator => "operator",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+pub enum ConfigWebUser {
+    Password(String),
+    Detailed {
+        password: String,
+        #[serde(default)]
+        gates: Option<std::collections::HashMap<String, Role>>,
+        // Base32, the way scan2blob-mkpass --totp prints it.
+        #[serde(default)]
+        totp: Option<String>,
+    },
+}
+
+#[derive(Clone)]
+pub struct WebUser {
+    pub password: String,
+    // None means operator of all of them.
+    gates: Option<std::collections::HashMap<String, Role>>,
+    pub totp: Option<String>,
+}
+
+impl TryFrom<ConfigWebUser> for WebUser {
+    type Error = scan2blob::error::WuffError;
+    fn try_from(
+        config: ConfigWebUser,
+    ) -> Result<Self, scan2blob::error::WuffError> {
+        match config {
+            ConfigWebUser::Password(password) => Ok(Self {
+                password,
+                gates: None,
+                totp: None,
+            }),
+            ConfigWebUser::Detailed {
+                password,
+                gates,
+                totp,
+            } => {
+                if let Some(ref totp) = totp
+                    && scan2blob::totp::base32_decode(totp)
+                        .is_none_or(|secret| secret.len() < 10)
+                {
+                    return Err(scan2blob::error::WuffError::from(
+                        "totp should be a base32 secret of at least 16 characters",
+                    ));
+                }
+                Ok(Self {
+                    password,
+                    gates,
+                    totp,
+                })
+            }
+        }
+    }
+}
+
+impl WebUser {
+    // None if the user can't even see the gate.
+    pub fn role(&self, gate_name: &str) -> Option<Role> {
+        if let Some(ref gates) = self.gates {
+            gates.get(gate_name).copied()
+        } else {
+            Some(Role::Operator)
+        }
+    }
+
+    pub fn gate_names(&self) -> impl Iterator<Item = &String> {
+        self.gates.iter().flat_map(|gates| gates.keys())
+    }
+}
@@ -5,11 +5,18 @@
 struct DestinationAndGate {
     destination: std::sync::Arc<crate::destination::Destination>,
     gate: std::sync::Arc<crate::gate::Gate>,
+    username: String,
+    // What the user can do to the gate with "ssh user@host <command>", if
+    // anything.
+    commands: Option<crate::gate::web::users::Role>,
 }
 
 struct SshConnection {
     sftp_listener: std::sync::Arc<SftpListener>,
+    peer: std::net::SocketAddr,
     authenticated_destination_and_gate: Option<DestinationAndGate>,
+    // For as long as the connection's open, the admin socket can see it.
+    connection: crate::activity::ConnectionGuard,
 
     // These are channels that have been opened with SSH_MSG_CHANNEL_OPEN, but
     // not yet attached to a subsystem with SSH_MSG_CHANNEL_REQUEST. (Obviously
@@ -18,14 +25,30 @@ struct SshConnection {
         russh::ChannelId,
         russh::Channel<russh::server::Msg>,
     >,
+    // Channels that are running "hold", each of which holds the gate open
+    // until the channel's closed (by either end, and the server closes it if
+    // someone closes the gate), or the whole connection goes away.
+    holding_channels: std::collections::HashMap<
+        russh::ChannelId,
+        crate::gate::GateAssertionGuard,
+    >,
 }
 
 impl SshConnection {
-    fn new(sftp_listener: &std::sync::Arc<SftpListener>) -> Self {
+    fn new(
+        sftp_listener: &std::sync::Arc<SftpListener>,
+        peer: std::net::SocketAddr,
+    ) -> Self {
         Self {
             sftp_listener: std::sync::Arc::clone(sftp_listener),
+            peer,
             authenticated_destination_and_gate: None,
+            connection: sftp_listener
+                .ctx
+                .activity
+                .connection(crate::upload_context::Listener::Sftp, peer),
             pending_channels: std::collections::HashMap::new(),
+            holding_channels: std::collections::HashMap::new(),
         }
     }
 }
@@ -55,6 +78,7 @@ impl russh::server::Handler for SshConnection {
             if user.authorized_keys.contains(public_key.key_data()) {
                 self.authenticated_destination_and_gate =
                     Some(user.destination_and_gate.clone());
+                self.connection.set_username(username);
                 return Ok(russh::server::Auth::Accept);
             }
         }
@@ -75,7 +99,20 @@ impl russh::server::Handler for SshConnection {
         channel: russh::ChannelId,
         session: &mut russh::server::Session,
     ) -> Result<(), Self::Error> {
-        session.close(channel)?;
+        // "ssh -n" sends EOF right away, and that's not a reason to stop
+        // holding the gate open.
+        if !self.holding_channels.contains_key(&channel) {
+            session.close(channel)?;
+        }
+        Ok(())
+    }
+
+    async fn channel_close(
+        &mut self,
+        channel: russh::ChannelId,
+        _session: &mut russh::server::Session,
+    ) -> Result<(), Self::Error> {
+        let _ = self.holding_channels.remove(&channel);
         Ok(())
     }
 
@@ -105,12 +142,186 @@ impl russh::server::Handler for SshConnection {
         let sftp_session: SftpSession = SftpSession::new(
             &self.sftp_listener.ctx,
             authenticated_destination_and_gate,
+            self.peer,
         );
         session.channel_success(channel_id)?;
         russh_sftp::server::run(channel.into_stream(), sftp_session).await;
 
         Ok(())
     }
+
+    // Instead of the sftp subsystem, the client can run one of a few
+    // commands, which is a way of opening and closing the gate from a
+    // terminal with the same key that the scanner uses.
+    async fn exec_request(
+        &mut self,
+        channel_id: russh::ChannelId,
+        data: &[u8],
+        session: &mut russh::server::Session,
+    ) -> Result<(), Self::Error> {
+        if self.pending_channels.remove(&channel_id).is_none() {
+            session.channel_failure(channel_id)?;
+            return Ok(());
+        }
+
+        let Some(authenticated_destination_and_gate) =
+            self.authenticated_destination_and_gate.as_ref()
+        else {
+            session.channel_failure(channel_id)?;
+            return Ok(());
+        };
+
+        session.channel_success(channel_id)?;
+        let command: std::borrow::Cow<str> = String::from_utf8_lossy(data);
+        match run_command(authenticated_destination_and_gate, &command) {
+            Ok((output, Some(guard))) => {
+                // This one keeps going until the client hangs up, or until
+                // someone closes the gate, which is the end of holding it
+                // open, so the client gets told, and the channel ends.
+                session.data(channel_id, russh::CryptoVec::from(output))?;
+                let released = guard.released();
+                let handle: russh::server::Handle = session.handle();
+                let async_spawner =
+                    self.sftp_listener.ctx.base_ctx.get_async_spawner();
+                async_spawner.spawn(async move {
+                    released.await;
+                    // 1 is stderr.
+                    let _ = handle
+                        .extended_data(
+                            channel_id,
+                            1,
+                            russh::CryptoVec::from(
+                                "the gate has been closed\n",
+                            ),
+                        )
+                        .await;
+                    let _ = handle.exit_status_request(channel_id, 1).await;
+                    let _ = handle.eof(channel_id).await;
+                    let _ = handle.close(channel_id).await;
+                });
+                let _ = self.holding_channels.insert(channel_id, guard);
+                return Ok(());
+            }
+            Ok((output, None)) => {
+                session.data(channel_id, russh::CryptoVec::from(output))?;
+                session.exit_status_request(channel_id, 0)?;
+            }
+            Err(err) => {
+                self.sftp_listener.ctx.log_info(format!(
+                    "sftp: {}: {}",
+                    authenticated_destination_and_gate.username, err
+                ));
+                // 1 is stderr.
+                session.extended_data(
+                    channel_id,
+                    1,
+                    russh::CryptoVec::from(format!("{}\n", err)),
+                )?;
+                session.exit_status_request(channel_id, 1)?;
+            }
+        }
+        session.eof(channel_id)?;
+        session.close(channel_id)?;
+        Ok(())
+    }
+}
+
+// The client's shell has already taken the quotes off of anything that the
+// user put in quotes, and ssh has put the words back together with spaces
+// in between, so everything after the command is the name hint. If the
+// user quoted it twice, so that the quotes got through, those come off, too.
+//
+// "hold" is the same as "open", except that the gate stays open for exactly
+// as long as the command keeps running, which is what the guard is for.
+fn run_command(
+    destination_and_gate: &DestinationAndGate,
+    command: &str,
+) -> Result<
+    (String, Option<crate::gate::GateAssertionGuard>),
+    scan2blob::error::WuffError,
+> {
+    let (command, args) = command
+        .trim()
+        .split_once(char::is_whitespace)
+        .map_or((command.trim(), ""), |(command, args)| {
+            (command, args.trim())
+        });
+    let role_needed: crate::gate::web::users::Role = match command {
+        "status" => crate::gate::web::users::Role::Viewer,
+        "open" | "hold" | "close" => crate::gate::web::users::Role::Operator,
+        _ => {
+            return Err(scan2blob::error::WuffError::from(format!(
+                "{:?}: expected open [name hint], hold [name hint], close or status",
+                command
+            )));
+        }
+    };
+    if destination_and_gate
+        .commands
+        .is_none_or(|role| role < role_needed)
+    {
+        return Err(scan2blob::error::WuffError::from(format!(
+            "{}: not allowed",
+            command
+        )));
+    }
+    let gate: &std::sync::Arc<crate::gate::Gate> = &destination_and_gate.gate;
+    let who: String = format!("{} (ssh)", destination_and_gate.username);
+    let name_hint: &str = args
+        .strip_prefix('"')
+        .and_then(|args| args.strip_suffix('"'))
+        .unwrap_or(args);
+    let name_hints: Vec<String> = if name_hint.is_empty() {
+        Vec::new()
+    } else {
+        vec![name_hint.to_string()]
+    };
+    match command {
+        "open" => {
+            let mut action: String = String::from("unlocked");
+            if !name_hints.is_empty() {
+                action.push_str(&format!(", name hints {:?}", name_hints));
+            }
+            gate.assert_gate_open_timed_with_name_hints(
+                name_hints, None, None, None,
+            )?;
+            gate.audit(&who, action);
+        }
+        "hold" => {
+            let guard: crate::gate::GateAssertionGuard =
+                gate.hold_open(&who, name_hints)?;
+            let mut output: String = gate_status(gate);
+            output.push_str("(until this is interrupted)\n");
+            return Ok((output, Some(guard)));
+        }
+        "close" => {
+            gate.assert_gate_closed();
+            gate.audit(&who, String::from("locked"));
+        }
+        _ => {}
+    }
+    Ok((gate_status(gate), None))
+}
+
+fn gate_status(gate: &crate::gate::Gate) -> String {
+    let (state, next_change_time) = gate.get_current_state_extended();
+    let mut status: String = format!(
+        "{}: {}",
+        gate.name,
+        if state.is_some() { "open" } else { "closed" }
+    );
+    if let Some(next_change_time) = next_change_time {
+        status.push_str(&format!(
+            ", {} in {}s",
+            if state.is_some() { "closes" } else { "opens" },
+            next_change_time.as_secs()
+        ));
+    }
+    if let Some(name_hint) = state.and_then(|state| state.into_iter().next()) {
+        status.push_str(&format!(", name hint {:?}", name_hint));
+    }
+    status.push('\n');
+    status
 }
 
 struct OpenFile {
@@ -123,6 +334,7 @@ struct OpenFile {
 struct SftpSession {
     ctx: std::sync::Arc<crate::ctx::Ctx>,
     destination_and_gate: DestinationAndGate,
+    peer: std::net::SocketAddr,
     open_files: std::collections::HashMap<String, OpenFile>,
     next_handle: std::sync::atomic::AtomicU64,
 }
@@ -131,10 +343,12 @@ impl SftpSession {
     fn new(
         ctx: &std::sync::Arc<crate::ctx::Ctx>,
         destination_and_gate: &DestinationAndGate,
+        peer: std::net::SocketAddr,
     ) -> Self {
         Self {
             ctx: std::sync::Arc::clone(&ctx),
             destination_and_gate: destination_and_gate.clone(),
+            peer,
             open_files: std::collections::HashMap::new(),
             next_handle: std::sync::atomic::AtomicU64::new(0),
         }
@@ -170,11 +384,17 @@ impl russh_sftp::server::Handler for SftpSession {
         {
             return Err(self.unimplemented());
         }
-        let Some(writer) = self
-            .destination_and_gate
-            .gate
-            .try_write_file(&filename, &self.destination_and_gate.destination)
-        else {
+        let upload_context: crate::upload_context::UploadContext =
+            crate::upload_context::UploadContext::new(
+                crate::upload_context::Listener::Sftp,
+                &self.destination_and_gate.username,
+                Some(self.peer),
+                &filename,
+            );
+        let Some(writer) = self.destination_and_gate.gate.try_write_file(
+            upload_context,
+            &self.destination_and_gate.destination,
+        ) else {
             self.ctx.log_info(format!(
                 "sftp: rejecting file upload because gate {} is closed",
                 self.destination_and_gate.gate.name
@@ -384,7 +604,7 @@ impl SftpListenerEachPort {
                 .await
                 .expect(&format!("{}", self.listen_on));
         loop {
-            let Ok((sock, _peername)) = server_sock.accept().await else {
+            let Ok((sock, peername)) = server_sock.accept().await else {
                 // log something
                 tokio::time::sleep(std::time::Duration::from_secs(1)).await;
                 continue;
@@ -392,7 +612,7 @@ impl SftpListenerEachPort {
             let russh_config: std::sync::Arc<russh::server::Config> =
                 std::sync::Arc::clone(&self.sftp_listener.russh_config);
             let ssh_connection: SshConnection =
-                SshConnection::new(&self.sftp_listener);
+                SshConnection::new(&self.sftp_listener, peername);
             async_spawner.spawn(async move {
                 let Ok(fut) = russh::server::run_stream(
                     russh_config,
@@ -418,6 +638,9 @@ pub struct ConfigListenerSftpUser {
     authorized_keys: Vec<String>,
     destination: String,
     gate: String,
+    // A viewer can run "status", and an operator (or admin) can run "open",
+    // "hold" and "close" as well. Nobody can run anything, by default.
+    commands: Option<crate::gate::web::users::Role>,
 }
 
 #[derive(serde::Deserialize)]
@@ -431,6 +654,7 @@ pub struct ConfigListenerSftpUserEnriched {
     authorized_keys: Vec<russh::keys::PublicKey>,
     destination: String,
     gate: String,
+    commands: Option<crate::gate::web::users::Role>,
 }
 
 pub struct ConfigListenerSftpEnriched {
@@ -449,6 +673,7 @@ impl TryFrom<ConfigListenerSftpUser> for ConfigListenerSftpUserEnriched {
             authorized_keys,
             destination,
             gate,
+            commands,
         } = config;
 
         let mut authorized_keys_enriched: Vec<russh::keys::PublicKey> =
@@ -472,6 +697,7 @@ impl TryFrom<ConfigListenerSftpUser> for ConfigListenerSftpUserEnriched {
             authorized_keys: authorized_keys_enriched,
             destination,
             gate,
+            commands,
         })
     }
 }
@@ -550,6 +776,7 @@ impl SftpListener {
                 authorized_keys,
                 destination,
                 gate,
+                commands,
             },
         ) in &config.users
         {
@@ -577,7 +804,9 @@ impl SftpListener {
                             authorized_keys,
                             destination_and_gate: DestinationAndGate {
                                 destination,
-                                gate
+                                gate,
+                                username: username.clone(),
+                                commands: *commands,
                             }
                         },
                     )
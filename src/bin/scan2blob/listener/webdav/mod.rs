@@ -6,6 +6,9 @@
 struct DestinationAndGate {
     destination: std::sync::Arc<crate::destination::Destination>,
     gate: std::sync::Arc<crate::gate::Gate>,
+    username: String,
+    // Filled in for each request.
+    peer: Option<std::net::SocketAddr>,
 }
 
 #[derive(Debug, Clone)]
@@ -211,8 +214,15 @@ impl dav_server::fs::GuardedFileSystem<DestinationAndGate>
                 return Err(dav_server::fs::FsError::NotImplemented);
             };
 
+            let upload_context: crate::upload_context::UploadContext =
+                crate::upload_context::UploadContext::new(
+                    crate::upload_context::Listener::Webdav,
+                    &destination_and_gate.username,
+                    destination_and_gate.peer,
+                    &orig_filename,
+                );
             let Some(writer) = destination_and_gate.gate.try_write_file(
-                &orig_filename,
+                upload_context,
                 &destination_and_gate.destination,
             ) else {
                 self.0.ctx.log_info(format!(
@@ -364,7 +374,7 @@ impl WebdavListenerEachPort {
                 .await
                 .expect(&format!("{}", self.listen_on));
         loop {
-            let Ok((sock, _peername)) = server_sock.accept().await else {
+            let Ok((sock, peername)) = server_sock.accept().await else {
                 // log something
                 tokio::time::sleep(std::time::Duration::from_secs(1)).await;
                 continue;
@@ -374,6 +384,7 @@ impl WebdavListenerEachPort {
                     .handle_connection(
                         std::sync::Arc::clone(&dav_handler),
                         sock,
+                        peername,
                     ),
             );
         }
@@ -435,7 +446,7 @@ pub struct WebdavListener {
     hyper_builder:
         hyper_util::server::conn::auto::Builder<hyper_util::rt::TokioExecutor>,
     users: std::collections::HashMap<String, WebdavListenerUser>,
-    http_basic_auth: scan2blob::http_basic_auth::HttpBasicAuth,
+    http_auth: scan2blob::http_auth::HttpAuth,
 }
 
 impl WebdavListener {
@@ -490,6 +501,8 @@ impl WebdavListener {
                     destination_and_gate: DestinationAndGate {
                         destination,
                         gate,
+                        username: username.clone(),
+                        peer: None,
                     },
                 },
             );
@@ -509,15 +522,15 @@ impl WebdavListener {
         let hyper_builder: hyper_util::server::conn::auto::Builder<
             hyper_util::rt::TokioExecutor,
         > = hyper_util::server::conn::auto::Builder::new(hyper_executor);
-        let http_basic_auth: scan2blob::http_basic_auth::HttpBasicAuth =
-            scan2blob::http_basic_auth::HttpBasicAuth::new();
+        let http_auth: scan2blob::http_auth::HttpAuth =
+            scan2blob::http_auth::HttpAuth::new();
         Ok(Self {
             ctx: std::sync::Arc::clone(ctx),
             listen_on: config.listen_on.clone(),
             rustls_acceptor,
             hyper_builder,
             users,
-            http_basic_auth,
+            http_auth,
         })
     }
 
@@ -551,7 +564,16 @@ impl WebdavListener {
             dav_server::DavHandler<DestinationAndGate>,
         >,
         sock: tokio::net::TcpStream,
+        peer: std::net::SocketAddr,
     ) {
+        // Each request logs in separately, so the username is whoever most
+        // recently did.
+        let connection: std::sync::Arc<crate::activity::ConnectionGuard> =
+            std::sync::Arc::new(
+                self.ctx
+                    .activity
+                    .connection(crate::upload_context::Listener::Webdav, peer),
+            );
         let tls_sock: tokio_rustls::server::TlsStream<tokio::net::TcpStream> =
             match self.rustls_acceptor.accept(sock).await {
                 Ok(tls_sock) => tls_sock,
@@ -570,8 +592,12 @@ impl WebdavListener {
                 dav_server::DavHandler<DestinationAndGate>,
             > = std::sync::Arc::clone(&dav_handler);
             move |req| {
-                std::sync::Arc::clone(&self_)
-                    .handle_request(std::sync::Arc::clone(&dav_handler), req)
+                std::sync::Arc::clone(&self_).handle_request(
+                    std::sync::Arc::clone(&dav_handler),
+                    req,
+                    peer,
+                    std::sync::Arc::clone(&connection),
+                )
             }
         });
         if let Err(err) = self
@@ -591,6 +617,8 @@ impl WebdavListener {
             dav_server::DavHandler<DestinationAndGate>,
         >,
         req: hyper::Request<ReqBody>,
+        peer: std::net::SocketAddr,
+        connection: std::sync::Arc<crate::activity::ConnectionGuard>,
     ) -> Result<hyper::Response<dav_server::body::Body>, hyper::http::Error>
     where
         ReqData: hyper::body::Buf + Send + 'static,
@@ -608,7 +636,7 @@ impl WebdavListener {
                 None
             };
 
-        let Some(destination_and_gate) = destination_and_gate else {
+        let Some(mut destination_and_gate) = destination_and_gate else {
             return hyper::Response::builder()
                 .status(hyper::StatusCode::UNAUTHORIZED)
                 .header(
@@ -618,6 +646,8 @@ impl WebdavListener {
                 .body(dav_server::body::Body::empty());
         };
 
+        connection.set_username(&destination_and_gate.username);
+        destination_and_gate.peer = Some(peer);
         Ok(dav_handler.handle_guarded(req, destination_and_gate).await)
     }
 
@@ -630,7 +660,7 @@ impl WebdavListener {
         };
 
         let Some((username, plaintext)) =
-            self.http_basic_auth.parse(auth_header)
+            self.http_auth.parse_basic(auth_header)
         else {
             return None;
         };
@@ -1,25 +1,74 @@
+mod activity;
+mod admin;
 mod ctx;
 mod destination;
 mod gate;
 mod listener;
 mod mime_types;
+mod mqtt;
+mod spool;
+mod upload_context;
 
 async fn async_main(
     ctx: std::sync::Arc<ctx::Ctx>,
 ) -> Result<(), scan2blob::error::WuffError> {
     let destinations: destination::Destinations =
         destination::Destinations::new(&ctx)?;
-    let gates: gate::Gates = gate::Gates::new(&ctx)?;
+    let gates: gate::Gates = gate::Gates::new(&ctx, &destinations)?;
     for (gate_name, gate_cfg) in &ctx.config.gates {
         let gate: std::sync::Arc<gate::Gate> = gates.get(gate_name).unwrap();
         if let Some(ref web_ui_cfg) = gate_cfg.web_ui {
             let web_ui: std::sync::Arc<gate::web::GateWebListener> =
                 std::sync::Arc::new(gate::web::GateWebListener::new(
-                    &ctx, &gate, web_ui_cfg,
+                    &ctx,
+                    vec![gate],
+                    false,
+                    web_ui_cfg,
                 )?);
             web_ui.start();
         }
     }
+    if let Some(ref web_ui_cfg) = ctx.config.web_ui {
+        let web_ui_gates: Vec<std::sync::Arc<gate::Gate>> =
+            if let Some(ref gate_names) = web_ui_cfg.gates {
+                gate_names
+                    .iter()
+                    .map(|gate_name| gates.get(gate_name).unwrap())
+                    .collect()
+            } else {
+                gates.all()
+            };
+        let web_ui: std::sync::Arc<gate::web::GateWebListener> =
+            std::sync::Arc::new(gate::web::GateWebListener::new(
+                &ctx,
+                web_ui_gates,
+                true,
+                &web_ui_cfg.listener,
+            )?);
+        web_ui.start();
+    }
+    if let Some(ref mqtt_cfg) = ctx.config.mqtt {
+        let mqtt_gates: Vec<std::sync::Arc<gate::Gate>> =
+            if let Some(ref gate_names) = mqtt_cfg.gates {
+                gate_names
+                    .iter()
+                    .map(|gate_name| gates.get(gate_name).unwrap())
+                    .collect()
+            } else {
+                gates.all()
+            };
+        let mqtt_client: std::sync::Arc<mqtt::MqttClient> =
+            std::sync::Arc::new(mqtt::MqttClient::new(
+                &ctx, mqtt_gates, mqtt_cfg,
+            )?);
+        mqtt_client.start();
+    }
+    if let Some(ref admin_cfg) = ctx.config.admin {
+        let admin: std::sync::Arc<admin::AdminListener> = std::sync::Arc::new(
+            admin::AdminListener::new(&ctx, gates.all(), admin_cfg),
+        );
+        admin.start()?;
+    }
     for listener_cfg in &ctx.config.listeners {
         match listener_cfg {
             listener::ConfigListenerEnriched::Sftp(listener_cfg) => {
@@ -58,6 +107,7 @@ async fn async_main(
     futures::select! {
         _ = futures::FutureExt::fuse(sigint.recv()) => Ok(()),
         _ = futures::FutureExt::fuse(sigterm.recv()) => Ok(()),
+        _ = futures::FutureExt::fuse(ctx.reload_requested.wait()) => Ok(()),
         err = futures::FutureExt::fuse(ctx.shutdown_due_to_error.wait()) =>
             Err(err.clone()),
     }
@@ -122,6 +172,24 @@ fn main() {
         ctx.log_info(format!("{}", err));
         std::process::exit(1);
     }
+    if ctx.reload_requested.get().is_some() {
+        reexec(&ctx);
+    }
+}
+
+// Starting over from scratch, with the same command line, is what reloading
+// the config file amounts to, since everything is built from it. If we were
+// daemonized, we'll be daemonized again, and the new process writes its own
+// PID to the PID file.
+fn reexec(ctx: &ctx::Ctx) -> ! {
+    let err: std::io::Error = match std::env::current_exe() {
+        Ok(exe) => std::os::unix::process::CommandExt::exec(
+            std::process::Command::new(exe).args(std::env::args_os().skip(1)),
+        ),
+        Err(err) => err,
+    };
+    ctx.log_err(format!("could not restart: {}", err));
+    std::process::exit(1);
 }
 
 struct PidFile {
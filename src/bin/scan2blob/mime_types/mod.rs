@@ -100,6 +100,13 @@ impl ConfigMimeTypesEnriched {
         let Some((_, extension)) = filename.rsplit_once('.') else {
             return None;
         };
+        self.get_by_extension(extension)
+    }
+
+    pub fn get_by_extension(
+        &self,
+        extension: &str,
+    ) -> Option<ConfigMimeTypeEnriched> {
         self.0.get(&extension.to_lowercase()).cloned()
     }
 }
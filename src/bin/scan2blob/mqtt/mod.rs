@@ -0,0 +1,745 @@
+// Gates can be watched and controlled over MQTT, which is mostly so that
+// Home Assistant can do it. Each gate's state goes to
+// "<topic_prefix>/<gate>/state" as JSON, retained, and gets published again
+// whenever it changes. The gate can be opened or closed by publishing "ON" or
+// "OFF" to "<topic_prefix>/<gate>/open/set", and opened with a name hint by
+// publishing the name hint to "<topic_prefix>/<gate>/name_hint/set".
+//
+// Anyone who can publish to those topics on the broker can open and close
+// the gates, so it's up to the broker's ACLs who that is. Everything that
+// comes in over MQTT goes in each gate's audit log as having been done by
+// "mqtt".
+//
+// Unless it's turned off, there are also Home Assistant discovery messages,
+// so that each gate shows up as a device with a switch and a text entity
+// for the name hint, without anyone having to configure anything in Home
+// Assistant.
+
+#[derive(serde::Deserialize)]
+pub struct ConfigMqtt {
+    pub host: String,
+    // The default depends on whether it's TLS or not.
+    pub port: Option<u16>,
+    // With the system's root certificates.
+    #[serde(default)]
+    pub tls: bool,
+    #[serde(default = "default_client_id")]
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<scan2blob::util::LiteralOrEnvironmentVariable>,
+    #[serde(default = "default_topic_prefix")]
+    pub topic_prefix: String,
+    // Where Home Assistant looks for discovery messages, or null for there
+    // not to be any.
+    #[serde(default = "default_discovery_prefix")]
+    pub discovery_prefix: Option<String>,
+    // All of them, if this isn't here.
+    pub gates: Option<Vec<String>>,
+}
+
+pub struct ConfigMqttEnriched {
+    pub host: String,
+    pub port: u16,
+    pub tls: bool,
+    pub client_id: String,
+    pub credentials: Option<(String, String)>,
+    pub topic_prefix: String,
+    pub discovery_prefix: Option<String>,
+    pub gates: Option<Vec<String>>,
+}
+
+impl TryFrom<ConfigMqtt> for ConfigMqttEnriched {
+    type Error = scan2blob::error::WuffError;
+    fn try_from(
+        config: ConfigMqtt,
+    ) -> Result<Self, scan2blob::error::WuffError> {
+        let ConfigMqtt {
+            host,
+            port,
+            tls,
+            client_id,
+            username,
+            password,
+            topic_prefix,
+            discovery_prefix,
+            gates,
+        } = config;
+        let credentials: Option<(String, String)> = match (username, password)
+        {
+            (Some(username), Some(password)) => {
+                Some((username, password.try_into()?))
+            }
+            (None, None) => None,
+            _ => {
+                return Err(scan2blob::error::WuffError::from(
+                    "mqtt: username and password go together",
+                ));
+            }
+        };
+        for topic in std::iter::once(&topic_prefix).chain(&discovery_prefix) {
+            check_topic_part(topic).map_err(|e| {
+                scan2blob::error::WuffError::from(format!("mqtt: {}", e))
+            })?;
+        }
+        Ok(Self {
+            host,
+            port: port.unwrap_or(if tls { 8883 } else { 1883 }),
+            tls,
+            client_id,
+            credentials,
+            topic_prefix,
+            discovery_prefix,
+            gates,
+        })
+    }
+}
+
+fn default_client_id() -> String {
+    String::from("scan2blob")
+}
+
+fn default_topic_prefix() -> String {
+    String::from("scan2blob")
+}
+
+fn default_discovery_prefix() -> Option<String> {
+    Some(String::from("homeassistant"))
+}
+
+// Prefixes can have slashes in them, but wildcards would mean something
+// else entirely.
+fn check_topic_part(s: &str) -> Result<(), scan2blob::error::WuffError> {
+    if s.is_empty() || s.contains(['+', '#']) {
+        return Err(scan2blob::error::WuffError::from(format!(
+            "{:?} can't be empty or have \"+\" or \"#\" in it",
+            s
+        )));
+    }
+    Ok(())
+}
+
+// A gate name goes in the middle of topics, so it can't have a slash in it
+// either.
+pub fn check_gate_name(
+    gate_name: &str,
+) -> Result<(), scan2blob::error::WuffError> {
+    check_topic_part(gate_name)?;
+    if gate_name.contains('/') {
+        return Err(scan2blob::error::WuffError::from(format!(
+            "{:?} can't have \"/\" in it",
+            gate_name
+        )));
+    }
+    Ok(())
+}
+
+// Home Assistant's IDs can only have letters, digits, "_" and "-" in them.
+fn object_id(gate_name: &str) -> String {
+    gate_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+// How many requests to the broker can be waiting to go out at once.
+const REQUEST_CAPACITY: usize = 64;
+
+const KEEP_ALIVE: std::time::Duration = std::time::Duration::from_secs(30);
+
+// After the connection to the broker fails, how long to wait before trying
+// again.
+const RECONNECT_DELAY: std::time::Duration = std::time::Duration::from_secs(5);
+
+// The same as the web UI's: when the gate's going to change by itself, we
+// wait a bit past when it's meant to, so that it definitely has.
+const STATE_SLACK: std::time::Duration = std::time::Duration::from_millis(100);
+
+#[derive(serde::Serialize)]
+struct MqttGateState {
+    open: bool,
+    name_hint: Option<String>,
+    // Seconds since the epoch, and seconds from when this was published,
+    // respectively. Neither one is there if the gate's closed, or isn't
+    // going to close by itself.
+    closes_at: Option<u64>,
+    time_remaining: Option<u64>,
+}
+
+impl MqttGateState {
+    fn new(gate: &crate::gate::Gate) -> Self {
+        let (state, time_until_gate_closes) =
+            gate.get_current_state_extended();
+        let open: bool = state.is_some();
+        let time_remaining: Option<std::time::Duration> =
+            time_until_gate_closes.filter(|_| open);
+        Self {
+            open,
+            name_hint: state
+                .and_then(|name_hints| name_hints.into_iter().next()),
+            closes_at: time_remaining.map(|time_remaining| {
+                (std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    + time_remaining)
+                    .as_secs()
+            }),
+            time_remaining: time_remaining
+                .map(|time_remaining| time_remaining.as_secs()),
+        }
+    }
+
+    // The time remaining is always different, and doesn't count.
+    fn is_same_state(&self, other: &Self) -> bool {
+        self.open == other.open
+            && self.name_hint == other.name_hint
+            && self.closes_at == other.closes_at
+    }
+}
+
+pub struct MqttClient {
+    ctx: std::sync::Arc<crate::ctx::Ctx>,
+    client: rumqttc::AsyncClient,
+    // Until it's started.
+    eventloop: std::sync::Mutex<Option<rumqttc::EventLoop>>,
+    topic_prefix: String,
+    discovery_prefix: Option<String>,
+    gates: Vec<std::sync::Arc<crate::gate::Gate>>,
+}
+
+impl MqttClient {
+    pub fn new(
+        ctx: &std::sync::Arc<crate::ctx::Ctx>,
+        mut gates: Vec<std::sync::Arc<crate::gate::Gate>>,
+        config: &ConfigMqttEnriched,
+    ) -> Result<Self, scan2blob::error::WuffError> {
+        gates.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut mqtt_options: rumqttc::MqttOptions = rumqttc::MqttOptions::new(
+            config.client_id.clone(),
+            config.host.clone(),
+            config.port,
+        );
+        let _ = mqtt_options.set_keep_alive(KEEP_ALIVE);
+        if let Some((ref username, ref password)) = config.credentials {
+            let _ = mqtt_options.set_credentials(username, password);
+        }
+        if config.tls {
+            let _ = mqtt_options
+                .set_transport(rumqttc::Transport::tls_with_default_config());
+        }
+        // If we go away without saying goodbye, the broker says it for us,
+        // and Home Assistant shows the gates as unavailable rather than
+        // however they last were.
+        let _ = mqtt_options.set_last_will(rumqttc::LastWill::new(
+            format!("{}/status", config.topic_prefix),
+            "offline",
+            rumqttc::QoS::AtLeastOnce,
+            true,
+        ));
+        let (client, eventloop) =
+            rumqttc::AsyncClient::new(mqtt_options, REQUEST_CAPACITY);
+        Ok(Self {
+            ctx: std::sync::Arc::clone(ctx),
+            client,
+            eventloop: std::sync::Mutex::new(Some(eventloop)),
+            topic_prefix: config.topic_prefix.clone(),
+            discovery_prefix: config.discovery_prefix.clone(),
+            gates,
+        })
+    }
+
+    pub fn start(self: &std::sync::Arc<Self>) {
+        let eventloop: rumqttc::EventLoop = self
+            .eventloop
+            .lock()
+            .unwrap()
+            .take()
+            .expect("started twice");
+        self.ctx.spawn_critical(
+            String::from("mqtt"),
+            std::sync::Arc::clone(self).run(eventloop),
+        );
+        for gate in &self.gates {
+            // Subscribing now, rather than once the task gets going, means
+            // that nothing gets missed in between.
+            let events: tokio::sync::broadcast::Receiver<
+                crate::gate::GateEvent,
+            > = gate.subscribe();
+            self.ctx.spawn_critical(
+                format!("mqtt for gate {}", gate.name),
+                std::sync::Arc::clone(self)
+                    .publish_states(std::sync::Arc::clone(gate), events),
+            );
+        }
+    }
+
+    fn topic(&self, gate: &crate::gate::Gate, suffix: &str) -> String {
+        format!("{}/{}/{}", self.topic_prefix, gate.name, suffix)
+    }
+
+    // Polling the event loop is what keeps the connection going (and
+    // reconnects it, when it needs to), as well as how anything that comes
+    // in from the broker gets here.
+    async fn run(
+        self: std::sync::Arc<Self>,
+        mut eventloop: rumqttc::EventLoop,
+    ) {
+        loop {
+            match eventloop.poll().await {
+                Ok(rumqttc::Event::Incoming(rumqttc::Packet::ConnAck(_))) => {
+                    self.ctx.log_info("mqtt: connected");
+                    // Not from in here, since the requests that this makes
+                    // only go anywhere while the event loop is being polled.
+                    let async_spawner = self.ctx.base_ctx.get_async_spawner();
+                    async_spawner
+                        .spawn(std::sync::Arc::clone(&self).connected());
+                }
+                Ok(rumqttc::Event::Incoming(rumqttc::Packet::Publish(
+                    publish,
+                ))) => {
+                    self.handle_command(&publish.topic, &publish.payload);
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    self.ctx.log_warn(format!("mqtt: {}", err));
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                }
+            }
+        }
+    }
+
+    // Each time we connect, it's a clean session, so everything that we
+    // subscribe to or tell the broker to hang onto has to be done again.
+    async fn connected(self: std::sync::Arc<Self>) {
+        if let Err(err) = self.try_connected().await {
+            self.ctx.log_warn(format!("mqtt: {}", err));
+        }
+    }
+
+    async fn try_connected(&self) -> Result<(), rumqttc::ClientError> {
+        for gate in &self.gates {
+            for suffix in ["open/set", "name_hint/set"] {
+                self.client
+                    .subscribe(
+                        self.topic(gate, suffix),
+                        rumqttc::QoS::AtLeastOnce,
+                    )
+                    .await?;
+            }
+            if self.discovery_prefix.is_some() {
+                self.publish_discovery(gate).await?;
+            }
+            self.publish_state(gate, &MqttGateState::new(gate)).await?;
+        }
+        self.client
+            .publish(
+                format!("{}/status", self.topic_prefix),
+                rumqttc::QoS::AtLeastOnce,
+                true,
+                "online",
+            )
+            .await
+    }
+
+    async fn publish_discovery(
+        &self,
+        gate: &crate::gate::Gate,
+    ) -> Result<(), rumqttc::ClientError> {
+        let Some(ref discovery_prefix) = self.discovery_prefix else {
+            return Ok(());
+        };
+        let object_id: String = object_id(&gate.name);
+        let device: serde_json::Value = serde_json::json!({
+            "identifiers": [format!("scan2blob_{}", object_id)],
+            "name": gate.name,
+            "manufacturer": "scan2blob",
+        });
+        let availability_topic: String =
+            format!("{}/status", self.topic_prefix);
+        let switch: serde_json::Value = serde_json::json!({
+            "name": "Open",
+            "unique_id": format!("scan2blob_{}_open", object_id),
+            "device": device,
+            "icon": "mdi:scanner",
+            "availability_topic": availability_topic,
+            "state_topic": self.topic(gate, "state"),
+            "value_template": "{{ 'ON' if value_json.open else 'OFF' }}",
+            "json_attributes_topic": self.topic(gate, "state"),
+            "command_topic": self.topic(gate, "open/set"),
+        });
+        let text: serde_json::Value = serde_json::json!({
+            "name": "Name hint",
+            "unique_id": format!("scan2blob_{}_name_hint", object_id),
+            "device": device,
+            "icon": "mdi:tag-text",
+            "availability_topic": availability_topic,
+            "state_topic": self.topic(gate, "state"),
+            "value_template": "{{ value_json.name_hint or '' }}",
+            "command_topic": self.topic(gate, "name_hint/set"),
+        });
+        for (component, entity, config) in
+            [("switch", "open", switch), ("text", "name_hint", text)]
+        {
+            self.client
+                .publish(
+                    format!(
+                        "{}/{}/scan2blob_{}/{}/config",
+                        discovery_prefix, component, object_id, entity
+                    ),
+                    rumqttc::QoS::AtLeastOnce,
+                    true,
+                    serde_json::to_string(&config).expect("serde_json"),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn publish_state(
+        &self,
+        gate: &crate::gate::Gate,
+        state: &MqttGateState,
+    ) -> Result<(), rumqttc::ClientError> {
+        self.client
+            .publish(
+                self.topic(gate, "state"),
+                rumqttc::QoS::AtLeastOnce,
+                true,
+                serde_json::to_string(state).expect("serde_json"),
+            )
+            .await
+    }
+
+    // Publishes the gate's state whenever it changes, whether that's because
+    // of something that somebody did, or because it changed by itself. This
+    // is the same idea as the web UI's event stream.
+    async fn publish_states(
+        self: std::sync::Arc<Self>,
+        gate: std::sync::Arc<crate::gate::Gate>,
+        mut events: tokio::sync::broadcast::Receiver<crate::gate::GateEvent>,
+    ) {
+        let mut last_state: Option<MqttGateState> = None;
+        loop {
+            let state: MqttGateState = MqttGateState::new(&gate);
+            let state_changed: bool = if let Some(ref last_state) = last_state
+            {
+                !state.is_same_state(last_state)
+            } else {
+                true
+            };
+            if state_changed {
+                // If we're not connected, this waits in line until we are,
+                // or until the line is full, and either way, everything gets
+                // published again when we connect.
+                if let Err(err) = self.publish_state(&gate, &state).await {
+                    self.ctx.log_warn(format!("mqtt: {}", err));
+                }
+                last_state = Some(state);
+            }
+
+            let (_state, next_change_time) = gate.get_current_state_extended();
+            let events_recv = events.recv();
+            let event: Result<
+                crate::gate::GateEvent,
+                tokio::sync::broadcast::error::RecvError,
+            > = if let Some(next_change_time) = next_change_time {
+                match tokio::time::timeout(
+                    next_change_time + STATE_SLACK,
+                    events_recv,
+                )
+                .await
+                {
+                    Ok(event) => event,
+                    Err(_) => {
+                        continue;
+                    }
+                }
+            } else {
+                events_recv.await
+            };
+            match event {
+                // Uploads don't change anything that we publish, and if
+                // we've missed something, the state's whatever it is now.
+                Ok(_)
+                | Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => {
+                    return;
+                }
+            }
+        }
+    }
+
+    fn handle_command(&self, topic: &str, payload: &[u8]) {
+        let Some(rest) = topic
+            .strip_prefix(self.topic_prefix.as_str())
+            .and_then(|rest| rest.strip_prefix('/'))
+        else {
+            return;
+        };
+        let Some((gate_name, command)) = rest.split_once('/') else {
+            return;
+        };
+        let Some(gate) = self.gates.iter().find(|gate| gate.name == gate_name)
+        else {
+            return;
+        };
+        let payload: std::borrow::Cow<str> = String::from_utf8_lossy(payload);
+        let payload: &str = payload.trim();
+        match command {
+            "open/set" => match payload {
+                "ON" => {
+                    self.open(gate, Vec::new());
+                }
+                "OFF" => {
+                    gate.assert_gate_closed();
+                    gate.audit("mqtt", String::from("locked"));
+                }
+                _ => {
+                    self.ctx.log_warn(format!(
+                        "mqtt: {}: expected ON or OFF, got {:?}",
+                        topic, payload
+                    ));
+                }
+            },
+            // Setting the name hint to nothing still opens the gate, just
+            // without one.
+            "name_hint/set" => {
+                let name_hints: Vec<String> = if payload.is_empty() {
+                    Vec::new()
+                } else {
+                    vec![payload.to_string()]
+                };
+                self.open(gate, name_hints);
+            }
+            _ => {}
+        }
+    }
+
+    fn open(&self, gate: &crate::gate::Gate, name_hints: Vec<String>) {
+        let mut action: String = String::from("unlocked");
+        if !name_hints.is_empty() {
+            action.push_str(&format!(", name hints {:?}", name_hints));
+        }
+        if let Err(err) = gate.assert_gate_open_timed_with_name_hints(
+            name_hints, None, None, None,
+        ) {
+            self.ctx
+                .log_warn(format!("mqtt: gate {}: {}", gate.name, err));
+        } else {
+            gate.audit("mqtt", action);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn new_client(
+        gate: &std::sync::Arc<crate::gate::Gate>,
+    ) -> std::sync::Arc<MqttClient> {
+        let config: ConfigMqttEnriched =
+            serde_json::from_value::<ConfigMqtt>(serde_json::json!({
+                "host": "localhost",
+                "topic_prefix": "scans/home",
+            }))
+            .unwrap()
+            .try_into()
+            .unwrap();
+        std::sync::Arc::new(
+            MqttClient::new(
+                &crate::gate::test::ctx(gate),
+                vec![std::sync::Arc::clone(gate)],
+                &config,
+            )
+            .unwrap(),
+        )
+    }
+
+    fn state(
+        open: bool,
+        name_hint: Option<&str>,
+        closes_at: Option<u64>,
+        time_remaining: Option<u64>,
+    ) -> MqttGateState {
+        MqttGateState {
+            open,
+            name_hint: name_hint.map(str::to_string),
+            closes_at,
+            time_remaining,
+        }
+    }
+
+    #[test]
+    fn config() {
+        let config: ConfigMqttEnriched =
+            serde_json::from_value::<ConfigMqtt>(serde_json::json!({
+                "host": "localhost",
+                "tls": true,
+            }))
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert_eq!(config.port, 8883);
+        assert_eq!(config.topic_prefix, "scan2blob");
+        assert_eq!(config.discovery_prefix.as_deref(), Some("homeassistant"));
+        assert!(config.credentials.is_none());
+
+        let bad = |cfg: serde_json::Value| {
+            let config: ConfigMqtt = serde_json::from_value(cfg).unwrap();
+            ConfigMqttEnriched::try_from(config).is_err()
+        };
+        assert!(bad(serde_json::json!({
+            "host": "localhost",
+            "username": "scanner",
+        })));
+        assert!(bad(serde_json::json!({
+            "host": "localhost",
+            "topic_prefix": "scans/#",
+        })));
+        assert!(bad(serde_json::json!({
+            "host": "localhost",
+            "discovery_prefix": "",
+        })));
+    }
+
+    #[test]
+    fn gate_names() {
+        assert!(check_gate_name("front-desk").is_ok());
+        assert!(check_gate_name("front desk").is_ok());
+        assert!(check_gate_name("").is_err());
+        assert!(check_gate_name("front/desk").is_err());
+        assert!(check_gate_name("front+desk").is_err());
+        assert!(check_gate_name("#").is_err());
+    }
+
+    #[test]
+    fn object_ids() {
+        assert_eq!(object_id("front-desk_2"), "front-desk_2");
+        assert_eq!(object_id("Front Desk"), "Front_Desk");
+        assert_eq!(object_id("caf\u{e9}.scans"), "caf__scans");
+    }
+
+    #[test]
+    fn same_state() {
+        let a: MqttGateState =
+            state(true, Some("taxes"), Some(1000), Some(60));
+        assert!(a.is_same_state(&state(
+            true,
+            Some("taxes"),
+            Some(1000),
+            None
+        )));
+        assert!(a.is_same_state(&state(
+            true,
+            Some("taxes"),
+            Some(1000),
+            Some(5)
+        )));
+        assert!(!a.is_same_state(&state(
+            false,
+            Some("taxes"),
+            Some(1000),
+            Some(60)
+        )));
+        assert!(!a.is_same_state(&state(true, None, Some(1000), Some(60))));
+        assert!(!a.is_same_state(&state(
+            true,
+            Some("taxes"),
+            Some(1001),
+            Some(60)
+        )));
+        assert!(!a.is_same_state(&state(true, Some("taxes"), None, None)));
+    }
+
+    #[test]
+    fn state_of_gate() {
+        let gate: std::sync::Arc<crate::gate::Gate> =
+            crate::gate::test::new_gate(serde_json::json!({}));
+        let closed: MqttGateState = MqttGateState::new(&gate);
+        assert!(!closed.open);
+        assert_eq!(closed.closes_at, None);
+        gate.assert_gate_open_timed_with_name_hints(
+            vec![String::from("taxes")],
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        let open: MqttGateState = MqttGateState::new(&gate);
+        assert!(open.open);
+        assert_eq!(open.name_hint.as_deref(), Some("taxes"));
+        assert!(!open.is_same_state(&closed));
+    }
+
+    #[test]
+    fn open_and_close() {
+        let gate: std::sync::Arc<crate::gate::Gate> =
+            crate::gate::test::new_gate(serde_json::json!({}));
+        let client: std::sync::Arc<MqttClient> = new_client(&gate);
+        client.handle_command("scans/home/test/open/set", b"ON");
+        assert_eq!(gate.get_current_state(), Some(None));
+        // Whitespace around the payload doesn't matter.
+        client.handle_command("scans/home/test/open/set", b" OFF\n");
+        assert_eq!(gate.get_current_state(), None);
+        let actions: Vec<(String, String)> = gate
+            .audit_log()
+            .into_iter()
+            .map(|entry| (entry.username, entry.action))
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                (String::from("mqtt"), String::from("locked")),
+                (String::from("mqtt"), String::from("unlocked")),
+            ]
+        );
+    }
+
+    #[test]
+    fn name_hint() {
+        let gate: std::sync::Arc<crate::gate::Gate> =
+            crate::gate::test::new_gate(serde_json::json!({}));
+        let client: std::sync::Arc<MqttClient> = new_client(&gate);
+        client.handle_command("scans/home/test/name_hint/set", b"taxes");
+        assert_eq!(
+            gate.get_current_state(),
+            Some(Some(String::from("taxes")))
+        );
+        client.handle_command("scans/home/test/open/set", b"OFF");
+        // No name hint still opens it.
+        client.handle_command("scans/home/test/name_hint/set", b"  ");
+        assert_eq!(gate.get_current_state(), Some(None));
+    }
+
+    #[test]
+    fn ignored_commands() {
+        let gate: std::sync::Arc<crate::gate::Gate> =
+            crate::gate::test::new_gate(serde_json::json!({}));
+        let client: std::sync::Arc<MqttClient> = new_client(&gate);
+        for topic in [
+            // Not under the prefix, or only partly.
+            "scan2blob/test/open/set",
+            "scans/homeward/test/open/set",
+            "scans/hometest/open/set",
+            // No such gate.
+            "scans/home/other/open/set",
+            // No such command.
+            "scans/home/test/open",
+            "scans/home/test/state",
+            "scans/home/test",
+        ] {
+            client.handle_command(topic, b"ON");
+            assert_eq!(gate.get_current_state(), None, "{}", topic);
+        }
+        client.handle_command("scans/home/test/open/set", b"on");
+        assert_eq!(gate.get_current_state(), None);
+        assert!(gate.audit_log().is_empty());
+    }
+}
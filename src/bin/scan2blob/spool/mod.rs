@@ -0,0 +1,106 @@
+// Spool files are temporary files on local disk, for when a file being
+// uploaded needs to be looked at as a whole (seeking around in it, reading it
+// more than once, etc.) rather than streamed straight through. They're
+// deleted when the SpoolFile is dropped.
+
+static NEXT_SPOOL_FILE_ID: std::sync::atomic::AtomicU64 =
+    std::sync::atomic::AtomicU64::new(0);
+
+// Batches and duplex jobs hold onto files that the scanner has already been
+// told went through, in spool files, until they're uploaded. Those wouldn't
+// survive the server starting over, so it mustn't while there are any.
+static NUM_SPOOL_FILES: std::sync::atomic::AtomicUsize =
+    std::sync::atomic::AtomicUsize::new(0);
+
+pub fn num_spool_files() -> usize {
+    NUM_SPOOL_FILES.load(std::sync::atomic::Ordering::Relaxed)
+}
+
+pub struct SpoolFile {
+    path: std::path::PathBuf,
+    len: u64,
+}
+
+impl SpoolFile {
+    // Reads everything from `reader` into a new spool file in `directory`.
+    // This does not finalize the reader: the caller does that, once it knows
+    // whether whatever it wanted to do with the spool file has worked.
+    pub async fn receive(
+        directory: &std::path::Path,
+        reader: &mut scan2blob::chunker::Reader,
+    ) -> Result<Self, scan2blob::error::WuffError> {
+        let id: u64 = NEXT_SPOOL_FILE_ID
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let path: std::path::PathBuf = directory.join(format!(
+            "scan2blob-{}-{}.spool",
+            std::process::id(),
+            id
+        ));
+        let mut f: tokio::fs::File = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .await?;
+        // From here on, if anything goes wrong, dropping the SpoolFile takes
+        // care of deleting what we've written so far.
+        let mut spool_file: Self = Self { path, len: 0 };
+        NUM_SPOOL_FILES.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        while let scan2blob::chunker::ChunkOrEof::Chunk(chunk) =
+            reader.get_next_chunk().await?
+        {
+            tokio::io::AsyncWriteExt::write_all(&mut f, &chunk).await?;
+            spool_file.len += chunk.len() as u64;
+        }
+        tokio::io::AsyncWriteExt::flush(&mut f).await?;
+        Ok(spool_file)
+    }
+
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    // Writes the whole contents of the spool file to `writer`, and finalizes
+    // it.
+    pub async fn copy_to(
+        &self,
+        mut writer: scan2blob::chunker::Writer,
+    ) -> Result<(), scan2blob::error::WuffError> {
+        let mut f: tokio::fs::File =
+            match tokio::fs::File::open(&self.path).await {
+                Ok(f) => f,
+                Err(err) => {
+                    let err: scan2blob::error::WuffError = err.into();
+                    writer.observe_error(err.clone());
+                    return Err(err);
+                }
+            };
+        let mut buf: Vec<u8> = vec![0u8; 65536];
+        loop {
+            let n: usize =
+                match tokio::io::AsyncReadExt::read(&mut f, &mut buf).await {
+                    Ok(n) => n,
+                    Err(err) => {
+                        let err: scan2blob::error::WuffError = err.into();
+                        writer.observe_error(err.clone());
+                        return Err(err);
+                    }
+                };
+            if n == 0 {
+                break;
+            }
+            writer.write(&buf[..n]).await?;
+        }
+        writer.finalize().await
+    }
+}
+
+impl Drop for SpoolFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+        NUM_SPOOL_FILES.fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
+    }
+}
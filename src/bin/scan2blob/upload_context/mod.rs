@@ -0,0 +1,148 @@
+// Everything we know about where an upload came from, and what's been done to
+// it on its way to the destination. This follows the upload all the way
+// through, so that it can end up in the sidecar, if there is one.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Listener {
+    Sftp,
+    Webdav,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(tag = "step", rename_all = "snake_case")]
+pub enum ProcessingStep {
+    MergeBatch { num_files: usize },
+    CollateDuplex,
+    RemoveBlankPages { num_removed: usize },
+    ConvertToPdf,
+    VirusScan,
+}
+
+#[derive(Clone, Debug)]
+pub struct UploadContext {
+    pub listener: Listener,
+    pub username: String,
+    pub client_addr: Option<std::net::SocketAddr>,
+    // Usually just the one, unless several uploads were combined into one.
+    pub orig_filenames: Vec<String>,
+    pub started: std::time::SystemTime,
+    // These get filled in by the gate.
+    pub gate: String,
+    pub name_hint: Option<String>,
+    pub category: Option<String>,
+    // Only if there's a category.
+    pub sub_prefix: String,
+    pub template: Option<scan2blob::template::Template>,
+    pub processing: Vec<ProcessingStep>,
+    // The gate keeps track of how the upload is going.
+    pub recent_uploads:
+        Option<std::sync::Arc<crate::gate::recent_uploads::RecentUploads>>,
+}
+
+#[derive(Clone, Copy, Debug, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadStage {
+    Started,
+    Progress,
+    Committed,
+    Failed,
+    // After it was committed.
+    Discarded,
+}
+
+// One of these goes out when a blob starts being uploaded, another each time
+// some more of it has been uploaded, and another when it's either been
+// committed or failed, and then another if it's discarded. A transform can turn one upload into
+// more than one blob, and a batch can turn several into one, so it's blobs
+// that these are about, rather than the files that came in.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct UploadEvent {
+    pub stage: UploadStage,
+    pub blob_name: String,
+    pub destination: String,
+    pub username: String,
+    pub orig_filenames: Vec<String>,
+    // When the upload (or the first of them) came in, in seconds since the
+    // epoch.
+    pub started: u64,
+    // How much of it has been uploaded so far. We don't know how much there's
+    // going to be until it's all been uploaded.
+    pub size: u64,
+    pub error: Option<String>,
+    // Once it's been committed, and if the gate lets uploads be discarded,
+    // until when that can be done, in seconds since the epoch.
+    pub discardable_until: Option<u64>,
+}
+
+impl UploadContext {
+    pub fn new(
+        listener: Listener,
+        username: &str,
+        client_addr: Option<std::net::SocketAddr>,
+        orig_filename: &str,
+    ) -> Self {
+        Self {
+            listener,
+            username: username.to_string(),
+            client_addr,
+            orig_filenames: vec![orig_filename.to_string()],
+            started: std::time::SystemTime::now(),
+            gate: String::new(),
+            name_hint: None,
+            category: None,
+            sub_prefix: String::new(),
+            template: None,
+            processing: Vec::new(),
+            recent_uploads: None,
+        }
+    }
+
+    // The context for something made out of several uploads, one after the
+    // other. Whatever they have in common comes from the first one.
+    pub fn combine(contexts: &[Self]) -> Self {
+        let mut combined: Self = contexts[0].clone();
+        for context in &contexts[1..] {
+            combined
+                .orig_filenames
+                .extend(context.orig_filenames.iter().cloned());
+            combined.started =
+                std::cmp::min(combined.started, context.started);
+        }
+        combined
+    }
+
+    pub fn report_upload(
+        &self,
+        destination: &str,
+        stage: UploadStage,
+        blob_name: &str,
+        size: u64,
+        error: Option<String>,
+    ) {
+        let Some(ref recent_uploads) = self.recent_uploads else {
+            return;
+        };
+        recent_uploads.report(UploadEvent {
+            stage,
+            blob_name: blob_name.to_string(),
+            destination: destination.to_string(),
+            username: self.username.clone(),
+            orig_filenames: self.orig_filenames.clone(),
+            started: self
+                .started
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            size,
+            error,
+            discardable_until: None,
+        });
+    }
+
+    pub fn with_step(&self, step: ProcessingStep) -> Self {
+        let mut context: Self = self.clone();
+        context.processing.push(step);
+        context
+    }
+}
@@ -0,0 +1,284 @@
+// A client for clamd's INSTREAM command, which scans a stream of data that's
+// sent over the same connection, rather than a file that clamd can go and
+// read for itself. The data goes in chunks, each of which is preceded by its
+// length as a 4-byte big-endian number, and a zero-length chunk marks the
+// end. Once it's seen the end, clamd sends back a single line saying what it
+// found.
+
+// Where to find clamd: either "host:port", or the path to a Unix socket.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Address {
+    Tcp(String),
+    Unix(std::path::PathBuf),
+}
+
+impl Address {
+    pub fn parse(s: &str) -> Result<Self, crate::error::WuffError> {
+        if s.starts_with('/') {
+            Ok(Address::Unix(std::path::PathBuf::from(s)))
+        } else if s.contains(':') {
+            Ok(Address::Tcp(s.to_string()))
+        } else {
+            Err(crate::error::WuffError::from(format!(
+                "clamd address {:?} should be either host:port or the absolute path to a socket",
+                s
+            )))
+        }
+    }
+}
+
+impl std::fmt::Display for Address {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Address::Tcp(address) => write!(f, "{}", address),
+            Address::Unix(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+pub trait Stream:
+    tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send
+{
+}
+
+impl<T> Stream for T where
+    T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send
+{
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Verdict {
+    Clean,
+    // The name of the signature that matched.
+    Infected(String),
+}
+
+// clamd won't answer with anything longer than this.
+const MAX_REPLY_LEN: usize = 4096;
+
+pub struct Scan<S> {
+    stream: S,
+}
+
+impl Scan<Box<dyn Stream>> {
+    pub async fn connect(
+        address: &Address,
+    ) -> Result<Self, crate::error::WuffError> {
+        let stream: Box<dyn Stream> = match address {
+            Address::Tcp(address) => {
+                Box::new(tokio::net::TcpStream::connect(address).await?)
+            }
+            Address::Unix(path) => {
+                Box::new(tokio::net::UnixStream::connect(path).await?)
+            }
+        };
+        Self::start(stream).await
+    }
+}
+
+impl<S> Scan<S>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    pub async fn start(
+        mut stream: S,
+    ) -> Result<Self, crate::error::WuffError> {
+        // The "z" means the command (and the reply) are NUL-terminated.
+        tokio::io::AsyncWriteExt::write_all(&mut stream, b"zINSTREAM\0")
+            .await?;
+        Ok(Self { stream })
+    }
+
+    pub async fn send(
+        &mut self,
+        data: &[u8],
+    ) -> Result<(), crate::error::WuffError> {
+        for piece in data.chunks(u32::MAX as usize) {
+            // An empty chunk would mean the end of the stream.
+            if piece.is_empty() {
+                continue;
+            }
+            let len: u32 = piece.len() as u32;
+            tokio::io::AsyncWriteExt::write_all(
+                &mut self.stream,
+                &len.to_be_bytes(),
+            )
+            .await?;
+            tokio::io::AsyncWriteExt::write_all(&mut self.stream, piece)
+                .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn finish(mut self) -> Result<Verdict, crate::error::WuffError> {
+        tokio::io::AsyncWriteExt::write_all(&mut self.stream, &[0u8; 4])
+            .await?;
+        tokio::io::AsyncWriteExt::flush(&mut self.stream).await?;
+
+        let mut reply: Vec<u8> = Vec::new();
+        let mut buf: [u8; 256] = [0u8; 256];
+        loop {
+            let n: usize =
+                tokio::io::AsyncReadExt::read(&mut self.stream, &mut buf)
+                    .await?;
+            if n == 0 {
+                break;
+            }
+            reply.extend_from_slice(&buf[..n]);
+            if let Some(end) = reply.iter().position(|b| *b == 0) {
+                reply.truncate(end);
+                break;
+            }
+            if reply.len() > MAX_REPLY_LEN {
+                return Err(crate::error::WuffError::from(
+                    "clamd: reply is too long",
+                ));
+            }
+        }
+        let Ok(reply) = String::from_utf8(reply) else {
+            return Err(crate::error::WuffError::from(
+                "clamd: reply is not valid UTF-8",
+            ));
+        };
+        parse_reply(reply.trim_end())
+    }
+}
+
+// The reply is "stream: OK" if nothing was found, "stream: <signature> FOUND"
+// if something was, and anything else ending in "ERROR" if clamd couldn't
+// scan it (for instance, because it was longer than clamd's StreamMaxLength).
+fn parse_reply(reply: &str) -> Result<Verdict, crate::error::WuffError> {
+    if let Some(result) = reply.strip_prefix("stream: ") {
+        if result == "OK" {
+            return Ok(Verdict::Clean);
+        }
+        if let Some(signature) = result.strip_suffix(" FOUND") {
+            return Ok(Verdict::Infected(signature.to_string()));
+        }
+    }
+    Err(crate::error::WuffError::from(format!("clamd: {}", reply)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run_test<F>(f: F)
+    where
+        F: AsyncFn(std::sync::Arc<crate::ctx::Ctx>) -> (),
+    {
+        let ctx = std::sync::Arc::new(crate::ctx::Ctx::new());
+        ctx.run_async_main({
+            let ctx = std::sync::Arc::clone(&ctx);
+            async move {
+                f(ctx).await;
+                Ok(())
+            }
+        })
+        .expect("unexpected error");
+    }
+
+    // Pretends to be clamd: reads one INSTREAM command, and answers it with
+    // `reply` if the data contains "EICAR", or "stream: OK" if it doesn't.
+    // Returns the data that was sent.
+    async fn mock_clamd(
+        mut stream: tokio::io::DuplexStream,
+        reply: &str,
+    ) -> Vec<u8> {
+        let mut command: [u8; 10] = [0u8; 10];
+        tokio::io::AsyncReadExt::read_exact(&mut stream, &mut command)
+            .await
+            .unwrap();
+        assert_eq!(&command, b"zINSTREAM\0");
+        let mut data: Vec<u8> = Vec::new();
+        loop {
+            let mut len: [u8; 4] = [0u8; 4];
+            tokio::io::AsyncReadExt::read_exact(&mut stream, &mut len)
+                .await
+                .unwrap();
+            let len: usize = u32::from_be_bytes(len) as usize;
+            if len == 0 {
+                break;
+            }
+            let mut chunk: Vec<u8> = vec![0u8; len];
+            tokio::io::AsyncReadExt::read_exact(&mut stream, &mut chunk)
+                .await
+                .unwrap();
+            data.extend_from_slice(&chunk);
+        }
+        let found: bool = data.windows(5).any(|w| w == b"EICAR");
+        let reply: String =
+            format!("{}\0", if found { reply } else { "stream: OK" });
+        tokio::io::AsyncWriteExt::write_all(&mut stream, reply.as_bytes())
+            .await
+            .unwrap();
+        data
+    }
+
+    async fn scan(
+        ctx: &std::sync::Arc<crate::ctx::Ctx>,
+        pieces: &[&[u8]],
+        reply: &'static str,
+    ) -> (Result<Verdict, crate::error::WuffError>, Vec<u8>) {
+        let async_spawner = ctx.get_async_spawner();
+        let (client, server) = tokio::io::duplex(16);
+        let daemon = async_spawner.spawn(mock_clamd(server, reply));
+        let mut scan: Scan<tokio::io::DuplexStream> =
+            Scan::start(client).await.unwrap();
+        for piece in pieces {
+            scan.send(piece).await.unwrap();
+        }
+        let verdict: Result<Verdict, crate::error::WuffError> =
+            scan.finish().await;
+        (verdict, daemon.await.unwrap())
+    }
+
+    #[test]
+    fn clean() {
+        run_test(async |ctx| {
+            let (verdict, data) =
+                scan(&ctx, &[b"Hello, ", b"", b"world!"], "").await;
+            assert_eq!(verdict.unwrap(), Verdict::Clean);
+            assert_eq!(data, b"Hello, world!");
+        });
+    }
+
+    #[test]
+    fn infected() {
+        run_test(async |ctx| {
+            let (verdict, _) = scan(
+                &ctx,
+                &[b"xxEIC", b"ARxx"],
+                "stream: Eicar-Signature FOUND",
+            )
+            .await;
+            assert_eq!(
+                verdict.unwrap(),
+                Verdict::Infected(String::from("Eicar-Signature"))
+            );
+        });
+    }
+
+    #[test]
+    fn error() {
+        run_test(async |ctx| {
+            let (verdict, _) =
+                scan(&ctx, &[b"EICAR"], "INSTREAM size limit exceeded. ERROR")
+                    .await;
+            assert!(verdict.is_err());
+        });
+    }
+
+    #[test]
+    fn parse_address() {
+        assert_eq!(
+            Address::parse("/run/clamav/clamd.ctl").unwrap(),
+            Address::Unix(std::path::PathBuf::from("/run/clamav/clamd.ctl"))
+        );
+        assert_eq!(
+            Address::parse("localhost:3310").unwrap(),
+            Address::Tcp(String::from("localhost:3310"))
+        );
+        assert!(Address::parse("clamd.ctl").is_err());
+    }
+}
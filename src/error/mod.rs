@@ -96,3 +96,11 @@ impl From<daemonize::Error> for WuffError {
         }
     }
 }
+
+impl From<lopdf::Error> for WuffError {
+    fn from(err: lopdf::Error) -> WuffError {
+        WuffError {
+            message: format!("pdf: {}", err),
+        }
+    }
+}
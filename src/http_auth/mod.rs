@@ -0,0 +1,94 @@
+// What comes in an Authorization header: either HTTP basic auth, which is a
+// username and a password, or a bearer token, which is all there is to an
+// API token.
+#[derive(Debug, PartialEq)]
+pub enum HttpCredentials {
+    Basic { username: String, password: String },
+    Bearer(String),
+}
+
+pub struct HttpAuth {
+    basic: regex::Regex,
+    bearer: regex::Regex,
+}
+
+impl HttpAuth {
+    pub fn new() -> Self {
+        Self {
+            basic: regex::Regex::new(r"^\s*(?i:Basic)\s+(.+?)\s*$")
+                .expect("regex"),
+            bearer: regex::Regex::new(r"^\s*(?i:Bearer)\s+(.+?)\s*$")
+                .expect("regex"),
+        }
+    }
+
+    pub fn parse(&self, auth_header: &str) -> Option<HttpCredentials> {
+        if let Some(m) = self.bearer.captures(auth_header) {
+            return Some(HttpCredentials::Bearer(
+                m.get(1).unwrap().as_str().into(),
+            ));
+        }
+
+        let Some(m) = self.basic.captures(auth_header) else {
+            return None;
+        };
+
+        let Ok(userpass) = base64::Engine::decode(
+            &base64::prelude::BASE64_STANDARD,
+            m.get(1).unwrap().as_str(),
+        ) else {
+            return None;
+        };
+
+        let Ok(userpass) = String::from_utf8(userpass) else {
+            return None;
+        };
+
+        let Some((user, pass)) = userpass.split_once(':') else {
+            return None;
+        };
+        Some(HttpCredentials::Basic {
+            username: user.into(),
+            password: pass.into(),
+        })
+    }
+
+    // For when only HTTP basic auth will do.
+    pub fn parse_basic(&self, auth_header: &str) -> Option<(String, String)> {
+        match self.parse(auth_header) {
+            Some(HttpCredentials::Basic { username, password }) => {
+                Some((username, password))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn basic() {
+        // "Aladdin:open sesame", from RFC 7617.
+        let s = "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ==";
+        assert_eq!(
+            HttpAuth::new().parse(s),
+            Some(HttpCredentials::Basic {
+                username: String::from("Aladdin"),
+                password: String::from("open sesame")
+            })
+        );
+        assert!(HttpAuth::new().parse("Basic !!!").is_none());
+    }
+
+    #[test]
+    fn bearer() {
+        let s = " bearer s2b_abc-123 ";
+        assert_eq!(
+            HttpAuth::new().parse(s),
+            Some(HttpCredentials::Bearer(String::from("s2b_abc-123")))
+        );
+        assert!(HttpAuth::new().parse_basic(s).is_none());
+    }
+}
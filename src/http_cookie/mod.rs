@@ -0,0 +1,37 @@
+// The value of a cookie, out of a Cookie header. Browsers send every cookie
+// that applies as "name=value; name=value", and if the same name shows up
+// more than once, the first one is the most specific, so that's the one we
+// go with.
+pub fn get<'a>(cookie_header: &'a str, name: &str) -> Option<&'a str> {
+    for cookie in cookie_header.split(';') {
+        let Some((cookie_name, value)) = cookie.split_once('=') else {
+            continue;
+        };
+        if cookie_name.trim() == name {
+            let value: &str = value.trim();
+            return Some(
+                value
+                    .strip_prefix('"')
+                    .and_then(|value| value.strip_suffix('"'))
+                    .unwrap_or(value),
+            );
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn several_cookies() {
+        let s = "theme=dark; session=abc123;lang=\"en\"; session=xyz";
+        assert_eq!(get(s, "session"), Some("abc123"));
+        assert_eq!(get(s, "lang"), Some("en"));
+        assert_eq!(get(s, "theme"), Some("dark"));
+        assert_eq!(get(s, "missing"), None);
+        assert_eq!(get("", "session"), None);
+        assert_eq!(get("session", "session"), None);
+    }
+}
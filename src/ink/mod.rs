@@ -0,0 +1,649 @@
+// Measures how much of a scanned page has anything on it, so that pages which
+// came out blank can be told apart from pages that didn't. This only works on
+// pages that are nothing but a single image, which is what scanners produce.
+// For anything else (pages with text on them, pages made of several images,
+// images in formats we can't decode) the answer is "don't know", and a page
+// we don't know about is never considered blank.
+
+// Pixels darker than this, on a scale where 0 is black and 255 is white,
+// count as ink.
+const DARK_LEVEL: u32 = 128;
+
+// Scanners tend to leave shadows along the edges of the page, so this
+// fraction of the page is ignored on each side.
+const MARGIN: f64 = 0.05;
+
+// Returns the fraction of the page (from 0.0 to 1.0) that's covered in ink,
+// or None if we can't tell.
+pub fn page_coverage(
+    doc: &lopdf::Document,
+    page_id: lopdf::ObjectId,
+) -> Option<f64> {
+    if !doc.get_page_fonts(page_id).ok()?.is_empty() {
+        return None;
+    }
+    let images: Vec<lopdf::xobject::PdfImage> =
+        doc.get_page_images(page_id).ok()?;
+    let [image] = images.as_slice() else {
+        return None;
+    };
+    image_coverage(image)
+}
+
+// Returns the numbers of the pages whose coverage is known to be less than
+// `threshold`.
+pub fn blank_pages(doc: &lopdf::Document, threshold: f64) -> Vec<u32> {
+    doc.get_pages()
+        .into_iter()
+        .filter(|(_, page_id)| {
+            page_coverage(doc, *page_id)
+                .is_some_and(|coverage| coverage < threshold)
+        })
+        .map(|(page_number, _)| page_number)
+        .collect()
+}
+
+// Which part of the image we're looking at, and how much ink we've found in
+// it so far.
+struct Counter {
+    x0: usize,
+    x1: usize,
+    y0: usize,
+    y1: usize,
+    dark: u64,
+}
+
+impl Counter {
+    fn new(width: usize, height: usize) -> Self {
+        let margin_x: usize = (width as f64 * MARGIN) as usize;
+        let margin_y: usize = (height as f64 * MARGIN) as usize;
+        Self {
+            x0: margin_x,
+            x1: width - margin_x,
+            y0: margin_y,
+            y1: height - margin_y,
+            dark: 0,
+        }
+    }
+
+    fn wants_row(&self, y: usize) -> bool {
+        y >= self.y0 && y < self.y1
+    }
+
+    fn coverage(&self) -> Option<f64> {
+        let total: u64 = ((self.x1 - self.x0) * (self.y1 - self.y0)) as u64;
+        if total == 0 {
+            None
+        } else {
+            Some(self.dark as f64 / total as f64)
+        }
+    }
+}
+
+fn get_i64(dict: &lopdf::Dictionary, key: &[u8]) -> Option<i64> {
+    dict.get(key).and_then(lopdf::Object::as_i64).ok()
+}
+
+fn get_bool(dict: &lopdf::Dictionary, key: &[u8]) -> Option<bool> {
+    dict.get(key).and_then(lopdf::Object::as_bool).ok()
+}
+
+fn image_coverage(image: &lopdf::xobject::PdfImage) -> Option<f64> {
+    let dict: &lopdf::Dictionary = image.origin_dict;
+    if get_bool(dict, b"ImageMask") == Some(true) {
+        return None;
+    }
+    let width: usize = usize::try_from(image.width).ok()?;
+    let height: usize = usize::try_from(image.height).ok()?;
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let components: usize = match image.color_space.as_deref() {
+        Some("DeviceGray" | "CalGray") => 1,
+        Some("DeviceRGB" | "CalRGB") => 3,
+        Some("DeviceCMYK") => 4,
+        _ => return None,
+    };
+    // A Decode array of [1 0 ...] means the samples are inverted. Anything
+    // fancier than that, we don't handle.
+    let invert: bool = match dict.get(b"Decode").and_then(|d| d.as_array()) {
+        Err(_) => false,
+        Ok(decode) => match decode.first().and_then(|d| d.as_float().ok()) {
+            Some(0.0) => false,
+            Some(1.0) => true,
+            _ => return None,
+        },
+    };
+    let filters: &[String] = image.filters.as_deref().unwrap_or(&[]);
+    let parms: Option<&lopdf::Dictionary> = dict
+        .get(b"DecodeParms")
+        .and_then(lopdf::Object::as_dict)
+        .ok();
+    let mut counter: Counter = Counter::new(width, height);
+
+    match filters {
+        [] => {
+            let bpc: usize = image.bits_per_component? as usize;
+            count_samples(
+                image.content,
+                width,
+                height,
+                components,
+                bpc,
+                invert,
+                &mut counter,
+            )?;
+        }
+        [filter] if filter == "FlateDecode" || filter == "LZWDecode" => {
+            let bpc: usize = image.bits_per_component? as usize;
+            let row_len: usize = (width * components * bpc).div_ceil(8);
+            let predictor: i64 =
+                parms.and_then(|p| get_i64(p, b"Predictor")).unwrap_or(1);
+            // PNG predictors put an extra byte at the start of each row.
+            let expected_len: usize = if predictor >= 10 {
+                (row_len + 1) * height
+            } else {
+                row_len * height
+            };
+            let mut data: Vec<u8> = if filter == "FlateDecode" {
+                inflate(image.content, expected_len)?
+            } else {
+                // An EarlyChange of 1 (the default) is what TIFF does.
+                let early_change: bool = parms
+                    .and_then(|p| get_i64(p, b"EarlyChange"))
+                    .unwrap_or(1)
+                    != 0;
+                lzw_decode(image.content, early_change, expected_len)?
+            };
+            data.truncate(expected_len);
+            if data.len() < expected_len {
+                return None;
+            }
+            let data: Vec<u8> = match predictor {
+                1 => data,
+                2 => undo_tiff_predictor(data, row_len, components, bpc)?,
+                10..=15 => undo_png_predictor(
+                    &data,
+                    row_len,
+                    std::cmp::max(1, components * bpc / 8),
+                )?,
+                _ => return None,
+            };
+            count_samples(
+                &data,
+                width,
+                height,
+                components,
+                bpc,
+                invert,
+                &mut counter,
+            )?;
+        }
+        [filter] if filter == "DCTDecode" => {
+            count_jpeg(image.content, width, height, &mut counter)?;
+        }
+        [filter] if filter == "CCITTFaxDecode" => {
+            if components != 1 {
+                return None;
+            }
+            let k: i64 = parms.and_then(|p| get_i64(p, b"K")).unwrap_or(0);
+            let columns: i64 =
+                parms.and_then(|p| get_i64(p, b"Columns")).unwrap_or(1728);
+            if columns as usize != width {
+                return None;
+            }
+            count_ccitt(
+                image.content,
+                k,
+                width,
+                height,
+                invert,
+                &mut counter,
+            )?;
+        }
+        _ => return None,
+    }
+    counter.coverage()
+}
+
+fn inflate(data: &[u8], expected_len: usize) -> Option<Vec<u8>> {
+    let mut out: Vec<u8> = Vec::with_capacity(expected_len);
+    // Don't let a malicious stream decompress into more than we asked for.
+    let decoder: flate2::read::ZlibDecoder<&[u8]> =
+        flate2::read::ZlibDecoder::new(data);
+    // A truncated stream still counts, if it got as far as we need it to.
+    let _ = std::io::Read::read_to_end(
+        &mut std::io::Read::take(decoder, expected_len as u64),
+        &mut out,
+    );
+    Some(out)
+}
+
+fn lzw_decode(
+    data: &[u8],
+    early_change: bool,
+    expected_len: usize,
+) -> Option<Vec<u8>> {
+    let mut decoder: weezl::decode::Decoder = if early_change {
+        weezl::decode::Decoder::with_tiff_size_switch(weezl::BitOrder::Msb, 8)
+    } else {
+        weezl::decode::Decoder::new(weezl::BitOrder::Msb, 8)
+    };
+    let mut out: Vec<u8> = vec![0u8; expected_len];
+    let mut consumed_in: usize = 0;
+    let mut consumed_out: usize = 0;
+    // The decoder works in bursts, so keep going until it's done, runs out of
+    // input, or has filled up our buffer.
+    while consumed_out < expected_len {
+        let result: weezl::BufferResult = decoder
+            .decode_bytes(&data[consumed_in..], &mut out[consumed_out..]);
+        consumed_in += result.consumed_in;
+        consumed_out += result.consumed_out;
+        match result.status {
+            Ok(weezl::LzwStatus::Ok) => {
+                if result.consumed_in == 0 && result.consumed_out == 0 {
+                    break;
+                }
+            }
+            Ok(weezl::LzwStatus::Done | weezl::LzwStatus::NoProgress) => {
+                break;
+            }
+            Err(_) => return None,
+        }
+    }
+    out.truncate(consumed_out);
+    Some(out)
+}
+
+fn undo_tiff_predictor(
+    mut data: Vec<u8>,
+    row_len: usize,
+    components: usize,
+    bpc: usize,
+) -> Option<Vec<u8>> {
+    if bpc != 8 {
+        return None;
+    }
+    for row in data.chunks_exact_mut(row_len) {
+        for i in components..row.len() {
+            row[i] = row[i].wrapping_add(row[i - components]);
+        }
+    }
+    Some(data)
+}
+
+fn undo_png_predictor(
+    data: &[u8],
+    row_len: usize,
+    bytes_per_pixel: usize,
+) -> Option<Vec<u8>> {
+    let mut out: Vec<u8> = Vec::with_capacity(data.len());
+    let mut prev: Vec<u8> = vec![0u8; row_len];
+    for row in data.chunks_exact(row_len + 1) {
+        let filter_type: u8 = row[0];
+        let mut cur: Vec<u8> = row[1..].to_vec();
+        for i in 0..row_len {
+            let a: u8 = if i >= bytes_per_pixel {
+                cur[i - bytes_per_pixel]
+            } else {
+                0
+            };
+            let b: u8 = prev[i];
+            let c: u8 = if i >= bytes_per_pixel {
+                prev[i - bytes_per_pixel]
+            } else {
+                0
+            };
+            let predicted: u8 = match filter_type {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => {
+                    let p: i16 = a as i16 + b as i16 - c as i16;
+                    let pa: i16 = (p - a as i16).abs();
+                    let pb: i16 = (p - b as i16).abs();
+                    let pc: i16 = (p - c as i16).abs();
+                    if pa <= pb && pa <= pc {
+                        a
+                    } else if pb <= pc {
+                        b
+                    } else {
+                        c
+                    }
+                }
+                _ => return None,
+            };
+            cur[i] = cur[i].wrapping_add(predicted);
+        }
+        out.extend_from_slice(&cur);
+        prev = cur;
+    }
+    Some(out)
+}
+
+// Lightness of a pixel, 0 (black) to 255 (white), given its components scaled
+// to 0..=255.
+fn lightness(pixel: &[u32]) -> u32 {
+    match pixel {
+        [gray] => *gray,
+        [r, g, b] => (r * 299 + g * 587 + b * 114) / 1000,
+        [c, m, y, k] => {
+            let ink: u32 = (c * 299 + m * 587 + y * 114) / 1000 + k;
+            255 - std::cmp::min(ink, 255)
+        }
+        _ => 255,
+    }
+}
+
+fn count_samples(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    components: usize,
+    bpc: usize,
+    invert: bool,
+    counter: &mut Counter,
+) -> Option<()> {
+    if !matches!(bpc, 1 | 2 | 4 | 8 | 16) {
+        return None;
+    }
+    let row_len: usize = (width * components * bpc).div_ceil(8);
+    if data.len() < row_len * height {
+        return None;
+    }
+    let max: u32 = (1u32 << bpc) - 1;
+    let mut pixel: Vec<u32> = vec![0; components];
+    for y in counter.y0..std::cmp::min(counter.y1, height) {
+        let row: &[u8] = &data[y * row_len..(y + 1) * row_len];
+        for x in counter.x0..counter.x1 {
+            for (c, value) in pixel.iter_mut().enumerate() {
+                let bit: usize = (x * components + c) * bpc;
+                let sample: u32 = match bpc {
+                    16 => u16::from_be_bytes([row[bit / 8], row[bit / 8 + 1]])
+                        as u32,
+                    8 => row[bit / 8] as u32,
+                    _ => {
+                        let shift: usize = 8 - bpc - (bit % 8);
+                        ((row[bit / 8] >> shift) as u32) & max
+                    }
+                };
+                let sample: u32 = if invert { max - sample } else { sample };
+                *value = sample * 255 / max;
+            }
+            if lightness(&pixel) < DARK_LEVEL {
+                counter.dark += 1;
+            }
+        }
+    }
+    Some(())
+}
+
+fn count_jpeg(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    counter: &mut Counter,
+) -> Option<()> {
+    let mut decoder: jpeg_decoder::Decoder<&[u8]> =
+        jpeg_decoder::Decoder::new(data);
+    let pixels: Vec<u8> = decoder.decode().ok()?;
+    let info: jpeg_decoder::ImageInfo = decoder.info()?;
+    if info.width as usize != width || info.height as usize != height {
+        return None;
+    }
+    // jpeg-decoder takes care of Adobe's inverted CMYK itself, and always
+    // hands back CMYK with 255 meaning no ink, so the image's Decode array
+    // doesn't come into it.
+    let (components, bpc, invert): (usize, usize, bool) =
+        match info.pixel_format {
+            jpeg_decoder::PixelFormat::L8 => (1, 8, false),
+            jpeg_decoder::PixelFormat::L16 => (1, 16, false),
+            jpeg_decoder::PixelFormat::RGB24 => (3, 8, false),
+            jpeg_decoder::PixelFormat::CMYK32 => (4, 8, true),
+        };
+    count_samples(&pixels, width, height, components, bpc, invert, counter)
+}
+
+fn count_ccitt(
+    data: &[u8],
+    k: i64,
+    width: usize,
+    height: usize,
+    invert: bool,
+    counter: &mut Counter,
+) -> Option<()> {
+    let width16: u16 = u16::try_from(width).ok()?;
+    let height16: u16 = u16::try_from(height).ok()?;
+    let mut y: usize = 0;
+    // The decoder gives us, for each row, the positions where the color
+    // changes, starting from white.
+    let mut count_row = |transitions: &[u16]| {
+        if counter.wants_row(y) {
+            let mut start: usize = 0;
+            let mut black: bool = false;
+            for end in transitions
+                .iter()
+                .map(|t| *t as usize)
+                .chain(std::iter::once(width))
+            {
+                if black != invert {
+                    let lo: usize = std::cmp::max(start, counter.x0);
+                    let hi: usize = std::cmp::min(end, counter.x1);
+                    if hi > lo {
+                        counter.dark += (hi - lo) as u64;
+                    }
+                }
+                start = end;
+                black = !black;
+            }
+        }
+        y += 1;
+    };
+    let iter = data.iter().copied();
+    if k < 0 {
+        fax::decoder::decode_g4(
+            iter,
+            width16,
+            Some(height16),
+            &mut count_row,
+        )?;
+    } else if k == 0 {
+        fax::decoder::decode_g3(iter, &mut count_row)?;
+    } else {
+        // Mixed 1D/2D coding isn't something the decoder does.
+        return None;
+    }
+    if y < height {
+        return None;
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pdf_with_image(image: &crate::pdf::Image, data: &[u8]) -> Vec<u8> {
+        let mut pdf: crate::pdf::Writer<Vec<u8>> =
+            crate::pdf::Writer::new(Vec::new()).unwrap();
+        pdf.add_image_page(image, &mut &data[..], data.len() as u64)
+            .unwrap();
+        pdf.finish().unwrap()
+    }
+
+    fn coverage_of(pdf: &[u8]) -> Option<f64> {
+        let doc: lopdf::Document = lopdf::Document::load_mem(pdf).unwrap();
+        let page_id: lopdf::ObjectId = doc.get_pages()[&1];
+        page_coverage(&doc, page_id)
+    }
+
+    fn gray_image(
+        width: u32,
+        height: u32,
+        filter: crate::pdf::Filter,
+    ) -> crate::pdf::Image {
+        crate::pdf::Image {
+            width,
+            height,
+            color_space: crate::pdf::ColorSpace::Gray,
+            bits_per_component: 8,
+            filter,
+            invert: false,
+            resolution: (72.0, 72.0),
+        }
+    }
+
+    // A white 100x100 image with a black square of the given size in the
+    // middle.
+    fn square(size: usize) -> Vec<u8> {
+        let mut data: Vec<u8> = vec![255u8; 100 * 100];
+        let start: usize = 50 - size / 2;
+        for y in start..start + size {
+            for x in start..start + size {
+                data[y * 100 + x] = 0;
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn uncompressed_gray() {
+        let image = gray_image(100, 100, crate::pdf::Filter::None);
+        assert_eq!(
+            coverage_of(&pdf_with_image(&image, &square(0))),
+            Some(0.0)
+        );
+        // The margins are ignored, so there are 90x90 pixels that count.
+        let coverage: f64 =
+            coverage_of(&pdf_with_image(&image, &square(9))).unwrap();
+        assert!((coverage - 0.01).abs() < 1e-9);
+    }
+
+    #[test]
+    fn margins_are_ignored() {
+        let image = gray_image(100, 100, crate::pdf::Filter::None);
+        let mut data: Vec<u8> = vec![255u8; 100 * 100];
+        for y in 0..100 {
+            for x in 0..5 {
+                data[y * 100 + x] = 0;
+            }
+        }
+        assert_eq!(coverage_of(&pdf_with_image(&image, &data)), Some(0.0));
+    }
+
+    #[test]
+    fn inverted_bilevel() {
+        // 1 bit per pixel with 1 meaning black, which is how a TIFF with
+        // WhiteIsZero ends up.
+        let mut image = gray_image(16, 20, crate::pdf::Filter::None);
+        image.bits_per_component = 1;
+        image.invert = true;
+        let mut data: Vec<u8> = vec![0u8; 2 * 20];
+        data[2 * 10] = 0xff;
+        let coverage: f64 =
+            coverage_of(&pdf_with_image(&image, &data)).unwrap();
+        // One row of 8 black pixels. The image is too narrow to have side
+        // margins, so that's out of 18 rows of 16 pixels.
+        assert!((coverage - 8.0 / (18.0 * 16.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn flate_with_tiff_predictor() {
+        let raw: Vec<u8> = square(20);
+        let mut differenced: Vec<u8> = raw.clone();
+        for row in differenced.chunks_exact_mut(100) {
+            for x in (1..100).rev() {
+                row[x] = row[x].wrapping_sub(row[x - 1]);
+            }
+        }
+        let mut encoder: flate2::write::ZlibEncoder<Vec<u8>> =
+            flate2::write::ZlibEncoder::new(
+                Vec::new(),
+                flate2::Compression::default(),
+            );
+        std::io::Write::write_all(&mut encoder, &differenced).unwrap();
+        let compressed: Vec<u8> = encoder.finish().unwrap();
+        let image = gray_image(
+            100,
+            100,
+            crate::pdf::Filter::Flate { predictor: true },
+        );
+        let coverage: f64 =
+            coverage_of(&pdf_with_image(&image, &compressed)).unwrap();
+        assert!((coverage - 400.0 / 8100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn lzw() {
+        let raw: Vec<u8> = square(30);
+        let compressed: Vec<u8> =
+            weezl::encode::Encoder::with_tiff_size_switch(
+                weezl::BitOrder::Msb,
+                8,
+            )
+            .encode(&raw)
+            .unwrap();
+        let image =
+            gray_image(100, 100, crate::pdf::Filter::Lzw { predictor: false });
+        let coverage: f64 =
+            coverage_of(&pdf_with_image(&image, &compressed)).unwrap();
+        assert!((coverage - 900.0 / 8100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ccitt_g4() {
+        let width: u16 = 100;
+        let mut encoder: fax::encoder::Encoder<fax::VecWriter> =
+            fax::encoder::Encoder::new(fax::VecWriter::new());
+        for y in 0..100 {
+            let row: Vec<fax::Color> = (0..width)
+                .map(|x| {
+                    if (40..60).contains(&x) && (40..60).contains(&y) {
+                        fax::Color::Black
+                    } else {
+                        fax::Color::White
+                    }
+                })
+                .collect();
+            encoder.encode_line(row.into_iter(), width).unwrap();
+        }
+        let compressed: Vec<u8> = encoder.finish().unwrap().finish();
+        let mut image = gray_image(
+            100,
+            100,
+            crate::pdf::Filter::CcittFax {
+                k: -1,
+                black_is_1: false,
+                encoded_byte_align: false,
+            },
+        );
+        image.bits_per_component = 1;
+        let coverage: f64 =
+            coverage_of(&pdf_with_image(&image, &compressed)).unwrap();
+        assert!((coverage - 400.0 / 8100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn undecodable_image() {
+        let image = gray_image(100, 100, crate::pdf::Filter::Dct);
+        assert_eq!(coverage_of(&pdf_with_image(&image, b"garbage")), None);
+    }
+
+    #[test]
+    fn finds_blank_pages() {
+        let image = gray_image(100, 100, crate::pdf::Filter::None);
+        let mut pdf: crate::pdf::Writer<Vec<u8>> =
+            crate::pdf::Writer::new(Vec::new()).unwrap();
+        for size in [0, 20, 1, 0] {
+            let data: Vec<u8> = square(size);
+            pdf.add_image_page(&image, &mut &data[..], data.len() as u64)
+                .unwrap();
+        }
+        let doc: lopdf::Document =
+            lopdf::Document::load_mem(&pdf.finish().unwrap()).unwrap();
+        // A single speck of dust doesn't count.
+        assert_eq!(blank_pages(&doc, 0.002), vec![1, 3, 4]);
+        assert_eq!(blank_pages(&doc, 0.0), Vec::<u32>::new());
+    }
+}
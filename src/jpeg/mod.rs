@@ -0,0 +1,208 @@
+// Just enough of a JPEG parser to be able to wrap a JPEG file, unmodified,
+// inside a PDF. PDF viewers know how to decode JPEG data themselves (that's
+// what the DCTDecode filter is) so all we need from the file is the handful
+// of things that have to go into the image dictionary.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct JpegInfo {
+    pub width: u32,
+    pub height: u32,
+    pub components: u8,
+    pub bits_per_component: u8,
+    // Dots per inch, horizontally and vertically, if the file says.
+    pub resolution: Option<(f64, f64)>,
+    // Adobe's CMYK JPEGs store their samples inverted.
+    pub adobe: bool,
+}
+
+fn read_u8<R: std::io::Read>(
+    r: &mut R,
+) -> Result<u8, crate::error::WuffError> {
+    let mut buf: [u8; 1] = [0u8; 1];
+    r.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u16<R: std::io::Read>(
+    r: &mut R,
+) -> Result<u16, crate::error::WuffError> {
+    let mut buf: [u8; 2] = [0u8; 2];
+    r.read_exact(&mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+
+pub fn parse_header<R: std::io::Read>(
+    r: &mut R,
+) -> Result<JpegInfo, crate::error::WuffError> {
+    if read_u16(r)? != 0xffd8 {
+        return Err(crate::error::WuffError::from("jpeg: missing SOI marker"));
+    }
+
+    let mut resolution: Option<(f64, f64)> = None;
+    let mut adobe: bool = false;
+    loop {
+        if read_u8(r)? != 0xff {
+            return Err(crate::error::WuffError::from(
+                "jpeg: expected a marker",
+            ));
+        }
+        // Any number of 0xff bytes may precede the actual marker code.
+        let mut marker: u8 = read_u8(r)?;
+        while marker == 0xff {
+            marker = read_u8(r)?;
+        }
+        match marker {
+            // Markers that don't have a length field.
+            0x01 | 0xd0..=0xd7 => continue,
+            0xd9 | 0xda => {
+                return Err(crate::error::WuffError::from(
+                    "jpeg: no SOF marker before image data",
+                ));
+            }
+            _ => {}
+        }
+
+        let len: u16 = read_u16(r)?;
+        if len < 2 {
+            return Err(crate::error::WuffError::from(
+                "jpeg: invalid segment length",
+            ));
+        }
+        let mut segment: Vec<u8> = vec![0u8; (len - 2) as usize];
+        r.read_exact(&mut segment)?;
+
+        match marker {
+            // APP0, which is where JFIF puts the pixel density.
+            0xe0 if segment.len() >= 12 && segment.starts_with(b"JFIF\0") => {
+                let units: u8 = segment[7];
+                let x: f64 =
+                    u16::from_be_bytes([segment[8], segment[9]]) as f64;
+                let y: f64 =
+                    u16::from_be_bytes([segment[10], segment[11]]) as f64;
+                if x > 0.0 && y > 0.0 {
+                    match units {
+                        1 => resolution = Some((x, y)),
+                        2 => resolution = Some((x * 2.54, y * 2.54)),
+                        _ => {}
+                    }
+                }
+            }
+            // APP14, which is where Adobe identifies itself.
+            0xee if segment.starts_with(b"Adobe") => {
+                adobe = true;
+            }
+            // SOF0 through SOF15, except for the handful of codes in that
+            // range which mean something else.
+            0xc0..=0xcf if !matches!(marker, 0xc4 | 0xc8 | 0xcc) => {
+                if segment.len() < 6 {
+                    return Err(crate::error::WuffError::from(
+                        "jpeg: truncated SOF segment",
+                    ));
+                }
+                let bits_per_component: u8 = segment[0];
+                let height: u32 =
+                    u16::from_be_bytes([segment[1], segment[2]]) as u32;
+                let width: u32 =
+                    u16::from_be_bytes([segment[3], segment[4]]) as u32;
+                let components: u8 = segment[5];
+                if width == 0 || height == 0 {
+                    return Err(crate::error::WuffError::from(
+                        "jpeg: image has no size",
+                    ));
+                }
+                if !matches!(components, 1 | 3 | 4) {
+                    return Err(crate::error::WuffError::from(format!(
+                        "jpeg: unsupported number of components: {}",
+                        components
+                    )));
+                }
+                return Ok(JpegInfo {
+                    width,
+                    height,
+                    components,
+                    bits_per_component,
+                    resolution,
+                    adobe,
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn segment(marker: u8, payload: &[u8]) -> Vec<u8> {
+        let mut v: Vec<u8> = vec![0xff, marker];
+        v.extend_from_slice(&((payload.len() + 2) as u16).to_be_bytes());
+        v.extend_from_slice(payload);
+        v
+    }
+
+    fn sof(width: u16, height: u16, components: u8) -> Vec<u8> {
+        let mut payload: Vec<u8> = vec![8];
+        payload.extend_from_slice(&height.to_be_bytes());
+        payload.extend_from_slice(&width.to_be_bytes());
+        payload.push(components);
+        for i in 0..components {
+            payload.extend_from_slice(&[i + 1, 0x11, 0]);
+        }
+        segment(0xc0, &payload)
+    }
+
+    #[test]
+    fn jfif_with_dpi() {
+        let mut data: Vec<u8> = vec![0xff, 0xd8];
+        data.extend(segment(0xe0, b"JFIF\0\x01\x02\x01\x01\x2c\x01\x2c\0\0"));
+        data.extend(sof(2480, 3508, 3));
+        let info = parse_header(&mut data.as_slice()).unwrap();
+        assert_eq!(
+            info,
+            JpegInfo {
+                width: 2480,
+                height: 3508,
+                components: 3,
+                bits_per_component: 8,
+                resolution: Some((300.0, 300.0)),
+                adobe: false,
+            }
+        );
+    }
+
+    #[test]
+    fn no_density_and_fill_bytes() {
+        let mut data: Vec<u8> = vec![0xff, 0xd8, 0xff];
+        data.extend(segment(0xdb, &[0u8; 65]));
+        data.extend(sof(10, 20, 1));
+        let info = parse_header(&mut data.as_slice()).unwrap();
+        assert_eq!(info.width, 10);
+        assert_eq!(info.height, 20);
+        assert_eq!(info.components, 1);
+        assert_eq!(info.resolution, None);
+    }
+
+    #[test]
+    fn adobe_cmyk() {
+        let mut data: Vec<u8> = vec![0xff, 0xd8];
+        data.extend(segment(0xee, b"Adobe\0\x64\0\0\0\0\0"));
+        data.extend(sof(10, 20, 4));
+        let info = parse_header(&mut data.as_slice()).unwrap();
+        assert!(info.adobe);
+        assert_eq!(info.components, 4);
+    }
+
+    #[test]
+    fn not_a_jpeg() {
+        let data: &[u8] = b"%PDF-1.4\n";
+        assert!(parse_header(&mut &data[..]).is_err());
+    }
+
+    #[test]
+    fn image_data_before_sof() {
+        let mut data: Vec<u8> = vec![0xff, 0xd8];
+        data.extend(segment(0xda, &[0u8; 10]));
+        assert!(parse_header(&mut data.as_slice()).is_err());
+    }
+}
@@ -1,7 +1,20 @@
+pub mod admin;
+pub mod api_token;
 pub mod chunker;
+pub mod clamd;
 pub mod ctx;
 pub mod error;
 pub mod http_accept_header;
-pub mod http_basic_auth;
+pub mod http_auth;
+pub mod http_cookie;
+pub mod ink;
+pub mod jpeg;
+pub mod name_hint;
+pub mod pdf;
 pub mod pwhash;
+pub mod schedule;
+pub mod signed_link;
+pub mod template;
+pub mod tiff;
+pub mod totp;
 pub mod util;
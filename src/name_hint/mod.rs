@@ -0,0 +1,195 @@
+// Name hints end up in blob names, so before they get anywhere near one,
+// they're normalized and checked. Some things just get fixed up (Unicode
+// normalization, and slashes if they aren't allowed), and everything else
+// that's wrong with a name hint is an error, so that whoever typed it in gets
+// told about it instead of getting a blob with an odd name.
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Normalization {
+    None,
+    #[default]
+    Nfc,
+    Nfkc,
+}
+
+// Slashes in blob names make virtual folders, which might be what's wanted,
+// or might not.
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Slashes {
+    // Each slash (or backslash) becomes a "-".
+    #[default]
+    Replace,
+    // Slashes are folder separators, and backslashes become "/".
+    Allow,
+}
+
+#[derive(Clone, Debug)]
+pub struct Sanitizer {
+    allowed_characters: Option<regex::Regex>,
+    max_length: usize,
+    normalization: Normalization,
+    slashes: Slashes,
+}
+
+impl Sanitizer {
+    // `allowed_characters` is a regex that matches a single allowed
+    // character, like "[\p{L}\p{N} _.-]". Control characters are never
+    // allowed, whatever it says. The maximum length is in characters.
+    pub fn new(
+        allowed_characters: Option<&str>,
+        max_length: usize,
+        normalization: Normalization,
+        slashes: Slashes,
+    ) -> Result<Self, crate::error::WuffError> {
+        let allowed_characters: Option<regex::Regex> =
+            if let Some(allowed_characters) = allowed_characters {
+                Some(
+                    regex::Regex::new(&format!(
+                        "^(?:{})$",
+                        allowed_characters
+                    ))
+                    .map_err(|e| {
+                        crate::error::WuffError::from(format!(
+                            "allowed_characters: {}",
+                            e
+                        ))
+                    })?,
+                )
+            } else {
+                None
+            };
+        if max_length == 0 {
+            return Err(crate::error::WuffError::from(
+                "max_length must be at least 1",
+            ));
+        }
+        Ok(Self {
+            allowed_characters,
+            max_length,
+            normalization,
+            slashes,
+        })
+    }
+
+    pub fn sanitize(
+        &self,
+        name_hint: &str,
+    ) -> Result<String, crate::error::WuffError> {
+        let name_hint: String = match self.normalization {
+            Normalization::None => name_hint.to_string(),
+            Normalization::Nfc => {
+                unicode_normalization::UnicodeNormalization::nfc(name_hint)
+                    .collect()
+            }
+            Normalization::Nfkc => {
+                unicode_normalization::UnicodeNormalization::nfkc(name_hint)
+                    .collect()
+            }
+        };
+        let name_hint: &str = name_hint.trim();
+        if name_hint.is_empty() {
+            return Err(crate::error::WuffError::from("Name hint is empty"));
+        }
+        if let Some(c) = name_hint.chars().find(|c| c.is_control()) {
+            return Err(crate::error::WuffError::from(format!(
+                "Name hint contains a control character ({:?})",
+                c
+            )));
+        }
+        let name_hint: String = match self.slashes {
+            Slashes::Replace => name_hint.replace(['/', '\\'], "-"),
+            Slashes::Allow => {
+                let name_hint: String = name_hint.replace('\\', "/");
+                if name_hint.split('/').any(|folder| {
+                    folder.trim().is_empty() || folder == "." || folder == ".."
+                }) {
+                    return Err(crate::error::WuffError::from(format!(
+                        "Name hint {:?} has an empty, \".\" or \"..\" folder in it",
+                        name_hint
+                    )));
+                }
+                name_hint
+            }
+        };
+        if let Some(ref allowed_characters) = self.allowed_characters {
+            let mut buf: [u8; 4] = [0u8; 4];
+            if let Some(c) = name_hint.chars().find(|c| {
+                if *c == '/' && self.slashes == Slashes::Allow {
+                    false
+                } else {
+                    !allowed_characters.is_match(c.encode_utf8(&mut buf))
+                }
+            }) {
+                return Err(crate::error::WuffError::from(format!(
+                    "Name hint contains a character that isn't allowed ({:?})",
+                    c
+                )));
+            }
+        }
+        let length: usize = name_hint.chars().count();
+        if length > self.max_length {
+            return Err(crate::error::WuffError::from(format!(
+                "Name hint is {} characters long, and can be at most {}",
+                length, self.max_length
+            )));
+        }
+        Ok(name_hint)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn defaults() {
+        let sanitizer: Sanitizer = Sanitizer::new(
+            None,
+            10,
+            Normalization::default(),
+            Slashes::default(),
+        )
+        .unwrap();
+        assert_eq!(sanitizer.sanitize("  lease ").unwrap(), "lease");
+        assert_eq!(sanitizer.sanitize("a/b\\..").unwrap(), "a-b-..");
+        // "e" followed by a combining acute accent is one character, once
+        // it's been normalized.
+        assert_eq!(sanitizer.sanitize("cafe\u{301}").unwrap(), "caf\u{e9}");
+        assert!(sanitizer.sanitize("   ").is_err());
+        assert!(sanitizer.sanitize("a\tb").is_err());
+        assert!(sanitizer.sanitize("electric bill").is_err());
+    }
+
+    #[test]
+    fn folders() {
+        let sanitizer: Sanitizer = Sanitizer::new(
+            Some(r"[a-z ]"),
+            100,
+            Normalization::Nfkc,
+            Slashes::Allow,
+        )
+        .unwrap();
+        assert_eq!(sanitizer.sanitize("taxes/w two").unwrap(), "taxes/w two");
+        assert_eq!(sanitizer.sanitize("a\\b").unwrap(), "a/b");
+        assert!(sanitizer.sanitize("/taxes").is_err());
+        assert!(sanitizer.sanitize("taxes//a").is_err());
+        assert!(sanitizer.sanitize("taxes/../a").is_err());
+        assert!(sanitizer.sanitize("Taxes").is_err());
+        assert!(sanitizer.sanitize("taxes-2025").is_err());
+    }
+
+    #[test]
+    fn bad_allowed_characters() {
+        assert!(
+            Sanitizer::new(
+                Some("[a-z"),
+                100,
+                Normalization::None,
+                Slashes::Replace
+            )
+            .is_err()
+        );
+    }
+}
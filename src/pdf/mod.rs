@@ -0,0 +1,1020 @@
+// A write-only PDF generator, for the one kind of PDF we ever need to make:
+// one image per page, with the image filling the whole page. The image data
+// is copied into the output exactly as it is, so that (for example) a JPEG
+// stays a JPEG and a CCITT G4 fax image stays a CCITT G4 fax image. That way
+// nothing ever needs to be decoded, and the whole thing can stream through in
+// a small, constant amount of memory.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorSpace {
+    Gray,
+    Rgb,
+    Cmyk,
+}
+
+impl ColorSpace {
+    pub fn num_components(&self) -> u32 {
+        match self {
+            Self::Gray => 1,
+            Self::Rgb => 3,
+            Self::Cmyk => 4,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Gray => "/DeviceGray",
+            Self::Rgb => "/DeviceRGB",
+            Self::Cmyk => "/DeviceCMYK",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    None,
+    Dct,
+    // The bool says whether the TIFF-style horizontal differencing predictor
+    // was applied before compressing.
+    Flate {
+        predictor: bool,
+    },
+    Lzw {
+        predictor: bool,
+    },
+    CcittFax {
+        k: i32,
+        black_is_1: bool,
+        encoded_byte_align: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub color_space: ColorSpace,
+    pub bits_per_component: u8,
+    pub filter: Filter,
+    // If set, the samples mean the opposite of what they'd normally mean for
+    // the color space (e.g. 0 is white, for DeviceGray)
+    pub invert: bool,
+    // Dots per inch, horizontally and vertically.
+    pub resolution: (f64, f64),
+}
+
+impl Image {
+    fn dictionary(&self, len: u64) -> String {
+        let mut dict: String = format!(
+            "<< /Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace {} /BitsPerComponent {}",
+            self.width,
+            self.height,
+            self.color_space.name(),
+            self.bits_per_component
+        );
+        let predictor_parms = || {
+            format!(
+                " /DecodeParms << /Predictor 2 /Colors {} /BitsPerComponent {} /Columns {} >>",
+                self.color_space.num_components(),
+                self.bits_per_component,
+                self.width
+            )
+        };
+        match self.filter {
+            Filter::None => {}
+            Filter::Dct => dict.push_str(" /Filter /DCTDecode"),
+            Filter::Flate { predictor } => {
+                dict.push_str(" /Filter /FlateDecode");
+                if predictor {
+                    dict.push_str(&predictor_parms());
+                }
+            }
+            Filter::Lzw { predictor } => {
+                dict.push_str(" /Filter /LZWDecode");
+                if predictor {
+                    dict.push_str(&predictor_parms());
+                }
+            }
+            Filter::CcittFax {
+                k,
+                black_is_1,
+                encoded_byte_align,
+            } => {
+                dict.push_str(&format!(
+                    " /Filter /CCITTFaxDecode /DecodeParms << /K {} /Columns {} /Rows {} /BlackIs1 {} /EncodedByteAlign {} >>",
+                    k, self.width, self.height, black_is_1, encoded_byte_align
+                ));
+            }
+        }
+        if self.invert {
+            dict.push_str(" /Decode [");
+            for _ in 0..self.color_space.num_components() {
+                dict.push_str(" 1 0");
+            }
+            dict.push_str(" ]");
+        }
+        dict.push_str(&format!(" /Length {} >>", len));
+        dict
+    }
+}
+
+// Object 1 is always the catalog and object 2 is always the page tree. We
+// write both of those at the very end, once we know what all the pages are.
+const CATALOG_ID: usize = 1;
+const PAGES_ID: usize = 2;
+
+pub struct Writer<W: std::io::Write> {
+    inner: W,
+    offset: u64,
+    // Indexed by object number. Element 0 is unused, since there's no object
+    // 0.
+    object_offsets: Vec<u64>,
+    page_ids: Vec<usize>,
+}
+
+impl<W: std::io::Write> Writer<W> {
+    pub fn new(inner: W) -> Result<Self, crate::error::WuffError> {
+        let mut writer: Self = Self {
+            inner,
+            offset: 0,
+            object_offsets: vec![0, 0, 0],
+            page_ids: Vec::new(),
+        };
+        // The second line is a comment with some high-bit characters in it,
+        // which is the customary way of telling file transfer programs that
+        // this is a binary file.
+        writer.write(b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n")?;
+        Ok(writer)
+    }
+
+    pub fn num_pages(&self) -> usize {
+        self.page_ids.len()
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), crate::error::WuffError> {
+        self.inner.write_all(data)?;
+        self.offset += data.len() as u64;
+        Ok(())
+    }
+
+    fn allocate_object(&mut self) -> usize {
+        self.object_offsets.push(0);
+        self.object_offsets.len() - 1
+    }
+
+    fn begin_object(
+        &mut self,
+        id: usize,
+    ) -> Result<(), crate::error::WuffError> {
+        self.object_offsets[id] = self.offset;
+        self.write(format!("{} 0 obj\n", id).as_bytes())
+    }
+
+    fn write_object(
+        &mut self,
+        id: usize,
+        body: &str,
+    ) -> Result<(), crate::error::WuffError> {
+        self.begin_object(id)?;
+        self.write(body.as_bytes())?;
+        self.write(b"\nendobj\n")
+    }
+
+    // Adds a page consisting of nothing but the given image. Exactly `len`
+    // bytes of already-encoded image data are copied from `data`.
+    pub fn add_image_page<R: std::io::Read>(
+        &mut self,
+        image: &Image,
+        data: &mut R,
+        len: u64,
+    ) -> Result<(), crate::error::WuffError> {
+        if image.resolution.0 <= 0.0 || image.resolution.1 <= 0.0 {
+            return Err(crate::error::WuffError::from(
+                "pdf: invalid image resolution",
+            ));
+        }
+
+        let image_id: usize = self.allocate_object();
+        self.begin_object(image_id)?;
+        self.write(image.dictionary(len).as_bytes())?;
+        self.write(b"\nstream\n")?;
+        let copied: u64 = std::io::copy(
+            &mut std::io::Read::take(data, len),
+            &mut OffsetTracker(self),
+        )?;
+        if copied != len {
+            return Err(crate::error::WuffError::from(
+                "pdf: image data was shorter than expected",
+            ));
+        }
+        self.write(b"\nendstream\nendobj\n")?;
+
+        let page_width: f64 = image.width as f64 * 72.0 / image.resolution.0;
+        let page_height: f64 = image.height as f64 * 72.0 / image.resolution.1;
+        let content: String = format!(
+            "q {:.2} 0 0 {:.2} 0 0 cm /Im0 Do Q",
+            page_width, page_height
+        );
+        let content_id: usize = self.allocate_object();
+        self.write_object(
+            content_id,
+            &format!(
+                "<< /Length {} >>\nstream\n{}\nendstream",
+                content.len(),
+                content
+            ),
+        )?;
+
+        let page_id: usize = self.allocate_object();
+        self.write_object(
+            page_id,
+            &format!(
+                "<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {:.2} {:.2}] /Resources << /XObject << /Im0 {} 0 R >> >> /Contents {} 0 R >>",
+                PAGES_ID, page_width, page_height, image_id, content_id
+            ),
+        )?;
+        self.page_ids.push(page_id);
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W, crate::error::WuffError> {
+        if self.page_ids.is_empty() {
+            return Err(crate::error::WuffError::from("pdf: no pages"));
+        }
+        let kids: Vec<String> = self
+            .page_ids
+            .iter()
+            .map(|id| format!("{} 0 R", id))
+            .collect();
+        self.write_object(
+            PAGES_ID,
+            &format!(
+                "<< /Type /Pages /Kids [{}] /Count {} >>",
+                kids.join(" "),
+                self.page_ids.len()
+            ),
+        )?;
+        self.write_object(
+            CATALOG_ID,
+            &format!("<< /Type /Catalog /Pages {} 0 R >>", PAGES_ID),
+        )?;
+
+        let xref_offset: u64 = self.offset;
+        let mut xref: String =
+            format!("xref\n0 {}\n", self.object_offsets.len());
+        xref.push_str("0000000000 65535 f \n");
+        for off in &self.object_offsets[1..] {
+            xref.push_str(&format!("{:010} 00000 n \n", off));
+        }
+        xref.push_str(&format!(
+            "trailer\n<< /Size {} /Root {} 0 R >>\nstartxref\n{}\n%%EOF\n",
+            self.object_offsets.len(),
+            CATALOG_ID,
+            xref_offset
+        ));
+        self.write(xref.as_bytes())?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+// Lets std::io::copy() write through the Writer without losing track of the
+// offset.
+struct OffsetTracker<'a, W: std::io::Write>(&'a mut Writer<W>);
+
+impl<'a, W: std::io::Write> std::io::Write for OffsetTracker<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n: usize = self.0.inner.write(buf)?;
+        self.0.offset += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.inner.flush()
+    }
+}
+
+// Scanners that don't say what their resolution is are rare, but when it
+// happens we have to pick something. This makes one pixel come out as one
+// point.
+const DEFAULT_RESOLUTION: (f64, f64) = (72.0, 72.0);
+
+pub fn convert_jpeg<R, W>(
+    input: &mut R,
+    pdf: &mut Writer<W>,
+) -> Result<(), crate::error::WuffError>
+where
+    R: std::io::Read + std::io::Seek,
+    W: std::io::Write,
+{
+    input.seek(std::io::SeekFrom::Start(0))?;
+    let info: crate::jpeg::JpegInfo =
+        crate::jpeg::parse_header(&mut std::io::BufReader::new(&mut *input))?;
+    let len: u64 = input.seek(std::io::SeekFrom::End(0))?;
+    input.seek(std::io::SeekFrom::Start(0))?;
+    let color_space: ColorSpace = match info.components {
+        1 => ColorSpace::Gray,
+        3 => ColorSpace::Rgb,
+        _ => ColorSpace::Cmyk,
+    };
+    let image: Image = Image {
+        width: info.width,
+        height: info.height,
+        color_space,
+        bits_per_component: info.bits_per_component,
+        filter: Filter::Dct,
+        invert: info.adobe && color_space == ColorSpace::Cmyk,
+        resolution: info.resolution.unwrap_or(DEFAULT_RESOLUTION),
+    };
+    pdf.add_image_page(&image, input, len)
+}
+
+fn tiff_page_to_image(
+    page: &crate::tiff::TiffPage,
+) -> Result<Image, crate::error::WuffError> {
+    let unsupported = |what: &str| {
+        Err(crate::error::WuffError::from(format!(
+            "tiff: unsupported {}",
+            what
+        )))
+    };
+    if page.tiled {
+        return unsupported("tiled image");
+    }
+    if page.strip_offsets.is_empty() {
+        return unsupported("image with no strips");
+    }
+    if page.extra_samples != 0 {
+        return unsupported("image with alpha channel");
+    }
+    if page.samples_per_pixel > 1 && page.planar_configuration != 1 {
+        return unsupported("planar configuration");
+    }
+    if page.fill_order != 1 {
+        return unsupported("fill order");
+    }
+    let (color_space, invert): (ColorSpace, bool) =
+        match (page.photometric, page.samples_per_pixel) {
+            (crate::tiff::PHOTOMETRIC_WHITE_IS_ZERO, 1) => {
+                (ColorSpace::Gray, true)
+            }
+            (crate::tiff::PHOTOMETRIC_BLACK_IS_ZERO, 1) => {
+                (ColorSpace::Gray, false)
+            }
+            (crate::tiff::PHOTOMETRIC_RGB, 3) => (ColorSpace::Rgb, false),
+            (crate::tiff::PHOTOMETRIC_SEPARATED, 4) => {
+                (ColorSpace::Cmyk, false)
+            }
+            _ => return unsupported("photometric interpretation"),
+        };
+    if !matches!(page.bits_per_sample, 1 | 2 | 4 | 8 | 16) {
+        return unsupported("bits per sample");
+    }
+    // PDF wants 16-bit samples big-endian. The StripReader swaps them around
+    // in uncompressed images, but there's no getting at them in compressed
+    // ones.
+    if page.bits_per_sample == 16
+        && !page.big_endian
+        && page.compression != crate::tiff::COMPRESSION_NONE
+    {
+        return unsupported("compressed little-endian 16-bit image");
+    }
+    let predictor: bool = match page.predictor {
+        1 => false,
+        2 => true,
+        _ => return unsupported("predictor"),
+    };
+
+    let single_strip = || {
+        if page.strip_offsets.len() == 1 {
+            Ok(())
+        } else {
+            Err(crate::error::WuffError::from(
+                "tiff: compressed images with more than one strip are not supported",
+            ))
+        }
+    };
+    let (filter, invert): (Filter, bool) = match page.compression {
+        crate::tiff::COMPRESSION_NONE => (Filter::None, invert),
+        crate::tiff::COMPRESSION_LZW => {
+            single_strip()?;
+            (Filter::Lzw { predictor }, invert)
+        }
+        crate::tiff::COMPRESSION_DEFLATE
+        | crate::tiff::COMPRESSION_DEFLATE_OLD => {
+            single_strip()?;
+            (Filter::Flate { predictor }, invert)
+        }
+        crate::tiff::COMPRESSION_CCITT_RLE
+        | crate::tiff::COMPRESSION_CCITT_T4
+        | crate::tiff::COMPRESSION_CCITT_T6 => {
+            single_strip()?;
+            if page.bits_per_sample != 1 || color_space != ColorSpace::Gray {
+                return unsupported("CCITT image that isn't bilevel");
+            }
+            let (k, encoded_byte_align): (i32, bool) = match page.compression {
+                crate::tiff::COMPRESSION_CCITT_RLE => (0, true),
+                crate::tiff::COMPRESSION_CCITT_T4 => {
+                    // Bit 0 says whether 2D coding was used, bit 1 says
+                    // uncompressed mode was used, and bit 2 says fill bits
+                    // were used to byte-align the EOL codes.
+                    if page.t4_options & 0x6 != 0 {
+                        return unsupported("T4 options");
+                    }
+                    (if page.t4_options & 1 != 0 { 1 } else { 0 }, false)
+                }
+                _ => (-1, false),
+            };
+            // The CCITT codes say what's white and what's black on their own.
+            // The photometric interpretation for a CCITT image just says
+            // whether the image should be shown inverted from that.
+            (
+                Filter::CcittFax {
+                    k,
+                    black_is_1: !invert,
+                    encoded_byte_align,
+                },
+                false,
+            )
+        }
+        _ => return unsupported("compression"),
+    };
+    Ok(Image {
+        width: page.width,
+        height: page.height,
+        color_space,
+        bits_per_component: page.bits_per_sample as u8,
+        filter,
+        invert,
+        resolution: page.resolution.unwrap_or(DEFAULT_RESOLUTION),
+    })
+}
+
+pub fn convert_tiff<R, W>(
+    input: &mut R,
+    pdf: &mut Writer<W>,
+) -> Result<(), crate::error::WuffError>
+where
+    R: std::io::Read + std::io::Seek,
+    W: std::io::Write,
+{
+    let pages: Vec<crate::tiff::TiffPage> = crate::tiff::read_pages(input)?;
+    // Check all the pages before writing any of them, so we don't get halfway
+    // through and then discover a page we can't handle.
+    let mut images: Vec<Image> = Vec::with_capacity(pages.len());
+    for page in &pages {
+        images.push(tiff_page_to_image(page)?);
+    }
+    for (page, image) in pages.iter().zip(images.iter()) {
+        let mut strips: crate::tiff::StripReader<R> =
+            crate::tiff::StripReader::new(input, page);
+        pdf.add_image_page(image, &mut strips, page.data_len())?;
+    }
+    Ok(())
+}
+
+// The kinds of file that we know how to turn into PDF pages.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputKind {
+    Jpeg,
+    Tiff,
+    Pdf,
+}
+
+impl InputKind {
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        match content_type {
+            "image/jpeg" => Some(Self::Jpeg),
+            "image/tiff" => Some(Self::Tiff),
+            "application/pdf" => Some(Self::Pdf),
+            _ => None,
+        }
+    }
+}
+
+// Adds the pages of an image file to `pdf`. PDFs can't be streamed through the
+// writer this way, see assemble() for those.
+pub fn convert<R, W>(
+    kind: InputKind,
+    input: &mut R,
+    pdf: &mut Writer<W>,
+) -> Result<(), crate::error::WuffError>
+where
+    R: std::io::Read + std::io::Seek,
+    W: std::io::Write,
+{
+    match kind {
+        InputKind::Jpeg => convert_jpeg(input, pdf),
+        InputKind::Tiff => convert_tiff(input, pdf),
+        InputKind::Pdf => {
+            Err(crate::error::WuffError::from("pdf: input is already a pdf"))
+        }
+    }
+}
+
+// Everything from here down works on whole documents in memory, using lopdf,
+// rather than streaming. That's the price of taking existing PDFs apart, so
+// it's only done for inputs that add up to no more than this many bytes; a
+// parsed document takes up several times the size of the file. Anything
+// bigger is an error, and whoever asked (blank page removal, duplex
+// collation, batching) uploads the files the way they came in instead.
+pub const MAX_IN_MEMORY_SIZE: u64 = 64 * 1024 * 1024;
+
+pub fn input_size<R>(input: &mut R) -> Result<u64, crate::error::WuffError>
+where
+    R: std::io::Seek,
+{
+    let size: u64 = input.seek(std::io::SeekFrom::End(0))?;
+    input.seek(std::io::SeekFrom::Start(0))?;
+    Ok(size)
+}
+
+pub fn check_in_memory_size(size: u64) -> Result<(), crate::error::WuffError> {
+    if size > MAX_IN_MEMORY_SIZE {
+        return Err(crate::error::WuffError::from(format!(
+            "pdf: {} bytes is too much to work on in memory (limit is {})",
+            size, MAX_IN_MEMORY_SIZE
+        )));
+    }
+    Ok(())
+}
+
+pub fn load<R>(
+    kind: InputKind,
+    input: &mut R,
+) -> Result<lopdf::Document, crate::error::WuffError>
+where
+    R: std::io::Read + std::io::Seek,
+{
+    check_in_memory_size(input_size(input)?)?;
+    let doc: lopdf::Document = if kind == InputKind::Pdf {
+        input.seek(std::io::SeekFrom::Start(0))?;
+        lopdf::Document::load_from(input)?
+    } else {
+        let mut pdf: Writer<Vec<u8>> = Writer::new(Vec::new())?;
+        convert(kind, input, &mut pdf)?;
+        lopdf::Document::load_mem(&pdf.finish()?)?
+    };
+    if doc.is_encrypted() {
+        return Err(crate::error::WuffError::from(
+            "pdf: encrypted documents are not supported",
+        ));
+    }
+    Ok(doc)
+}
+
+pub fn num_pages(doc: &lopdf::Document) -> u32 {
+    doc.get_pages().len() as u32
+}
+
+// Pages can inherit these from their ancestors in the page tree. Since we're
+// about to give them a different parent, they need their own copies.
+const INHERITABLE_PAGE_ATTRIBUTES: [&[u8]; 4] =
+    [b"Resources", b"MediaBox", b"CropBox", b"Rotate"];
+
+fn flatten_inherited_attributes(
+    doc: &mut lopdf::Document,
+    page_id: lopdf::ObjectId,
+) -> Result<(), crate::error::WuffError> {
+    let mut inherited: Vec<(&[u8], lopdf::Object)> = Vec::new();
+    let page: &lopdf::Dictionary = doc.get_dictionary(page_id)?;
+    let mut parent: Option<lopdf::ObjectId> = page
+        .get(b"Parent")
+        .and_then(lopdf::Object::as_reference)
+        .ok();
+    let mut depth: usize = 0;
+    while let Some(parent_id) = parent {
+        depth += 1;
+        if depth > 64 {
+            return Err(crate::error::WuffError::from(
+                "pdf: page tree is too deep",
+            ));
+        }
+        let node: &lopdf::Dictionary = doc.get_dictionary(parent_id)?;
+        for key in INHERITABLE_PAGE_ATTRIBUTES {
+            if page.has(key) || inherited.iter().any(|(k, _)| *k == key) {
+                continue;
+            }
+            if let Ok(value) = node.get(key) {
+                inherited.push((key, value.clone()));
+            }
+        }
+        parent = node
+            .get(b"Parent")
+            .and_then(lopdf::Object::as_reference)
+            .ok();
+    }
+    let page: &mut lopdf::Dictionary = doc.get_dictionary_mut(page_id)?;
+    for (key, value) in inherited {
+        page.set(key, value);
+    }
+    Ok(())
+}
+
+// Builds a new document out of pages taken from `inputs`. Each element of
+// `pages` is an index into `inputs`, and a page number (starting from 1)
+// within that document. Pages can be left out, and can come in any order, but
+// can't be used more than once.
+pub fn assemble(
+    inputs: Vec<lopdf::Document>,
+    pages: &[(usize, u32)],
+) -> Result<lopdf::Document, crate::error::WuffError> {
+    if pages.is_empty() {
+        return Err(crate::error::WuffError::from("pdf: no pages"));
+    }
+
+    let mut output: lopdf::Document = lopdf::Document::with_version("1.5");
+    let mut next_id: u32 = 1;
+    let mut input_pages: Vec<
+        std::collections::BTreeMap<u32, lopdf::ObjectId>,
+    > = Vec::with_capacity(inputs.len());
+    for mut input in inputs {
+        // Give each input its own range of object numbers, so they can all
+        // live in the same document without stepping on each other.
+        input.renumber_objects_with(next_id);
+        let page_ids: std::collections::BTreeMap<u32, lopdf::ObjectId> =
+            input.get_pages();
+        for page_id in page_ids.values() {
+            flatten_inherited_attributes(&mut input, *page_id)?;
+        }
+        if let Some((max_id, _)) = input.objects.keys().next_back() {
+            next_id = std::cmp::max(next_id, max_id + 1);
+        }
+        output.objects.append(&mut input.objects);
+        input_pages.push(page_ids);
+    }
+    output.max_id = next_id - 1;
+
+    let pages_id: lopdf::ObjectId = output.new_object_id();
+    let mut kids: Vec<lopdf::Object> = Vec::with_capacity(pages.len());
+    for (input, page_number) in pages {
+        let Some(page_id) = input_pages
+            .get(*input)
+            .and_then(|page_ids| page_ids.get(page_number))
+        else {
+            return Err(crate::error::WuffError::from(format!(
+                "pdf: document {} has no page {}",
+                input, page_number
+            )));
+        };
+        let page_ref: lopdf::Object = lopdf::Object::Reference(*page_id);
+        if kids.contains(&page_ref) {
+            return Err(crate::error::WuffError::from(
+                "pdf: a page can only be used once",
+            ));
+        }
+        output
+            .get_dictionary_mut(*page_id)?
+            .set("Parent", lopdf::Object::Reference(pages_id));
+        kids.push(page_ref);
+    }
+
+    let mut pages_dict: lopdf::Dictionary = lopdf::Dictionary::new();
+    pages_dict.set("Type", lopdf::Object::Name(b"Pages".to_vec()));
+    pages_dict.set("Count", lopdf::Object::Integer(kids.len() as i64));
+    pages_dict.set("Kids", lopdf::Object::Array(kids));
+    output
+        .objects
+        .insert(pages_id, lopdf::Object::Dictionary(pages_dict));
+
+    let mut catalog: lopdf::Dictionary = lopdf::Dictionary::new();
+    catalog.set("Type", lopdf::Object::Name(b"Catalog".to_vec()));
+    catalog.set("Pages", lopdf::Object::Reference(pages_id));
+    let catalog_id: lopdf::ObjectId =
+        output.add_object(lopdf::Object::Dictionary(catalog));
+    output
+        .trailer
+        .set("Root", lopdf::Object::Reference(catalog_id));
+
+    // The old catalogs and page trees, and any pages we left out, are all
+    // unreachable now.
+    output.prune_objects();
+    Ok(output)
+}
+
+// Writes out a single PDF with all the pages of all the inputs, in order. If
+// none of the inputs are PDFs to begin with, this streams; otherwise it goes
+// through assemble().
+pub fn merge<R, W>(
+    inputs: &mut [(InputKind, R)],
+    out: W,
+) -> Result<W, crate::error::WuffError>
+where
+    R: std::io::Read + std::io::Seek,
+    W: std::io::Write,
+{
+    if inputs.iter().all(|(kind, _)| *kind != InputKind::Pdf) {
+        let mut pdf: Writer<W> = Writer::new(out)?;
+        for (kind, input) in inputs.iter_mut() {
+            convert(*kind, input, &mut pdf)?;
+        }
+        return pdf.finish();
+    }
+
+    // They all end up in memory at the same time.
+    let mut total_size: u64 = 0;
+    for (_, input) in inputs.iter_mut() {
+        total_size = total_size.saturating_add(input_size(input)?);
+    }
+    check_in_memory_size(total_size)?;
+
+    let mut docs: Vec<lopdf::Document> = Vec::with_capacity(inputs.len());
+    let mut pages: Vec<(usize, u32)> = Vec::new();
+    for (i, (kind, input)) in inputs.iter_mut().enumerate() {
+        let doc: lopdf::Document = load(*kind, input)?;
+        for page_number in 1..=num_pages(&doc) {
+            pages.push((i, page_number));
+        }
+        docs.push(doc);
+    }
+    let mut doc: lopdf::Document = assemble(docs, &pages)?;
+    let mut out: W = out;
+    doc.save_to(&mut out)?;
+    out.flush()?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Checks that every entry in the xref table points at the object it's
+    // supposed to, and returns the number of objects.
+    fn check_xref(pdf: &[u8]) -> usize {
+        let startxref: usize =
+            pdf.windows(10).rposition(|w| w == b"startxref\n").unwrap();
+        let xref_offset: usize = std::str::from_utf8(&pdf[startxref + 10..])
+            .unwrap()
+            .lines()
+            .next()
+            .unwrap()
+            .parse()
+            .unwrap();
+        let xref: &str = std::str::from_utf8(&pdf[xref_offset..]).unwrap();
+        assert!(xref.starts_with("xref\n0 "));
+        let mut lines = xref.lines().skip(1);
+        let count: usize = lines
+            .next()
+            .unwrap()
+            .split(' ')
+            .nth(1)
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(lines.next().unwrap(), "0000000000 65535 f ");
+        for id in 1..count {
+            let line: &str = lines.next().unwrap();
+            let off: usize = line[..10].parse().unwrap();
+            assert!(
+                pdf[off..].starts_with(format!("{} 0 obj\n", id).as_bytes()),
+                "object {} is not at offset {}",
+                id,
+                off
+            );
+        }
+        count - 1
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack.windows(needle.len()).any(|w| w == needle)
+    }
+
+    #[test]
+    fn one_gray_page() {
+        let mut pdf: Writer<Vec<u8>> = Writer::new(Vec::new()).unwrap();
+        let image: Image = Image {
+            width: 2,
+            height: 1,
+            color_space: ColorSpace::Gray,
+            bits_per_component: 8,
+            filter: Filter::None,
+            invert: false,
+            resolution: (144.0, 144.0),
+        };
+        pdf.add_image_page(&image, &mut &[0u8, 255u8][..], 2)
+            .unwrap();
+        let out: Vec<u8> = pdf.finish().unwrap();
+        assert!(out.starts_with(b"%PDF-1.4\n"));
+        assert!(out.ends_with(b"%%EOF\n"));
+        assert_eq!(check_xref(&out), 5);
+        let text: String = String::from_utf8_lossy(&out).into_owned();
+        assert!(text.contains("/MediaBox [0 0 1.00 0.50]"));
+        assert!(text.contains("/Count 1"));
+    }
+
+    #[test]
+    fn short_image_data() {
+        let mut pdf: Writer<Vec<u8>> = Writer::new(Vec::new()).unwrap();
+        let image: Image = Image {
+            width: 2,
+            height: 1,
+            color_space: ColorSpace::Gray,
+            bits_per_component: 8,
+            filter: Filter::None,
+            invert: false,
+            resolution: (72.0, 72.0),
+        };
+        assert!(pdf.add_image_page(&image, &mut &[0u8][..], 2).is_err());
+    }
+
+    #[test]
+    fn no_pages() {
+        let pdf: Writer<Vec<u8>> = Writer::new(Vec::new()).unwrap();
+        assert!(pdf.finish().is_err());
+    }
+
+    #[test]
+    fn ccitt_dictionary() {
+        let image: Image = Image {
+            width: 1728,
+            height: 2200,
+            color_space: ColorSpace::Gray,
+            bits_per_component: 1,
+            filter: Filter::CcittFax {
+                k: -1,
+                black_is_1: false,
+                encoded_byte_align: false,
+            },
+            invert: false,
+            resolution: (200.0, 200.0),
+        };
+        assert_eq!(
+            image.dictionary(1234),
+            "<< /Type /XObject /Subtype /Image /Width 1728 /Height 2200 /ColorSpace /DeviceGray /BitsPerComponent 1 /Filter /CCITTFaxDecode /DecodeParms << /K -1 /Columns 1728 /Rows 2200 /BlackIs1 false /EncodedByteAlign false >> /Length 1234 >>"
+        );
+    }
+
+    #[test]
+    fn tiff_to_pdf() {
+        let tiff: Vec<u8> = crate::tiff::test::build_gray_tiff(&[
+            (2, 2, vec![vec![1, 2], vec![3, 4]]),
+            (3, 1, vec![vec![5, 6, 7]]),
+        ]);
+        let mut pdf: Writer<Vec<u8>> = Writer::new(Vec::new()).unwrap();
+        convert_tiff(&mut std::io::Cursor::new(tiff), &mut pdf).unwrap();
+        assert_eq!(pdf.num_pages(), 2);
+        let out: Vec<u8> = pdf.finish().unwrap();
+        assert_eq!(check_xref(&out), 8);
+        assert!(contains(&out, b"stream\n\x01\x02\x03\x04\nendstream"));
+        assert!(contains(&out, b"stream\n\x05\x06\x07\nendstream"));
+        let text: String = String::from_utf8_lossy(&out).into_owned();
+        assert!(text.contains("/Count 2"));
+        assert!(text.contains("/MediaBox [0 0 0.48 0.48]"));
+    }
+
+    #[test]
+    fn sixteen_bit_tiff_to_pdf() {
+        // 0x0102 and 0x0304, little-endian.
+        let tiff: Vec<u8> = crate::tiff::test::build_tiff(
+            &[(2, 1, vec![vec![0x02, 0x01, 0x04, 0x03]])],
+            16,
+        );
+        let mut pdf: Writer<Vec<u8>> = Writer::new(Vec::new()).unwrap();
+        convert_tiff(&mut std::io::Cursor::new(tiff.clone()), &mut pdf)
+            .unwrap();
+        let out: Vec<u8> = pdf.finish().unwrap();
+        assert_eq!(check_xref(&out), 5);
+        assert!(contains(&out, b"stream\n\x01\x02\x03\x04\nendstream"));
+        let text: String = String::from_utf8_lossy(&out).into_owned();
+        assert!(text.contains("/BitsPerComponent 16"));
+
+        // Compressed, they can't be swapped, so they're refused.
+        let mut page: crate::tiff::TiffPage =
+            crate::tiff::read_pages(&mut std::io::Cursor::new(tiff))
+                .unwrap()
+                .remove(0);
+        page.compression = crate::tiff::COMPRESSION_DEFLATE;
+        assert!(tiff_page_to_image(&page).is_err());
+        page.big_endian = true;
+        assert!(tiff_page_to_image(&page).is_ok());
+    }
+
+    #[test]
+    fn jpeg_to_pdf() {
+        let mut jpeg: Vec<u8> = vec![0xff, 0xd8];
+        jpeg.extend_from_slice(&[
+            0xff, 0xc0, 0, 11, 8, 0, 1, 0, 1, 1, 1, 0x11, 0,
+        ]);
+        jpeg.extend_from_slice(&[0xff, 0xd9]);
+        let mut pdf: Writer<Vec<u8>> = Writer::new(Vec::new()).unwrap();
+        convert_jpeg(&mut std::io::Cursor::new(jpeg.clone()), &mut pdf)
+            .unwrap();
+        let out: Vec<u8> = pdf.finish().unwrap();
+        assert_eq!(check_xref(&out), 5);
+        assert!(contains(&out, &jpeg));
+        let text: String = String::from_utf8_lossy(&out).into_owned();
+        assert!(text.contains("/Filter /DCTDecode"));
+        assert!(text.contains(&format!("/Length {}", jpeg.len())));
+    }
+
+    fn gray_tiff(num_pages: u8) -> Vec<u8> {
+        let pages: Vec<(u32, u32, Vec<Vec<u8>>)> =
+            (0..num_pages).map(|i| (1, 1, vec![vec![i]])).collect();
+        crate::tiff::test::build_gray_tiff(&pages)
+    }
+
+    fn gray_pdf(num_pages: u8) -> Vec<u8> {
+        let mut pdf: Writer<Vec<u8>> = Writer::new(Vec::new()).unwrap();
+        convert_tiff(
+            &mut std::io::Cursor::new(gray_tiff(num_pages)),
+            &mut pdf,
+        )
+        .unwrap();
+        pdf.finish().unwrap()
+    }
+
+    // Returns the first sample of each page's image, which the tests use to
+    // tell the pages apart.
+    fn page_markers(doc: &lopdf::Document) -> Vec<u8> {
+        doc.get_pages()
+            .values()
+            .map(|page_id| {
+                doc.get_page_images(*page_id).unwrap()[0].content[0]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn merge_images() {
+        let mut inputs: Vec<(InputKind, std::io::Cursor<Vec<u8>>)> = vec![
+            (InputKind::Tiff, std::io::Cursor::new(gray_tiff(2))),
+            (InputKind::Tiff, std::io::Cursor::new(gray_tiff(1))),
+        ];
+        let out: Vec<u8> = merge(&mut inputs, Vec::new()).unwrap();
+        assert_eq!(check_xref(&out), 11);
+        let doc: lopdf::Document = lopdf::Document::load_mem(&out).unwrap();
+        assert_eq!(page_markers(&doc), vec![0, 1, 0]);
+    }
+
+    #[test]
+    fn merge_with_pdf() {
+        let mut inputs: Vec<(InputKind, std::io::Cursor<Vec<u8>>)> = vec![
+            (InputKind::Pdf, std::io::Cursor::new(gray_pdf(2))),
+            (InputKind::Tiff, std::io::Cursor::new(gray_tiff(3))),
+        ];
+        let out: Vec<u8> = merge(&mut inputs, Vec::new()).unwrap();
+        let doc: lopdf::Document = lopdf::Document::load_mem(&out).unwrap();
+        assert_eq!(num_pages(&doc), 5);
+        assert_eq!(page_markers(&doc), vec![0, 1, 0, 1, 2]);
+    }
+
+    #[test]
+    fn assemble_reorders_and_drops() {
+        let load_pdf = |num_pages: u8| {
+            load(
+                InputKind::Pdf,
+                &mut std::io::Cursor::new(gray_pdf(num_pages)),
+            )
+            .unwrap()
+        };
+        let doc: lopdf::Document = assemble(
+            vec![load_pdf(3), load_pdf(2)],
+            &[(1, 2), (0, 3), (0, 1)],
+        )
+        .unwrap();
+        assert_eq!(page_markers(&doc), vec![1, 2, 0]);
+        // The pages we left out shouldn't still be taking up space.
+        let num_images: usize = doc
+            .objects
+            .values()
+            .filter(|obj| {
+                obj.as_stream().is_ok_and(|s| s.dict.has(b"Subtype"))
+            })
+            .count();
+        assert_eq!(num_images, 3);
+    }
+
+    #[test]
+    fn assemble_rejects_bad_pages() {
+        let load_pdf = || {
+            load(InputKind::Pdf, &mut std::io::Cursor::new(gray_pdf(1)))
+                .unwrap()
+        };
+        assert!(assemble(vec![load_pdf()], &[(0, 2)]).is_err());
+        assert!(assemble(vec![load_pdf()], &[(1, 1)]).is_err());
+        assert!(assemble(vec![load_pdf()], &[(0, 1), (0, 1)]).is_err());
+        assert!(assemble(vec![load_pdf()], &[]).is_err());
+    }
+
+    #[test]
+    fn too_big_to_load() {
+        let mut input: std::io::Cursor<Vec<u8>> =
+            std::io::Cursor::new(vec![0; MAX_IN_MEMORY_SIZE as usize + 1]);
+        let err: crate::error::WuffError =
+            load(InputKind::Pdf, &mut input).unwrap_err();
+        assert!(err.to_string().contains("too much to work on in memory"));
+    }
+
+    #[test]
+    fn too_big_to_merge() {
+        // Each of them would be small enough on its own.
+        let half: usize = MAX_IN_MEMORY_SIZE as usize / 2 + 1;
+        let mut inputs: Vec<(InputKind, std::io::Cursor<Vec<u8>>)> = vec![
+            (InputKind::Pdf, std::io::Cursor::new(vec![0; half])),
+            (InputKind::Pdf, std::io::Cursor::new(vec![0; half])),
+        ];
+        let err: crate::error::WuffError =
+            merge(&mut inputs, Vec::new()).unwrap_err();
+        assert!(err.to_string().contains("too much to work on in memory"));
+    }
+}
@@ -0,0 +1,323 @@
+// A schedule is a list of windows during which something should be open,
+// each of which looks like "Mon-Fri 08:00-18:00". The days can be a single
+// day, a range of days ("Fri-Mon" wraps around the weekend), several of
+// those separated by commas, or "daily". The times are a start and an end,
+// and "24:00" is allowed as an end. If the end is before the start, the
+// window runs overnight into the next day.
+//
+// Times are local times in the schedule's time zone, so a window that's
+// "08:00-18:00" stays 08:00-18:00 across daylight saving time changes.
+
+#[derive(Clone, Debug)]
+struct Window {
+    // Indexed by days since Monday.
+    days: [bool; 7],
+    // Minutes since midnight.
+    start: i64,
+    end: i64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Schedule {
+    time_zone: jiff::tz::TimeZone,
+    windows: Vec<Window>,
+}
+
+// How far ahead next_change() is willing to look. A week and a day is enough
+// to get past any window, including one that runs overnight.
+const LOOKAHEAD_DAYS: i64 = 8;
+
+fn parse_day(s: &str) -> Option<usize> {
+    let day: usize = match s.to_ascii_lowercase().as_str() {
+        "mon" | "monday" => 0,
+        "tue" | "tuesday" => 1,
+        "wed" | "wednesday" => 2,
+        "thu" | "thursday" => 3,
+        "fri" | "friday" => 4,
+        "sat" | "saturday" => 5,
+        "sun" | "sunday" => 6,
+        _ => {
+            return None;
+        }
+    };
+    Some(day)
+}
+
+fn parse_days(s: &str) -> Option<[bool; 7]> {
+    if s.eq_ignore_ascii_case("daily") {
+        return Some([true; 7]);
+    }
+    let mut days: [bool; 7] = [false; 7];
+    for part in s.split(',') {
+        if let Some((first, last)) = part.split_once('-') {
+            let first: usize = parse_day(first)?;
+            let last: usize = parse_day(last)?;
+            let mut day: usize = first;
+            loop {
+                days[day] = true;
+                if day == last {
+                    break;
+                }
+                day = (day + 1) % 7;
+            }
+        } else {
+            days[parse_day(part)?] = true;
+        }
+    }
+    Some(days)
+}
+
+fn parse_time(s: &str) -> Option<i64> {
+    let (hours, minutes) = s.split_once(':')?;
+    if hours.is_empty() || hours.len() > 2 || minutes.len() != 2 {
+        return None;
+    }
+    let hours: i64 = hours.parse().ok()?;
+    let minutes: i64 = minutes.parse().ok()?;
+    if minutes >= 60 {
+        return None;
+    }
+    let time: i64 = hours * 60 + minutes;
+    if time > 24 * 60 {
+        return None;
+    }
+    Some(time)
+}
+
+impl Window {
+    fn parse(s: &str) -> Result<Self, crate::error::WuffError> {
+        let err = || {
+            crate::error::WuffError::from(format!(
+                "{:?}: expected something like \"Mon-Fri 08:00-18:00\"",
+                s
+            ))
+        };
+        let Some((days, times)) = s.trim().split_once(char::is_whitespace)
+        else {
+            return Err(err());
+        };
+        let Some(days) = parse_days(days) else {
+            return Err(err());
+        };
+        let Some((start, end)) = times.trim().split_once('-') else {
+            return Err(err());
+        };
+        let (Some(start), Some(end)) = (parse_time(start), parse_time(end))
+        else {
+            return Err(err());
+        };
+        // 24:00 only makes sense as the end of something.
+        if start == 24 * 60 || start == end {
+            return Err(err());
+        }
+        Ok(Self { days, start, end })
+    }
+
+    // When this window would start and end, if it started on the given day.
+    fn on(
+        &self,
+        date: jiff::civil::Date,
+    ) -> Option<(jiff::civil::DateTime, jiff::civil::DateTime)> {
+        let weekday: usize = date.weekday().to_monday_zero_offset() as usize;
+        if !self.days[weekday] {
+            return None;
+        }
+        let midnight: jiff::civil::DateTime =
+            date.to_datetime(jiff::civil::Time::midnight());
+        let end: i64 = if self.end <= self.start {
+            self.end + 24 * 60
+        } else {
+            self.end
+        };
+        Some((
+            midnight
+                .checked_add(jiff::SignedDuration::from_mins(self.start))
+                .ok()?,
+            midnight
+                .checked_add(jiff::SignedDuration::from_mins(end))
+                .ok()?,
+        ))
+    }
+}
+
+impl Schedule {
+    // If there's no time zone, it's the system's.
+    pub fn parse(
+        windows: &[String],
+        time_zone: Option<&str>,
+    ) -> Result<Self, crate::error::WuffError> {
+        let time_zone: jiff::tz::TimeZone = if let Some(time_zone) = time_zone
+        {
+            jiff::tz::TimeZone::get(time_zone).map_err(|e| {
+                crate::error::WuffError::from(format!(
+                    "time zone {:?}: {}",
+                    time_zone, e
+                ))
+            })?
+        } else {
+            jiff::tz::TimeZone::system()
+        };
+        Self::parse_in(windows, time_zone)
+    }
+
+    pub fn parse_in(
+        windows: &[String],
+        time_zone: jiff::tz::TimeZone,
+    ) -> Result<Self, crate::error::WuffError> {
+        let windows: Vec<Window> = windows
+            .iter()
+            .map(|window| Window::parse(window))
+            .collect::<Result<Vec<Window>, crate::error::WuffError>>()?;
+        Ok(Self { time_zone, windows })
+    }
+
+    pub fn is_open(&self, t: jiff::Timestamp) -> bool {
+        let now: jiff::civil::DateTime =
+            t.to_zoned(self.time_zone.clone()).datetime();
+        let today: jiff::civil::Date = now.date();
+        // Yesterday's windows matter too, if they run overnight.
+        let Ok(yesterday) = today.yesterday() else {
+            return false;
+        };
+        self.windows.iter().any(|window| {
+            [yesterday, today].into_iter().any(|date| {
+                if let Some((start, end)) = window.on(date) {
+                    start <= now && now < end
+                } else {
+                    false
+                }
+            })
+        })
+    }
+
+    // The next time after `t` that the schedule goes from open to closed or
+    // the other way around, or None if that isn't going to happen (for
+    // instance, if there are no windows at all).
+    pub fn next_change(&self, t: jiff::Timestamp) -> Option<jiff::Zoned> {
+        let open_now: bool = self.is_open(t);
+        let today: jiff::civil::Date =
+            t.to_zoned(self.time_zone.clone()).date();
+        let mut boundaries: Vec<jiff::Zoned> = Vec::new();
+        for days in -1..=LOOKAHEAD_DAYS {
+            let Ok(date) = today.checked_add(jiff::Span::new().days(days))
+            else {
+                continue;
+            };
+            for window in &self.windows {
+                let Some((start, end)) = window.on(date) else {
+                    continue;
+                };
+                for boundary in [start, end] {
+                    if let Ok(boundary) =
+                        boundary.to_zoned(self.time_zone.clone())
+                    {
+                        if boundary.timestamp() > t {
+                            boundaries.push(boundary);
+                        }
+                    }
+                }
+            }
+        }
+        boundaries.sort();
+        // A boundary isn't necessarily a change, since windows can overlap or
+        // run into each other.
+        boundaries
+            .into_iter()
+            .find(|boundary| self.is_open(boundary.timestamp()) != open_now)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn schedule(windows: &[&str]) -> Schedule {
+        let windows: Vec<String> =
+            windows.iter().map(|s| s.to_string()).collect();
+        Schedule::parse_in(&windows, jiff::tz::TimeZone::UTC).unwrap()
+    }
+
+    fn at(s: &str) -> jiff::Timestamp {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_errors() {
+        for window in [
+            "",
+            "Mon-Fri",
+            "08:00-18:00",
+            "Mon-Fri 08:00",
+            "Mon-Fri 8-18",
+            "Mon-Fry 08:00-18:00",
+            "Mon-Fri 08:00-18:60",
+            "Mon-Fri 08:00-24:01",
+            "Mon-Fri 24:00-08:00",
+            "Mon-Fri 08:00-08:00",
+        ] {
+            let windows: Vec<String> = vec![window.to_string()];
+            assert!(
+                Schedule::parse_in(&windows, jiff::tz::TimeZone::UTC).is_err(),
+                "{:?}",
+                window
+            );
+        }
+    }
+
+    #[test]
+    fn office_hours() {
+        let schedule: Schedule = schedule(&["Mon-Fri 08:00-18:00"]);
+        // 2025-06-02 was a Monday.
+        assert!(!schedule.is_open(at("2025-06-02T07:59:00Z")));
+        assert!(schedule.is_open(at("2025-06-02T08:00:00Z")));
+        assert!(schedule.is_open(at("2025-06-06T17:59:00Z")));
+        assert!(!schedule.is_open(at("2025-06-06T18:00:00Z")));
+        assert!(!schedule.is_open(at("2025-06-07T12:00:00Z")));
+        assert_eq!(
+            schedule
+                .next_change(at("2025-06-02T12:00:00Z"))
+                .unwrap()
+                .timestamp(),
+            at("2025-06-02T18:00:00Z")
+        );
+        assert_eq!(
+            schedule
+                .next_change(at("2025-06-06T20:00:00Z"))
+                .unwrap()
+                .timestamp(),
+            at("2025-06-09T08:00:00Z")
+        );
+    }
+
+    #[test]
+    fn overnight_and_adjacent() {
+        let schedule: Schedule =
+            schedule(&["Fri-Sun 22:00-06:00", "sat 06:00-12:00"]);
+        // 2025-06-07 was a Saturday.
+        assert!(schedule.is_open(at("2025-06-07T01:00:00Z")));
+        assert!(schedule.is_open(at("2025-06-07T07:00:00Z")));
+        assert!(!schedule.is_open(at("2025-06-07T13:00:00Z")));
+        assert!(schedule.is_open(at("2025-06-09T05:59:00Z")));
+        assert!(!schedule.is_open(at("2025-06-09T06:00:00Z")));
+        // Saturday morning runs straight into the second window.
+        assert_eq!(
+            schedule
+                .next_change(at("2025-06-07T01:00:00Z"))
+                .unwrap()
+                .timestamp(),
+            at("2025-06-07T12:00:00Z")
+        );
+    }
+
+    #[test]
+    fn never_changes() {
+        assert!(
+            schedule(&[])
+                .next_change(at("2025-06-02T12:00:00Z"))
+                .is_none()
+        );
+        let always: Schedule = schedule(&["daily 00:00-24:00"]);
+        assert!(always.is_open(at("2025-06-02T00:00:00Z")));
+        assert!(always.next_change(at("2025-06-02T12:00:00Z")).is_none());
+    }
+}
@@ -0,0 +1,156 @@
+// A link that opens a gate without anyone having to log in, because it's
+// signed (HMAC-SHA256, with a secret that only the server knows), so nobody
+// can make one up, or change the one they've got. Everything about the link
+// is in its query string, so there's nothing to look up, except how many
+// times it's been used so far, if that's limited.
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct SignedLink {
+    pub gate: String,
+    // Random, so that each link can be told apart from the others, for
+    // counting how many times it's been used.
+    pub id: String,
+    // Seconds since the epoch.
+    pub expires: Option<u64>,
+    pub uses: Option<u32>,
+    pub name_hint: Option<String>,
+}
+
+// What goes in the query string: the link, and its signature. This can't
+// just flatten a SignedLink into it, since serde_urlencoded can't cope with
+// numbers in flattened structs.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Query {
+    gate: String,
+    id: String,
+    expires: Option<u64>,
+    uses: Option<u32>,
+    name_hint: Option<String>,
+    sig: String,
+}
+
+type HmacSha256 = hmac::Hmac<sha2::Sha256>;
+
+impl SignedLink {
+    // The same link always comes out the same, and any two different links
+    // come out different, which wouldn't necessarily be true if the fields
+    // were just stuck together.
+    fn mac(&self, key: &[u8]) -> HmacSha256 {
+        let mut mac: HmacSha256 =
+            <HmacSha256 as hmac::Mac>::new_from_slice(key).expect("hmac");
+        let message: String = serde_json::to_string(&(
+            &self.gate,
+            &self.id,
+            self.expires,
+            self.uses,
+            &self.name_hint,
+        ))
+        .expect("serde_json");
+        hmac::Mac::update(&mut mac, message.as_bytes());
+        mac
+    }
+
+    pub fn to_query(&self, key: &[u8]) -> String {
+        let sig: String = base64::Engine::encode(
+            &base64::prelude::BASE64_URL_SAFE_NO_PAD,
+            hmac::Mac::finalize(self.mac(key)).into_bytes(),
+        );
+        serde_urlencoded::to_string(Query {
+            gate: self.gate.clone(),
+            id: self.id.clone(),
+            expires: self.expires,
+            uses: self.uses,
+            name_hint: self.name_hint.clone(),
+            sig,
+        })
+        .expect("serde_urlencoded")
+    }
+
+    pub fn from_query(
+        query: &str,
+        key: &[u8],
+    ) -> Result<Self, crate::error::WuffError> {
+        let invalid =
+            || crate::error::WuffError::from("This link isn't valid");
+        let Ok(Query {
+            gate,
+            id,
+            expires,
+            uses,
+            name_hint,
+            sig,
+        }) = serde_urlencoded::from_str::<Query>(query)
+        else {
+            return Err(invalid());
+        };
+        let link: SignedLink = SignedLink {
+            gate,
+            id,
+            expires,
+            uses,
+            name_hint,
+        };
+        let Ok(sig) = base64::Engine::decode(
+            &base64::prelude::BASE64_URL_SAFE_NO_PAD,
+            sig,
+        ) else {
+            return Err(invalid());
+        };
+        if hmac::Mac::verify_slice(link.mac(key), &sig).is_err() {
+            return Err(invalid());
+        }
+        Ok(link)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        if let Some(expires) = self.expires {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+                >= expires
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn link() -> SignedLink {
+        SignedLink {
+            gate: String::from("front desk"),
+            id: String::from("abc"),
+            expires: Some(4102444800),
+            uses: None,
+            name_hint: Some(String::from("lease & stuff")),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let query: String = link().to_query(b"secret");
+        assert_eq!(SignedLink::from_query(&query, b"secret").unwrap(), link());
+        assert!(SignedLink::from_query(&query, b"other secret").is_err());
+        assert!(!link().is_expired());
+    }
+
+    #[test]
+    fn tampered() {
+        let query: String = link().to_query(b"secret");
+        let tampered: String = query.replace("front+desk", "back+door");
+        assert_ne!(query, tampered);
+        assert!(SignedLink::from_query(&tampered, b"secret").is_err());
+        let mut no_hint: SignedLink = link();
+        no_hint.name_hint = None;
+        let sig: &str = query.rsplit_once("sig=").unwrap().1;
+        let forged: String = format!(
+            "{}&sig={}",
+            serde_urlencoded::to_string(&no_hint).unwrap(),
+            sig
+        );
+        assert!(SignedLink::from_query(&forged, b"secret").is_err());
+    }
+}
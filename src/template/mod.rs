@@ -0,0 +1,152 @@
+// A template for things like blob names. Anything in braces is a variable,
+// like "{date}", and gets replaced by its value. "{-date}" is the same, except
+// that if the value isn't empty, a "-" goes in front of it, which is handy for
+// things that might not be there. "{{" and "}}" are literal braces.
+//
+// Which variables there are is up to whoever's using the template, and
+// anything else is an error when the template is parsed, rather than when
+// it's rendered.
+
+#[derive(Clone, Debug, PartialEq)]
+enum Piece {
+    Literal(String),
+    Variable { name: String, dash: bool },
+}
+
+#[derive(Clone, Debug)]
+pub struct Template {
+    pieces: Vec<Piece>,
+}
+
+impl Template {
+    pub fn parse(
+        s: &str,
+        variables: &[&str],
+    ) -> Result<Self, crate::error::WuffError> {
+        let mut pieces: Vec<Piece> = Vec::new();
+        let mut literal: String = String::new();
+        let mut chars: std::iter::Peekable<std::str::Chars> =
+            s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    let _ = chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    let _ = chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name: String = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => {
+                                break;
+                            }
+                            Some(c) => {
+                                name.push(c);
+                            }
+                            None => {
+                                return Err(crate::error::WuffError::from(
+                                    format!("{:?}: unterminated \"{{\"", s),
+                                ));
+                            }
+                        }
+                    }
+                    let (name, dash): (&str, bool) =
+                        if let Some(name) = name.strip_prefix('-') {
+                            (name, true)
+                        } else {
+                            (&name, false)
+                        };
+                    if !variables.contains(&name) {
+                        return Err(crate::error::WuffError::from(format!(
+                            "{:?}: unknown variable {:?}, expected one of: {}",
+                            s,
+                            name,
+                            variables.join(", ")
+                        )));
+                    }
+                    if !literal.is_empty() {
+                        pieces.push(Piece::Literal(std::mem::take(
+                            &mut literal,
+                        )));
+                    }
+                    pieces.push(Piece::Variable {
+                        name: name.to_string(),
+                        dash,
+                    });
+                }
+                '}' => {
+                    return Err(crate::error::WuffError::from(format!(
+                        "{:?}: unmatched \"}}\"",
+                        s
+                    )));
+                }
+                c => {
+                    literal.push(c);
+                }
+            }
+        }
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(literal));
+        }
+        Ok(Self { pieces })
+    }
+
+    // Variables that aren't in `values` are treated as being empty.
+    pub fn render(
+        &self,
+        values: &std::collections::HashMap<&str, String>,
+    ) -> String {
+        let mut rendered: String = String::new();
+        for piece in &self.pieces {
+            match piece {
+                Piece::Literal(literal) => {
+                    rendered.push_str(literal);
+                }
+                Piece::Variable { name, dash } => {
+                    let value: &str =
+                        values.get(name.as_str()).map_or("", String::as_str);
+                    if *dash && !value.is_empty() {
+                        rendered.push('-');
+                    }
+                    rendered.push_str(value);
+                }
+            }
+        }
+        rendered
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const VARIABLES: &[&str] = &["date", "name_hint"];
+
+    #[test]
+    fn render() {
+        let template: Template =
+            Template::parse("{{x}}/{date}/scan{-name_hint}", VARIABLES)
+                .unwrap();
+        let mut values: std::collections::HashMap<&str, String> =
+            std::collections::HashMap::new();
+        values.insert("date", String::from("2025-06-02"));
+        assert_eq!(template.render(&values), "{x}/2025-06-02/scan");
+        values.insert("name_hint", String::from("lease"));
+        assert_eq!(template.render(&values), "{x}/2025-06-02/scan-lease");
+    }
+
+    #[test]
+    fn parse_errors() {
+        for template in ["{date", "date}", "{time}", "{-}", "{}"] {
+            assert!(
+                Template::parse(template, VARIABLES).is_err(),
+                "{}",
+                template
+            );
+        }
+    }
+}
@@ -0,0 +1,658 @@
+// A TIFF reader that only goes as far as the directory structure. It tells you
+// where each page's image data lives in the file and how it's encoded, but it
+// doesn't decode anything itself. Only classic (32-bit offset) TIFF is
+// supported, not BigTIFF.
+
+pub const COMPRESSION_NONE: u16 = 1;
+pub const COMPRESSION_CCITT_RLE: u16 = 2;
+pub const COMPRESSION_CCITT_T4: u16 = 3;
+pub const COMPRESSION_CCITT_T6: u16 = 4;
+pub const COMPRESSION_LZW: u16 = 5;
+pub const COMPRESSION_DEFLATE: u16 = 8;
+pub const COMPRESSION_PACKBITS: u16 = 32773;
+pub const COMPRESSION_DEFLATE_OLD: u16 = 32946;
+
+pub const PHOTOMETRIC_WHITE_IS_ZERO: u16 = 0;
+pub const PHOTOMETRIC_BLACK_IS_ZERO: u16 = 1;
+pub const PHOTOMETRIC_RGB: u16 = 2;
+pub const PHOTOMETRIC_SEPARATED: u16 = 5;
+
+const TAG_IMAGE_WIDTH: u16 = 256;
+const TAG_IMAGE_LENGTH: u16 = 257;
+const TAG_BITS_PER_SAMPLE: u16 = 258;
+const TAG_COMPRESSION: u16 = 259;
+const TAG_PHOTOMETRIC: u16 = 262;
+const TAG_FILL_ORDER: u16 = 266;
+const TAG_STRIP_OFFSETS: u16 = 273;
+const TAG_SAMPLES_PER_PIXEL: u16 = 277;
+const TAG_ROWS_PER_STRIP: u16 = 278;
+const TAG_STRIP_BYTE_COUNTS: u16 = 279;
+const TAG_X_RESOLUTION: u16 = 282;
+const TAG_Y_RESOLUTION: u16 = 283;
+const TAG_PLANAR_CONFIGURATION: u16 = 284;
+const TAG_T4_OPTIONS: u16 = 292;
+const TAG_RESOLUTION_UNIT: u16 = 296;
+const TAG_PREDICTOR: u16 = 317;
+const TAG_TILE_WIDTH: u16 = 322;
+const TAG_EXTRA_SAMPLES: u16 = 338;
+
+const TYPE_BYTE: u16 = 1;
+const TYPE_SHORT: u16 = 3;
+const TYPE_LONG: u16 = 4;
+const TYPE_RATIONAL: u16 = 5;
+
+// Sanity limits, so that a malicious or broken file can't make us allocate
+// unbounded amounts of memory or loop forever.
+const MAX_PAGES: usize = 10000;
+const MAX_ENTRIES_PER_DIRECTORY: u16 = 1000;
+const MAX_VALUES_PER_ENTRY: u32 = 1 << 20;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TiffPage {
+    pub width: u32,
+    pub height: u32,
+    pub bits_per_sample: u16,
+    pub samples_per_pixel: u16,
+    pub extra_samples: usize,
+    pub compression: u16,
+    pub photometric: u16,
+    pub fill_order: u16,
+    pub planar_configuration: u16,
+    pub predictor: u16,
+    pub t4_options: u32,
+    pub rows_per_strip: u32,
+    pub strip_offsets: Vec<u64>,
+    pub strip_byte_counts: Vec<u64>,
+    // Dots per inch, horizontally and vertically, if the file says.
+    pub resolution: Option<(f64, f64)>,
+    pub tiled: bool,
+    // Samples that are more than a byte are stored in the file's byte
+    // order, which is only the one that PDF wants if this is set.
+    pub big_endian: bool,
+}
+
+impl TiffPage {
+    pub fn data_len(&self) -> u64 {
+        self.strip_byte_counts.iter().sum()
+    }
+}
+
+#[derive(Clone, Copy)]
+enum ByteOrder {
+    LittleEndian,
+    BigEndian,
+}
+
+impl ByteOrder {
+    fn u16(&self, b: [u8; 2]) -> u16 {
+        match self {
+            Self::LittleEndian => u16::from_le_bytes(b),
+            Self::BigEndian => u16::from_be_bytes(b),
+        }
+    }
+
+    fn u32(&self, b: [u8; 4]) -> u32 {
+        match self {
+            Self::LittleEndian => u32::from_le_bytes(b),
+            Self::BigEndian => u32::from_be_bytes(b),
+        }
+    }
+}
+
+enum Value {
+    Integers(Vec<u64>),
+    Rationals(Vec<f64>),
+    Other,
+}
+
+impl Value {
+    fn first_integer(&self) -> Option<u64> {
+        match self {
+            Self::Integers(v) => v.first().copied(),
+            _ => None,
+        }
+    }
+
+    fn first_rational(&self) -> Option<f64> {
+        match self {
+            Self::Rationals(v) => v.first().copied(),
+            Self::Integers(v) => v.first().map(|i| *i as f64),
+            Self::Other => None,
+        }
+    }
+}
+
+struct Reader<'a, R: std::io::Read + std::io::Seek> {
+    r: &'a mut R,
+    byte_order: ByteOrder,
+}
+
+impl<'a, R: std::io::Read + std::io::Seek> Reader<'a, R> {
+    fn read_u16(&mut self) -> Result<u16, crate::error::WuffError> {
+        let mut buf: [u8; 2] = [0u8; 2];
+        self.r.read_exact(&mut buf)?;
+        Ok(self.byte_order.u16(buf))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, crate::error::WuffError> {
+        let mut buf: [u8; 4] = [0u8; 4];
+        self.r.read_exact(&mut buf)?;
+        Ok(self.byte_order.u32(buf))
+    }
+
+    fn seek(&mut self, off: u64) -> Result<(), crate::error::WuffError> {
+        self.r.seek(std::io::SeekFrom::Start(off))?;
+        Ok(())
+    }
+
+    fn read_value(
+        &mut self,
+        value_type: u16,
+        count: u32,
+        value_or_offset: [u8; 4],
+    ) -> Result<Value, crate::error::WuffError> {
+        let type_size: u32 = match value_type {
+            TYPE_BYTE => 1,
+            TYPE_SHORT => 2,
+            TYPE_LONG => 4,
+            TYPE_RATIONAL => 8,
+            _ => return Ok(Value::Other),
+        };
+        if count > MAX_VALUES_PER_ENTRY {
+            return Err(crate::error::WuffError::from(
+                "tiff: too many values in directory entry",
+            ));
+        }
+        let total_size: u32 = type_size * count;
+        let data: Vec<u8> = if total_size <= 4 {
+            value_or_offset[..total_size as usize].to_vec()
+        } else {
+            self.seek(self.byte_order.u32(value_or_offset) as u64)?;
+            let mut data: Vec<u8> = vec![0u8; total_size as usize];
+            self.r.read_exact(&mut data)?;
+            data
+        };
+        Ok(match value_type {
+            TYPE_BYTE => {
+                Value::Integers(data.iter().map(|b| *b as u64).collect())
+            }
+            TYPE_SHORT => Value::Integers(
+                data.chunks_exact(2)
+                    .map(|b| self.byte_order.u16([b[0], b[1]]) as u64)
+                    .collect(),
+            ),
+            TYPE_LONG => Value::Integers(
+                data.chunks_exact(4)
+                    .map(|b| {
+                        self.byte_order.u32([b[0], b[1], b[2], b[3]]) as u64
+                    })
+                    .collect(),
+            ),
+            _ => Value::Rationals(
+                data.chunks_exact(8)
+                    .map(|b| {
+                        let num: u32 =
+                            self.byte_order.u32([b[0], b[1], b[2], b[3]]);
+                        let den: u32 =
+                            self.byte_order.u32([b[4], b[5], b[6], b[7]]);
+                        if den == 0 {
+                            0.0
+                        } else {
+                            num as f64 / den as f64
+                        }
+                    })
+                    .collect(),
+            ),
+        })
+    }
+
+    // Returns the page, and the offset of the next directory (or 0 if this
+    // was the last one)
+    fn read_directory(
+        &mut self,
+        off: u64,
+    ) -> Result<(TiffPage, u64), crate::error::WuffError> {
+        self.seek(off)?;
+        let num_entries: u16 = self.read_u16()?;
+        if num_entries > MAX_ENTRIES_PER_DIRECTORY {
+            return Err(crate::error::WuffError::from(
+                "tiff: too many directory entries",
+            ));
+        }
+        let mut raw_entries: Vec<(u16, u16, u32, [u8; 4])> =
+            Vec::with_capacity(num_entries as usize);
+        for _ in 0..num_entries {
+            let tag: u16 = self.read_u16()?;
+            let value_type: u16 = self.read_u16()?;
+            let count: u32 = self.read_u32()?;
+            let mut value_or_offset: [u8; 4] = [0u8; 4];
+            self.r.read_exact(&mut value_or_offset)?;
+            raw_entries.push((tag, value_type, count, value_or_offset));
+        }
+        let next_directory: u64 = self.read_u32()? as u64;
+
+        let mut entries: std::collections::HashMap<u16, Value> =
+            std::collections::HashMap::new();
+        for (tag, value_type, count, value_or_offset) in raw_entries {
+            let value: Value =
+                self.read_value(value_type, count, value_or_offset)?;
+            entries.insert(tag, value);
+        }
+
+        let integer = |tag: u16,
+                       default: Option<u64>|
+         -> Result<u64, crate::error::WuffError> {
+            entries
+                .get(&tag)
+                .and_then(Value::first_integer)
+                .or(default)
+                .ok_or_else(|| {
+                    crate::error::WuffError::from(format!(
+                        "tiff: missing required tag {}",
+                        tag
+                    ))
+                })
+        };
+        let integers = |tag: u16| -> Vec<u64> {
+            match entries.get(&tag) {
+                Some(Value::Integers(v)) => v.clone(),
+                _ => Vec::new(),
+            }
+        };
+
+        let width: u32 = integer(TAG_IMAGE_WIDTH, None)? as u32;
+        let height: u32 = integer(TAG_IMAGE_LENGTH, None)? as u32;
+        let resolution: Option<(f64, f64)> = match (
+            entries
+                .get(&TAG_X_RESOLUTION)
+                .and_then(Value::first_rational),
+            entries
+                .get(&TAG_Y_RESOLUTION)
+                .and_then(Value::first_rational),
+            integer(TAG_RESOLUTION_UNIT, Some(2))?,
+        ) {
+            (Some(x), Some(y), 2) if x > 0.0 && y > 0.0 => Some((x, y)),
+            (Some(x), Some(y), 3) if x > 0.0 && y > 0.0 => {
+                Some((x * 2.54, y * 2.54))
+            }
+            _ => None,
+        };
+        let strip_offsets: Vec<u64> = integers(TAG_STRIP_OFFSETS);
+        let strip_byte_counts: Vec<u64> = integers(TAG_STRIP_BYTE_COUNTS);
+        if strip_offsets.len() != strip_byte_counts.len() {
+            return Err(crate::error::WuffError::from(
+                "tiff: strip offsets and byte counts don't match up",
+            ));
+        }
+        let page: TiffPage = TiffPage {
+            width,
+            height,
+            bits_per_sample: integer(TAG_BITS_PER_SAMPLE, Some(1))? as u16,
+            samples_per_pixel: integer(TAG_SAMPLES_PER_PIXEL, Some(1))? as u16,
+            extra_samples: integers(TAG_EXTRA_SAMPLES).len(),
+            compression: integer(TAG_COMPRESSION, Some(1))? as u16,
+            photometric: integer(TAG_PHOTOMETRIC, None)? as u16,
+            fill_order: integer(TAG_FILL_ORDER, Some(1))? as u16,
+            planar_configuration: integer(TAG_PLANAR_CONFIGURATION, Some(1))?
+                as u16,
+            predictor: integer(TAG_PREDICTOR, Some(1))? as u16,
+            t4_options: integer(TAG_T4_OPTIONS, Some(0))? as u32,
+            rows_per_strip: integer(TAG_ROWS_PER_STRIP, Some(u32::MAX as u64))?
+                as u32,
+            strip_offsets,
+            strip_byte_counts,
+            resolution,
+            tiled: entries.contains_key(&TAG_TILE_WIDTH),
+            big_endian: matches!(self.byte_order, ByteOrder::BigEndian),
+        };
+        Ok((page, next_directory))
+    }
+}
+
+pub fn read_pages<R: std::io::Read + std::io::Seek>(
+    r: &mut R,
+) -> Result<Vec<TiffPage>, crate::error::WuffError> {
+    r.seek(std::io::SeekFrom::Start(0))?;
+    let mut magic: [u8; 4] = [0u8; 4];
+    r.read_exact(&mut magic)?;
+    let byte_order: ByteOrder = match magic {
+        [b'I', b'I', 42, 0] => ByteOrder::LittleEndian,
+        [b'M', b'M', 0, 42] => ByteOrder::BigEndian,
+        [b'I', b'I', 43, 0] | [b'M', b'M', 0, 43] => {
+            return Err(crate::error::WuffError::from(
+                "tiff: BigTIFF is not supported",
+            ));
+        }
+        _ => {
+            return Err(crate::error::WuffError::from(
+                "tiff: bad magic number",
+            ));
+        }
+    };
+    let mut reader: Reader<R> = Reader { r, byte_order };
+    let mut next_directory: u64 = reader.read_u32()? as u64;
+    let mut visited: std::collections::HashSet<u64> =
+        std::collections::HashSet::new();
+    let mut pages: Vec<TiffPage> = Vec::new();
+    while next_directory != 0 {
+        if !visited.insert(next_directory) || pages.len() >= MAX_PAGES {
+            return Err(crate::error::WuffError::from(
+                "tiff: directory chain doesn't end",
+            ));
+        }
+        let page: TiffPage;
+        (page, next_directory) = reader.read_directory(next_directory)?;
+        pages.push(page);
+    }
+    if pages.is_empty() {
+        return Err(crate::error::WuffError::from("tiff: no pages"));
+    }
+    Ok(pages)
+}
+
+// Reads all of a page's strips back-to-back, as if they were one contiguous
+// piece of data. If the page is uncompressed and has 16-bit samples in
+// little-endian order, they come out big-endian, which is what PDF wants.
+// (Compressed ones can't be fixed up without decompressing them.)
+pub struct StripReader<'a, R: std::io::Read + std::io::Seek> {
+    r: &'a mut R,
+    strips: Vec<(u64, u64)>,
+    next_strip: usize,
+    remaining_in_strip: u64,
+    swap_bytes: bool,
+    // The second half of a sample that's been swapped, but that didn't fit
+    // in the caller's buffer.
+    carry: Option<u8>,
+}
+
+impl<'a, R: std::io::Read + std::io::Seek> StripReader<'a, R> {
+    pub fn new(r: &'a mut R, page: &TiffPage) -> Self {
+        Self {
+            r,
+            strips: page
+                .strip_offsets
+                .iter()
+                .copied()
+                .zip(page.strip_byte_counts.iter().copied())
+                .collect(),
+            next_strip: 0,
+            remaining_in_strip: 0,
+            swap_bytes: page.bits_per_sample == 16
+                && !page.big_endian
+                && page.compression == COMPRESSION_NONE,
+            carry: None,
+        }
+    }
+
+    fn read_raw(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.remaining_in_strip == 0 {
+            let Some((off, len)) = self.strips.get(self.next_strip) else {
+                return Ok(0);
+            };
+            self.r.seek(std::io::SeekFrom::Start(*off))?;
+            self.remaining_in_strip = *len;
+            self.next_strip += 1;
+        }
+        let max_len: usize =
+            std::cmp::min(buf.len() as u64, self.remaining_in_strip) as usize;
+        let n: usize = self.r.read(&mut buf[..max_len])?;
+        if n == 0 {
+            return Err(std::io::Error::from(
+                std::io::ErrorKind::UnexpectedEof,
+            ));
+        }
+        self.remaining_in_strip -= n as u64;
+        Ok(n)
+    }
+
+    // Reads one whole sample, or nothing at all if there's nothing left.
+    fn read_pair(&mut self) -> std::io::Result<Option<[u8; 2]>> {
+        let mut pair: [u8; 2] = [0u8; 2];
+        if self.read_raw(&mut pair[..1])? == 0 {
+            return Ok(None);
+        }
+        if self.read_raw(&mut pair[1..])? == 0 {
+            return Err(std::io::Error::from(
+                std::io::ErrorKind::UnexpectedEof,
+            ));
+        }
+        Ok(Some(pair))
+    }
+}
+
+impl<'a, R: std::io::Read + std::io::Seek> std::io::Read
+    for StripReader<'a, R>
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if !self.swap_bytes || buf.is_empty() {
+            return self.read_raw(buf);
+        }
+        if let Some(b) = self.carry.take() {
+            buf[0] = b;
+            return Ok(1);
+        }
+        // Whole samples at a time, so that each one can be swapped.
+        let len: usize = buf.len() & !1;
+        if len == 0 {
+            let Some(pair) = self.read_pair()? else {
+                return Ok(0);
+            };
+            buf[0] = pair[1];
+            self.carry = Some(pair[0]);
+            return Ok(1);
+        }
+        let mut n: usize = self.read_raw(&mut buf[..len])?;
+        if n % 2 == 1 {
+            if self.read_raw(&mut buf[n..n + 1])? == 0 {
+                return Err(std::io::Error::from(
+                    std::io::ErrorKind::UnexpectedEof,
+                ));
+            }
+            n += 1;
+        }
+        for sample in buf[..n].chunks_exact_mut(2) {
+            sample.swap(0, 1);
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+
+    // Builds a little-endian TIFF with one directory per page. Each page is
+    // an uncompressed 8-bit grayscale image whose data is split into the
+    // given strips.
+    pub(crate) fn build_gray_tiff(
+        pages: &[(u32, u32, Vec<Vec<u8>>)],
+    ) -> Vec<u8> {
+        build_tiff(pages, 8)
+    }
+
+    // The same, with however many bits per sample. The strips are taken
+    // as-is, so multi-byte samples in them need to be little-endian.
+    pub(crate) fn build_tiff(
+        pages: &[(u32, u32, Vec<Vec<u8>>)],
+        bits_per_sample: u32,
+    ) -> Vec<u8> {
+        let mut out: Vec<u8> = b"II\x2a\x00\0\0\0\0".to_vec();
+        let mut prev_next_pointer: usize = 4;
+        for (width, height, strips) in pages {
+            // Strip data first
+            let mut offsets: Vec<u32> = Vec::new();
+            for strip in strips {
+                offsets.push(out.len() as u32);
+                out.extend_from_slice(strip);
+            }
+            // Out-of-line arrays
+            let offsets_at: u32 = out.len() as u32;
+            for off in &offsets {
+                out.extend_from_slice(&off.to_le_bytes());
+            }
+            let counts_at: u32 = out.len() as u32;
+            for strip in strips {
+                out.extend_from_slice(&(strip.len() as u32).to_le_bytes());
+            }
+            let xres_at: u32 = out.len() as u32;
+            out.extend_from_slice(&300u32.to_le_bytes());
+            out.extend_from_slice(&1u32.to_le_bytes());
+            if !out.len().is_multiple_of(2) {
+                out.push(0);
+            }
+
+            let directory_at: u32 = out.len() as u32;
+            out[prev_next_pointer..prev_next_pointer + 4]
+                .copy_from_slice(&directory_at.to_le_bytes());
+            let entries: Vec<(u16, u16, u32, u32)> = vec![
+                (TAG_IMAGE_WIDTH, TYPE_LONG, 1, *width),
+                (TAG_IMAGE_LENGTH, TYPE_LONG, 1, *height),
+                (TAG_BITS_PER_SAMPLE, TYPE_SHORT, 1, bits_per_sample),
+                (TAG_COMPRESSION, TYPE_SHORT, 1, 1),
+                (TAG_PHOTOMETRIC, TYPE_SHORT, 1, 1),
+                (
+                    TAG_STRIP_OFFSETS,
+                    TYPE_LONG,
+                    offsets.len() as u32,
+                    if offsets.len() == 1 {
+                        offsets[0]
+                    } else {
+                        offsets_at
+                    },
+                ),
+                (
+                    TAG_STRIP_BYTE_COUNTS,
+                    TYPE_LONG,
+                    strips.len() as u32,
+                    if strips.len() == 1 {
+                        strips[0].len() as u32
+                    } else {
+                        counts_at
+                    },
+                ),
+                (TAG_X_RESOLUTION, TYPE_RATIONAL, 1, xres_at),
+                (TAG_Y_RESOLUTION, TYPE_RATIONAL, 1, xres_at),
+            ];
+            out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+            for (tag, value_type, count, value) in entries {
+                out.extend_from_slice(&tag.to_le_bytes());
+                out.extend_from_slice(&value_type.to_le_bytes());
+                out.extend_from_slice(&count.to_le_bytes());
+                if value_type == TYPE_SHORT {
+                    out.extend_from_slice(&(value as u16).to_le_bytes());
+                    out.extend_from_slice(&[0, 0]);
+                } else {
+                    out.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            prev_next_pointer = out.len();
+            out.extend_from_slice(&[0, 0, 0, 0]);
+        }
+        out
+    }
+
+    #[test]
+    fn two_pages() {
+        let data: Vec<u8> = build_gray_tiff(&[
+            (2, 2, vec![vec![1, 2], vec![3, 4]]),
+            (3, 1, vec![vec![5, 6, 7]]),
+        ]);
+        let mut cursor = std::io::Cursor::new(data);
+        let pages: Vec<TiffPage> = read_pages(&mut cursor).unwrap();
+        assert_eq!(pages.len(), 2);
+
+        assert_eq!(pages[0].width, 2);
+        assert_eq!(pages[0].height, 2);
+        assert_eq!(pages[0].bits_per_sample, 8);
+        assert_eq!(pages[0].samples_per_pixel, 1);
+        assert_eq!(pages[0].compression, COMPRESSION_NONE);
+        assert_eq!(pages[0].photometric, PHOTOMETRIC_BLACK_IS_ZERO);
+        assert_eq!(pages[0].resolution, Some((300.0, 300.0)));
+        assert_eq!(pages[0].strip_byte_counts, vec![2, 2]);
+        assert_eq!(pages[0].data_len(), 4);
+
+        assert_eq!(pages[1].width, 3);
+        assert_eq!(pages[1].strip_byte_counts, vec![3]);
+
+        let mut data: Vec<u8> = Vec::new();
+        std::io::Read::read_to_end(
+            &mut StripReader::new(&mut cursor, &pages[0]),
+            &mut data,
+        )
+        .unwrap();
+        assert_eq!(data, vec![1, 2, 3, 4]);
+
+        let mut data: Vec<u8> = Vec::new();
+        std::io::Read::read_to_end(
+            &mut StripReader::new(&mut cursor, &pages[1]),
+            &mut data,
+        )
+        .unwrap();
+        assert_eq!(data, vec![5, 6, 7]);
+    }
+
+    #[test]
+    fn sixteen_bit_little_endian() {
+        // Two 16-bit samples, 0x0102 and 0x0304, with the first one split
+        // across strips.
+        let data: Vec<u8> = build_tiff(
+            &[(2, 1, vec![vec![0x02], vec![0x01, 0x04, 0x03]])],
+            16,
+        );
+        let mut cursor = std::io::Cursor::new(data);
+        let pages: Vec<TiffPage> = read_pages(&mut cursor).unwrap();
+        assert!(!pages[0].big_endian);
+        assert_eq!(pages[0].bits_per_sample, 16);
+
+        let mut data: Vec<u8> = Vec::new();
+        std::io::Read::read_to_end(
+            &mut StripReader::new(&mut cursor, &pages[0]),
+            &mut data,
+        )
+        .unwrap();
+        assert_eq!(data, vec![0x01, 0x02, 0x03, 0x04]);
+
+        // A byte at a time, too.
+        let mut strips: StripReader<_> =
+            StripReader::new(&mut cursor, &pages[0]);
+        let mut data: Vec<u8> = Vec::new();
+        let mut buf: [u8; 1] = [0u8; 1];
+        while std::io::Read::read(&mut strips, &mut buf).unwrap() == 1 {
+            data.push(buf[0]);
+        }
+        assert_eq!(data, vec![0x01, 0x02, 0x03, 0x04]);
+    }
+
+    #[test]
+    fn sixteen_bit_odd_length() {
+        let data: Vec<u8> =
+            build_tiff(&[(2, 1, vec![vec![0x02, 0x01, 0x04]])], 16);
+        let mut cursor = std::io::Cursor::new(data);
+        let pages: Vec<TiffPage> = read_pages(&mut cursor).unwrap();
+        let mut data: Vec<u8> = Vec::new();
+        assert!(
+            std::io::Read::read_to_end(
+                &mut StripReader::new(&mut cursor, &pages[0]),
+                &mut data,
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn directory_loop() {
+        let mut data: Vec<u8> = build_gray_tiff(&[(1, 1, vec![vec![0]])]);
+        // Make the last "next directory" pointer point back at the first
+        // directory.
+        let len: usize = data.len();
+        let first: [u8; 4] = data[4..8].try_into().unwrap();
+        data[len - 4..].copy_from_slice(&first);
+        assert!(read_pages(&mut std::io::Cursor::new(data)).is_err());
+    }
+
+    #[test]
+    fn not_a_tiff() {
+        let data: Vec<u8> = b"GIF89a\0\0".to_vec();
+        assert!(read_pages(&mut std::io::Cursor::new(data)).is_err());
+    }
+}
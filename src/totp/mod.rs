@@ -0,0 +1,153 @@
+// Time-based one-time passwords (RFC 6238), the kind that authenticator apps
+// make up: six digits, a new one every thirty seconds, from HMAC-SHA1 of how
+// many thirty-second steps there have been since the epoch. Those are the
+// defaults that every app supports, so there's no choosing anything else.
+//
+// Secrets are written in base32, which is what otpauth:// URIs and people
+// typing them in by hand both expect.
+
+const STEP_SECS: u64 = 30;
+const DIGITS: u32 = 6;
+// How many steps either side of now are still accepted, for clocks that
+// aren't quite right, and people who aren't quite quick.
+const SKEW_STEPS: u64 = 1;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+pub fn base32_encode(data: &[u8]) -> String {
+    let mut encoded: String = String::new();
+    let mut buffer: u32 = 0;
+    let mut bits: u32 = 0;
+    for b in data {
+        buffer = (buffer << 8) | (*b as u32);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(
+                BASE32_ALPHABET[((buffer >> bits) & 31) as usize] as char,
+            );
+        }
+    }
+    if bits > 0 {
+        encoded.push(
+            BASE32_ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char,
+        );
+    }
+    encoded
+}
+
+// Case, spaces and padding don't matter, since people copy these around by
+// hand.
+pub fn base32_decode(s: &str) -> Option<Vec<u8>> {
+    let mut decoded: Vec<u8> = Vec::new();
+    let mut buffer: u32 = 0;
+    let mut bits: u32 = 0;
+    for c in s.bytes() {
+        if c == b' ' || c == b'=' {
+            continue;
+        }
+        let c: u8 = c.to_ascii_uppercase();
+        let value: usize = BASE32_ALPHABET.iter().position(|a| *a == c)?;
+        buffer = (buffer << 5) | (value as u32);
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push(((buffer >> bits) & 0xff) as u8);
+        }
+    }
+    Some(decoded)
+}
+
+pub fn generate_secret() -> String {
+    let mut buf: [u8; 20] = [0u8; 20];
+    rustls::crypto::ring::default_provider()
+        .secure_random
+        .fill(&mut buf)
+        .expect("secure_random");
+    base32_encode(&buf)
+}
+
+// For an authenticator app, usually by way of a QR code.
+pub fn otpauth_uri(issuer: &str, account: &str, secret: &str) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}",
+        crate::util::percent_encode(issuer),
+        crate::util::percent_encode(account),
+        secret,
+        crate::util::percent_encode(issuer)
+    )
+}
+
+pub fn code_at_step(secret: &[u8], step: u64) -> u32 {
+    let mut mac: hmac::Hmac<sha1::Sha1> =
+        <hmac::Hmac<sha1::Sha1> as hmac::Mac>::new_from_slice(secret)
+            .expect("hmac");
+    hmac::Mac::update(&mut mac, &step.to_be_bytes());
+    let digest = hmac::Mac::finalize(mac).into_bytes();
+    // "Dynamic truncation", from RFC 4226.
+    let offset: usize = (digest[digest.len() - 1] & 0x0f) as usize;
+    let value: u32 = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+    value % 10u32.pow(DIGITS)
+}
+
+// The step that the code matched, if it did, so that the caller can refuse
+// to accept the same one twice.
+pub fn verify(
+    secret: &str,
+    code: &str,
+    now: std::time::SystemTime,
+) -> Option<u64> {
+    let secret: Vec<u8> = base32_decode(secret)?;
+    let code: &str = code.trim();
+    if code.len() != DIGITS as usize
+        || !code.bytes().all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+    let step: u64 =
+        now.duration_since(std::time::UNIX_EPOCH).ok()?.as_secs() / STEP_SECS;
+    (step.saturating_sub(SKEW_STEPS)..=step + SKEW_STEPS)
+        .find(|step| code_at_step(&secret, *step) == code)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn base32_round_trip() {
+        // From RFC 4648.
+        assert_eq!(base32_encode(b"foobar"), "MZXW6YTBOI");
+        assert_eq!(base32_decode("mzxw 6ytb oi======").unwrap(), b"foobar");
+        assert!(base32_decode("MZXW1").is_none());
+        let secret: String = generate_secret();
+        assert_eq!(base32_decode(&secret).unwrap().len(), 20);
+    }
+
+    #[test]
+    fn rfc_6238_test_vectors() {
+        // The SHA-1 ones, cut down to six digits.
+        let secret: &[u8] = b"12345678901234567890";
+        assert_eq!(code_at_step(secret, 59 / 30), 287082);
+        assert_eq!(code_at_step(secret, 1111111109 / 30), 81804);
+        assert_eq!(code_at_step(secret, 1234567890 / 30), 5924);
+        let secret: String = base32_encode(secret);
+        let at = |secs: u64| {
+            std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs)
+        };
+        assert_eq!(
+            verify(&secret, "081804", at(1111111109)),
+            Some(1111111109 / 30)
+        );
+        // One step either side is fine, and two isn't.
+        assert!(verify(&secret, "081804", at(1111111109 + 30)).is_some());
+        assert!(verify(&secret, "081804", at(1111111109 + 60)).is_none());
+        assert!(verify(&secret, "81804", at(1111111109)).is_none());
+    }
+}
@@ -108,10 +108,73 @@ pub fn system_time_to_utc_rfc3339(t: std::time::SystemTime) -> String {
     as_str
 }
 
+// For putting something in a URL path. Anything other than letters, digits
+// and "-._~" gets percent-encoded.
+pub fn percent_encode(s: &str) -> String {
+    let mut encoded: String = String::new();
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+// The other way around. None if there's a bad "%" in it, or if it isn't
+// UTF-8 once it's been decoded.
+pub fn percent_decode(s: &str) -> Option<String> {
+    let mut decoded: Vec<u8> = Vec::new();
+    let mut bytes: std::slice::Iter<u8> = s.as_bytes().iter();
+    while let Some(b) = bytes.next() {
+        if *b == b'%' {
+            let (Some(hi), Some(lo)) = (bytes.next(), bytes.next()) else {
+                return None;
+            };
+            let hi: u32 = (*hi as char).to_digit(16)?;
+            let lo: u32 = (*lo as char).to_digit(16)?;
+            decoded.push((hi * 16 + lo) as u8);
+        } else {
+            decoded.push(*b);
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+// Something unguessable, for things like session cookies, that's safe to
+// put in a URL or a header without any further encoding.
+pub fn random_token() -> String {
+    let mut buf: [u8; 32] = [0u8; 32];
+    rustls::crypto::ring::default_provider()
+        .secure_random
+        .fill(&mut buf)
+        .expect("secure_random");
+    base64::Engine::encode(&base64::prelude::BASE64_URL_SAFE_NO_PAD, buf)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn random_tokens() {
+        let a: String = random_token();
+        assert_eq!(a.len(), 43);
+        assert_ne!(a, random_token());
+    }
+
+    #[test]
+    fn percent_encoding() {
+        assert_eq!(percent_encode("front-desk_2"), "front-desk_2");
+        assert_eq!(percent_encode("a b/c\u{e9}"), "a%20b%2Fc%C3%A9");
+        assert_eq!(percent_decode("a%20b%2Fc%C3%A9").unwrap(), "a b/c\u{e9}");
+        assert_eq!(percent_decode("%2f").unwrap(), "/");
+        assert!(percent_decode("%2").is_none());
+        assert!(percent_decode("%zz").is_none());
+        assert!(percent_decode("%ff").is_none());
+    }
+
     #[test]
     fn stringify_systemtime() {
         let t = std::time::SystemTime::UNIX_EPOCH